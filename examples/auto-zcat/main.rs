@@ -0,0 +1,131 @@
+use egzreader::EgzReader;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::io::{stdout, BufReader, BufWriter, Read, Write};
+use std::process;
+
+mod digest;
+
+use digest::{to_hex, Algorithm, Digest};
+
+const ALGORITHMS: [Algorithm; 2] = [Algorithm::Sha256, Algorithm::Md5];
+
+// Writer passing bytes through while hashing them.
+struct Hashing<'a, W> {
+    inner: W,
+    digests: &'a mut [Digest],
+}
+impl<W: Write> Write for Hashing<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        for d in self.digests.iter_mut() {
+            d.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Expected digests of `path` from its sidecar files, e.g. "data.gz.sha256".
+// A sidecar holds the hex digest, optionally followed by a filename as sha256sum writes.
+fn sidecars(path: &str) -> io::Result<Vec<(Algorithm, String)>> {
+    let mut expected = Vec::new();
+    for &algorithm in ALGORITHMS.iter() {
+        let sidecar = format!("{}.{}", path, algorithm.extension());
+        match fs::read_to_string(&sidecar) {
+            Ok(s) => {
+                let hex = s.split_whitespace().next().unwrap_or("");
+                expected.push((algorithm, hex.to_ascii_lowercase()));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", sidecar, e))),
+        }
+    }
+    if expected.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no .sha256 or .md5 sidecar file",
+        ));
+    }
+    Ok(expected)
+}
+
+// Writes the decoded content of `path` to `w`, verifying it if `verify` is given.
+// With `raw`, the file itself is verified instead of the decoded content.
+fn cat<W: Write>(w: &mut W, path: &str, verify: bool, raw: bool) -> io::Result<()> {
+    let expected = if verify { sidecars(path)? } else { Vec::new() };
+    let mut digests: Vec<Digest> = expected.iter().map(|(a, _)| Digest::new(*a)).collect();
+
+    let mut r = BufReader::new(EgzReader::new(File::open(path)?));
+    if raw {
+        io::copy(&mut r, w)?;
+        let mut f = File::open(path)?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match f.read(&mut buf)? {
+                0 => break,
+                n => digests.iter_mut().for_each(|d| d.update(&buf[..n])),
+            }
+        }
+    } else {
+        let mut w = Hashing {
+            inner: &mut *w,
+            digests: &mut digests,
+        };
+        io::copy(&mut r, &mut w)?;
+    }
+
+    for ((algorithm, expected), digest) in expected.iter().zip(digests) {
+        let actual = to_hex(&digest.finish());
+        if &actual != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} mismatch of the {} content: expected {}, got {}",
+                    algorithm.extension(),
+                    if raw { "raw" } else { "decoded" },
+                    expected,
+                    actual
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let verify = args.iter().any(|a| a == "--verify-checksums");
+    let raw = args.iter().any(|a| a == "--checksum-raw");
+    let files: Vec<&String> = args[1..]
+        .iter()
+        .filter(|a| *a != "--verify-checksums" && *a != "--checksum-raw")
+        .collect();
+
+    if files.is_empty() {
+        println!("USAGE:");
+        println!("$ auto-zcat [--verify-checksums [--checksum-raw]] FILE [FILE]...");
+        println!("Either gzip or non-gzip files can be accepted.");
+        println!("With --verify-checksums, the decoded content of each FILE is verified");
+        println!("against FILE.sha256 and/or FILE.md5, failing on a mismatch.");
+        println!("With --checksum-raw, FILE itself is verified instead.");
+    }
+
+    let w = stdout();
+    let mut w = BufWriter::new(w.lock());
+
+    let mut failed = false;
+    for path in files {
+        let result = cat(&mut w, path, verify, raw).and_then(|_| w.flush());
+        if let Err(e) = result {
+            eprintln!("auto-zcat: {}: {}", path, e);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,237 @@
+use egzreader::EgzReader;
+use std::env;
+use std::fs::File;
+use std::io::{self, stdout, BufReader, BufWriter, Read, Write};
+use std::process;
+
+// Number of unchanged lines shown around changes.
+const CONTEXT: usize = 3;
+// Maximum size of the table for the diff, in cells.
+const MAX_CELLS: usize = 16 * 1024 * 1024;
+
+fn open(path: &str) -> io::Result<BufReader<EgzReader<File>>> {
+    Ok(BufReader::new(EgzReader::new(File::open(path)?)))
+}
+
+fn fail(msg: String) -> ! {
+    eprintln!("egzdiff: {}", msg);
+    process::exit(2);
+}
+
+// Returns the offset of the first different byte, or None if identical.
+fn compare<A: Read, B: Read>(mut a: A, mut b: B) -> io::Result<Option<u64>> {
+    let mut buf_a = vec![0; 64 * 1024];
+    let mut buf_b = vec![0; 64 * 1024];
+    let mut offset = 0;
+    loop {
+        let n = read_full(&mut a, &mut buf_a)?;
+        let m = read_full(&mut b, &mut buf_b)?;
+        if let Some(i) = buf_a[..n].iter().zip(&buf_b[..m]).position(|(x, y)| x != y) {
+            return Ok(Some(offset + i as u64));
+        }
+        if n != m {
+            return Ok(Some(offset + n.min(m) as u64));
+        }
+        if n == 0 {
+            return Ok(None);
+        }
+        offset += n as u64;
+    }
+}
+
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..])? {
+            0 => break,
+            m => n += m,
+        }
+    }
+    Ok(n)
+}
+
+// Lines including their newlines, none for empty data.
+fn lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&c| c == b'\n').collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Same,
+    Delete,
+    Insert,
+}
+
+// Edit script from `a` to `b` by the longest common subsequence.
+fn edits(a: &[&[u8]], b: &[&[u8]]) -> Option<Vec<Op>> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+    let (n, m) = (a_mid.len(), b_mid.len());
+    if MAX_CELLS < (n + 1).saturating_mul(m + 1) {
+        return None;
+    }
+
+    // lcs[i * (m + 1) + j] is the LCS length of a_mid[i..] and b_mid[j..].
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * (m + 1) + j] = if a_mid[i] == b_mid[j] {
+                lcs[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+            };
+        }
+    }
+
+    let mut ops = vec![Op::Same; prefix];
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && a_mid[i] == b_mid[j] {
+            ops.push(Op::Same);
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1]) {
+            ops.push(Op::Delete);
+            i += 1;
+        } else {
+            ops.push(Op::Insert);
+            j += 1;
+        }
+    }
+    ops.extend(vec![Op::Same; suffix]);
+    Some(ops)
+}
+
+fn write_line<W: Write>(w: &mut W, mark: char, line: &[u8]) -> io::Result<()> {
+    write!(w, "{}", mark)?;
+    w.write_all(line)?;
+    if !line.ends_with(b"\n") {
+        writeln!(w)?;
+        writeln!(w, "\\ No newline at end of file")?;
+    }
+    Ok(())
+}
+
+fn unified_diff<W: Write>(w: &mut W, names: (&str, &str), a: &[u8], b: &[u8]) -> io::Result<()> {
+    let (a, b) = (lines(a), lines(b));
+    let ops = match edits(&a, &b) {
+        Some(ops) => ops,
+        None => fail("inputs are too large for --diff".to_string()),
+    };
+    writeln!(w, "--- {}", names.0)?;
+    writeln!(w, "+++ {}", names.1)?;
+
+    // Positions in `ops`, `a` and `b` for each op.
+    let mut pos = Vec::with_capacity(ops.len());
+    let (mut i, mut j) = (0, 0);
+    for &op in ops.iter() {
+        pos.push((i, j));
+        match op {
+            Op::Same => {
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+
+    let mut k = 0;
+    while k < ops.len() {
+        if ops[k] == Op::Same {
+            k += 1;
+            continue;
+        }
+
+        // Extend the hunk while changes are within 2 * CONTEXT lines.
+        let start = k.saturating_sub(CONTEXT);
+        let mut end = k;
+        let mut same = 0;
+        while end < ops.len() && same <= 2 * CONTEXT {
+            same = if ops[end] == Op::Same { same + 1 } else { 0 };
+            end += 1;
+        }
+        let end = end - same.saturating_sub(CONTEXT);
+
+        let (a_start, b_start) = pos[start];
+        let a_len = ops[start..end]
+            .iter()
+            .filter(|&&op| op != Op::Insert)
+            .count();
+        let b_len = ops[start..end]
+            .iter()
+            .filter(|&&op| op != Op::Delete)
+            .count();
+        writeln!(
+            w,
+            "@@ -{},{} +{},{} @@",
+            a_start + (a_len != 0) as usize,
+            a_len,
+            b_start + (b_len != 0) as usize,
+            b_len
+        )?;
+        for (&op, &(i, j)) in ops[start..end].iter().zip(&pos[start..end]) {
+            match op {
+                Op::Same => write_line(w, ' ', a[i])?,
+                Op::Delete => write_line(w, '-', a[i])?,
+                Op::Insert => write_line(w, '+', b[j])?,
+            }
+        }
+        k = end;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let diff = args.iter().any(|a| a == "--diff");
+    let files: Vec<&String> = args[1..].iter().filter(|a| *a != "--diff").collect();
+
+    if files.len() != 2 {
+        println!("USAGE:");
+        println!("$ egzdiff [--diff] FILE1 FILE2");
+        println!("Compares the decoded contents of gzip or non-gzip files.");
+        println!("With --diff, a unified diff of the contents is printed.");
+        println!("Exits with 0 if identical, 1 if different, 2 on errors.");
+        process::exit(2);
+    }
+
+    let open = |path: &str| open(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+    let (a, b) = (open(files[0]), open(files[1]));
+
+    if diff {
+        let read = |mut r: BufReader<EgzReader<File>>, path: &str| {
+            let mut data = Vec::new();
+            r.read_to_end(&mut data)
+                .unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+            data
+        };
+        let (a, b) = (read(a, files[0]), read(b, files[1]));
+        if a == b {
+            return;
+        }
+
+        let w = stdout();
+        let mut w = BufWriter::new(w.lock());
+        unified_diff(&mut w, (files[0], files[1]), &a, &b)
+            .and_then(|_| w.flush())
+            .unwrap_or_else(|e| fail(e.to_string()));
+        process::exit(1);
+    }
+
+    match compare(a, b) {
+        Ok(None) => {}
+        Ok(Some(offset)) => {
+            println!("{} {} differ: byte {}", files[0], files[1], offset + 1);
+            process::exit(1);
+        }
+        Err(e) => fail(e.to_string()),
+    }
+}
@@ -0,0 +1,68 @@
+use egzreader::{EgzReader, EgzReaderBuilder, Format};
+use std::env;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+
+// Prints the format of `r` and the gzip header if any.
+fn identify<R: Read, W: Write>(w: &mut W, name: &str, mut r: EgzReader<R>) -> io::Result<()> {
+    match r.detect()? {
+        Format::Raw => write!(w, "{}: Raw (not compressed)", name)?,
+        format => write!(w, "{}: {:?}", name, format)?,
+    }
+
+    if let Some(header) = r.gz_header() {
+        if let Some(filename) = header.filename() {
+            write!(
+                w,
+                ", original name \"{}\"",
+                String::from_utf8_lossy(filename)
+            )?;
+        }
+        if header.mtime() != 0 {
+            write!(w, ", modified {}", header.mtime())?;
+        }
+        write!(w, ", OS {}", header.os())?;
+        if let Some(comment) = header.comment() {
+            write!(w, ", comment \"{}\"", String::from_utf8_lossy(comment))?;
+        }
+    }
+    writeln!(w)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("USAGE:");
+        println!("$ egzid [--all] [FILE]...");
+        println!("Prints the format detected by EgzReader and the gzip header of each FILE,");
+        println!("or of stdin without FILE.");
+        println!("With --all, base64 gzip, zlib and raw deflate are also detected.");
+        process::exit(2);
+    }
+    let all = args.iter().any(|a| a == "--all");
+    let args: Vec<&String> = args.iter().filter(|a| *a != "--all").collect();
+    let builder = EgzReaderBuilder::new().base64(all).zlib(all).deflate(all);
+
+    let w = io::stdout();
+    let mut w = BufWriter::new(w.lock());
+    let mut failed = false;
+    let result = if args.is_empty() {
+        identify(&mut w, "-", builder.build(io::stdin().lock()))
+    } else {
+        for path in args.iter() {
+            let result = builder.open(path).and_then(|r| identify(&mut w, path, r));
+            if let Err(e) = result {
+                eprintln!("egzid: {}: {}", path, e);
+                failed = true;
+            }
+        }
+        Ok(())
+    };
+    if let Err(e) = result.and_then(|_| w.flush()) {
+        eprintln!("egzid: {}", e);
+        failed = true;
+    }
+    if failed {
+        process::exit(1);
+    }
+}
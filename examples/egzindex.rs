@@ -0,0 +1,106 @@
+use egzreader::{IndexedReader, MemberIndex};
+use std::env;
+use std::fs::File;
+use std::io::{self, stdout, BufWriter, Read, Seek, SeekFrom, Write};
+use std::process;
+
+fn fail(msg: String) -> ! {
+    eprintln!("egzindex: {}", msg);
+    process::exit(1);
+}
+
+fn usage() -> ! {
+    println!("USAGE:");
+    println!("$ egzindex build FILE.gz");
+    println!("$ egzindex show FILE.gz");
+    println!("$ egzindex extract FILE.gz OFFSET LENGTH");
+    println!("build writes the index of the gzip members of FILE.gz to FILE.gz.gzi.");
+    println!("The .gzi file keeps the members only, and checkpoints are recorded in the last one.");
+    println!("show prints the compressed and decoded offsets of the members and the checkpoints.");
+    println!("extract writes LENGTH decoded bytes from OFFSET to stdout.");
+    println!("show and extract build the index if FILE.gz.gzi does not exist.");
+    process::exit(2);
+}
+
+fn gzi_path(path: &str) -> String {
+    format!("{}.gzi", path)
+}
+
+// Reads the index of `f` from the .gzi file, or builds it.
+fn load(path: &str, f: &mut File) -> io::Result<MemberIndex> {
+    match File::open(gzi_path(path)) {
+        Ok(gzi) => MemberIndex::read_gzi(gzi, f),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => MemberIndex::build(f),
+        Err(e) => Err(e),
+    }
+}
+
+fn build(path: &str, mut f: File) -> io::Result<()> {
+    let index = MemberIndex::build(&mut f)?;
+    index.write_gzi(File::create(gzi_path(path))?)
+}
+
+fn show(path: &str, mut f: File) -> io::Result<()> {
+    let index = load(path, &mut f)?;
+    let w = stdout();
+    let mut w = BufWriter::new(w.lock());
+    writeln!(w, "{:>20} {:>20}", "compressed", "decoded")?;
+    for p in index.points() {
+        writeln!(
+            w,
+            "{:>20} {:>20}",
+            p.compressed_offset(),
+            p.decoded_offset()
+        )?;
+    }
+    if !index.checkpoints().is_empty() {
+        writeln!(w, "checkpoints inside the members:")?;
+        for p in index.checkpoints() {
+            writeln!(
+                w,
+                "{:>20} {:>20}",
+                p.compressed_offset(),
+                p.decoded_offset()
+            )?;
+        }
+    }
+    writeln!(
+        w,
+        "{} members, {} checkpoints, {} bytes decoded",
+        index.points().len(),
+        index.checkpoints().len(),
+        index.decoded_size()
+    )?;
+    w.flush()
+}
+
+fn extract(path: &str, mut f: File, offset: u64, len: u64) -> io::Result<()> {
+    let index = load(path, &mut f)?;
+    let mut r = IndexedReader::new(index, f);
+    r.seek(SeekFrom::Start(offset))?;
+    let w = stdout();
+    let mut w = w.lock();
+    io::copy(&mut r.take(len), &mut w)?;
+    w.flush()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        usage();
+    }
+    let path = &args[2];
+    let f = File::open(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+    let number = |s: &str| {
+        s.parse()
+            .unwrap_or_else(|_| fail(format!("invalid number: {}", s)))
+    };
+
+    let result = match (args[1].as_str(), args.len()) {
+        ("build", 3) => build(path, f),
+        ("show", 3) => show(path, f),
+        ("extract", 5) => extract(path, f, number(&args[3]), number(&args[4])),
+        _ => usage(),
+    };
+    result.unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+}
@@ -0,0 +1,34 @@
+use egzreader::concat_members;
+use std::env;
+use std::fs::File;
+use std::io::{stdout, BufWriter};
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() <= 1 {
+        println!("USAGE:");
+        println!("$ gz-concat FILE [FILE]... > OUTPUT");
+        println!("Every FILE is verified before they are concatenated without recompression.");
+        return;
+    }
+
+    let files: Vec<File> = args[1..]
+        .iter()
+        .map(|a| {
+            File::open(a).unwrap_or_else(|e| {
+                eprintln!("{}: {}", a, e);
+                process::exit(1);
+            })
+        })
+        .collect();
+
+    let w = stdout();
+    let w = BufWriter::new(w.lock());
+
+    if let Err(e) = concat_members(files, w) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
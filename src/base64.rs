@@ -0,0 +1,128 @@
+use std::io::{Read, Result};
+
+// Value of a base64 character, accepting both standard and URL-safe alphabets.
+fn value(c: u8) -> Option<u32> {
+    let v = match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'+' | b'-' => 62,
+        b'/' | b'_' => 63,
+        _ => return None,
+    };
+    Some(v as u32)
+}
+
+// gzip stream encoded by base64 begins with "H4sI" (0x1f 0x8b 0x08).
+pub(crate) fn is_base64_gzip(preread: &[u8]) -> bool {
+    11 <= preread.len()
+        && preread.starts_with(b"H4sI")
+        && preread[..11].iter().all(|&c| value(c).is_some())
+}
+
+// Streaming base64 decoder.
+//
+// ASCII whitespaces are skipped, and the data ends at padding or any other non-base64 byte
+// (e.g. the closing quote of a JSON string).
+#[derive(Debug)]
+pub(crate) struct Base64Decoder<R: Read> {
+    pub(crate) inner: R,
+
+    // Decoded bytes not yet returned.
+    out: Vec<u8>,
+    pos: usize,
+
+    acc: u32,
+    bits: u32,
+    end: bool,
+}
+
+impl<R: Read> Base64Decoder<R> {
+    pub(crate) fn new(inner: R) -> Base64Decoder<R> {
+        Base64Decoder {
+            inner,
+            out: Vec::new(),
+            pos: 0,
+            acc: 0,
+            bits: 0,
+            end: false,
+        }
+    }
+
+    fn decode(&mut self, input: &[u8]) {
+        for &c in input {
+            let v = match value(c) {
+                Some(v) => v,
+                None if c.is_ascii_whitespace() => continue,
+                None => {
+                    self.end = true;
+                    return;
+                }
+            };
+
+            self.acc = (self.acc << 6) | v;
+            self.bits += 6;
+            if 8 <= self.bits {
+                self.bits -= 8;
+                self.out.push((self.acc >> self.bits) as u8);
+                self.acc &= (1 << self.bits) - 1;
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for Base64Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut input = [0; 4 * 1024];
+        while self.pos == self.out.len() {
+            if self.end || buf.is_empty() {
+                return Ok(0);
+            }
+            self.out.clear();
+            self.pos = 0;
+
+            let n = self.inner.read(&mut input)?;
+            if n == 0 {
+                self.end = true;
+            }
+            self.decode(&input[..n]);
+        }
+
+        let n = (&self.out[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::{is_base64_gzip, Base64Decoder};
+
+    fn decode(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        Base64Decoder::new(data).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(decode(b""), b"");
+        assert_eq!(decode(b"SGVsbG8h"), b"Hello!");
+        assert_eq!(decode(b"SGVsbG8="), b"Hello");
+        assert_eq!(decode(b"SGVsbA=="), b"Hell");
+        assert_eq!(decode(b"SGVsbA"), b"Hell");
+        assert_eq!(decode(b"SGVs\r\nbG8h"), b"Hello!");
+        assert_eq!(decode(b"SGVsbG8h\", \"next\""), b"Hello!");
+        assert_eq!(decode(b"-_-_"), [0xfb, 0xff, 0xbf]);
+        assert_eq!(decode(b"+/+/"), [0xfb, 0xff, 0xbf]);
+    }
+    #[test]
+    fn base64_gzip() {
+        assert!(is_base64_gzip(b"H4sIAOtHdGA"));
+        assert!(!is_base64_gzip(b"H4sIAOtHd"));
+        assert!(!is_base64_gzip(b"H4sIAO\"HdGA"));
+        assert!(!is_base64_gzip(b"SGVsbG8hSGV"));
+    }
+}
@@ -0,0 +1,602 @@
+use flate2::read::MultiGzDecoder;
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+use std::collections::VecDeque;
+use std::io::{Chain, Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::mem;
+use std::thread;
+
+use crate::error::DecodeError;
+use crate::header::{parse_gz_header, OwnedGzHeader};
+
+// Subfield ID of the block size.
+const BC: [u8; 2] = *b"BC";
+const FEXTRA: u8 = 1 << 2;
+// Length of the gzip header up to XLEN.
+const XLEN_END: usize = 12;
+// Maximum decoded size of a block defined by the specification.
+const MAX_BLOCK_LEN: usize = 64 * 1024;
+// Number of blocks read ahead for each thread decoding in parallel.
+const BLOCKS_PER_THREAD: usize = 4;
+
+// Returns the number of bytes to read for BGZF detection,
+// or None if the gzip header has no extra field.
+pub(crate) fn probe_len(preread: &[u8]) -> Option<usize> {
+    if preread.get(3).map_or(true, |flg| flg & FEXTRA == 0) {
+        None
+    } else if preread.len() < XLEN_END {
+        Some(XLEN_END)
+    } else {
+        Some(XLEN_END + u16::from_le_bytes([preread[10], preread[11]]) as usize)
+    }
+}
+
+// Returns true if `preread` begins with a gzip header having the BC subfield.
+pub(crate) fn is_bgzf(preread: &[u8]) -> bool {
+    parse_gz_header(preread)
+        .ok()
+        .and_then(|h| h.subfield(BC))
+        .map_or(false, |bc| bc.len() == 2)
+}
+
+fn invalid(msg: &str) -> Error {
+    DecodeError::corrupt(msg)
+}
+
+// A block read into `input`, or the end of the blocks.
+enum Raw {
+    // The length of the gzip header of the block
+    Block(usize),
+    // Switched to the gzip members.
+    Members,
+    End,
+}
+
+#[derive(Debug)]
+enum Input<R: Read> {
+    Blocks(R),
+    // Gzip members following the blocks, beginning with the header read as a block.
+    Members(Box<MultiGzDecoder<Chain<Cursor<Vec<u8>>, R>>>),
+    Moving,
+}
+
+/// A reader of BGZF (blocked gzip used by BAM, tabix and bgzip) supporting virtual offsets.
+///
+/// BGZF is a multi-member gzip stream whose members, called blocks, record their sizes.
+/// A position in the decoded stream is given by a virtual offset:
+/// the offset of the block in the compressed stream shifted left by 16 bits,
+/// ORed with the offset in the decoded block.
+/// Offsets in the compressed stream count from the position where `reader` is given.
+///
+/// The stream must end with the empty EOF block, or reading returns an error of
+/// `UnexpectedEof` reporting a possibly truncated stream.
+/// [EgzReader](crate::EgzReader) skips this check unless
+/// [require_bgzf_eof](crate::EgzReaderBuilder::require_bgzf_eof) is set.
+///
+/// A gzip member without the BC subfield, e.g. of a file appended by `cat`, ends the
+/// blocks. It and the following members are decoded as a multi-member gzip stream,
+/// where virtual offsets are not available.
+///
+/// Blocks are decoded on the calling thread by default.
+/// [threads](BgzfReader::threads) decodes blocks read ahead in parallel,
+/// delivering them in order, as htslib does.
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::prelude::*;
+/// use egzreader::BgzfReader;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut r = BgzfReader::new(File::open("calls.vcf.gz")?);
+/// let mut line = String::new();
+/// r.read_to_string(&mut line)?;
+///
+/// // Virtual offset from an index, e.g. tabix
+/// r.seek_virtual(0x1234_0056)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BgzfReader<R: Read> {
+    reader: Input<R>,
+
+    // Offsets of the current block and the next block in the compressed stream.
+    block_offset: u64,
+    next_offset: u64,
+
+    // Bytes of the block being read, kept over errors, e.g. WouldBlock.
+    input: Vec<u8>,
+    block: Vec<u8>,
+    pos: usize,
+
+    header: Option<OwnedGzHeader>,
+    // Whether the last block read was empty, i.e. the EOF block.
+    eof_block: bool,
+    require_eof_block: bool,
+
+    // Blocks decoded in parallel with their offsets, and the error following them.
+    threads: usize,
+    decoded: VecDeque<(u64, Vec<u8>)>,
+    pending: Option<Error>,
+}
+
+impl<R: Read> BgzfReader<R> {
+    /// Creates a reader of the BGZF stream from the current position of `reader`.
+    pub fn new(reader: R) -> BgzfReader<R> {
+        BgzfReader {
+            reader: Input::Blocks(reader),
+            block_offset: 0,
+            next_offset: 0,
+            input: Vec::new(),
+            block: Vec::new(),
+            pos: 0,
+            header: None,
+            eof_block: false,
+            require_eof_block: true,
+            threads: 1,
+            decoded: VecDeque::new(),
+            pending: None,
+        }
+    }
+
+    /// Decodes blocks on `threads` threads. 1, i.e. the calling thread, by default.
+    ///
+    /// Up to 4 blocks for each thread, i.e. 256 KiB of decoded data,
+    /// are read ahead and decoded at once. 0 uses the available parallelism.
+    pub fn threads(mut self, threads: usize) -> BgzfReader<R> {
+        self.threads = match threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        self
+    }
+
+    // Treats a stream without the EOF block as complete.
+    pub(crate) fn lenient(mut self, lenient: bool) -> BgzfReader<R> {
+        self.require_eof_block = !lenient;
+        self
+    }
+
+    /// Returns the virtual offset of the next byte to read.
+    pub fn virtual_offset(&self) -> u64 {
+        self.block_offset << 16 | self.pos as u64
+    }
+
+    /// Returns the header of the first block read, once a block has been read.
+    ///
+    /// The blocks written by `bgzip` have the same header except the block size.
+    pub fn header(&self) -> Option<&OwnedGzHeader> {
+        self.header.as_ref()
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        match &self.reader {
+            Input::Blocks(r) => r,
+            Input::Members(d) => d.get_ref().get_ref().1,
+            Input::Moving => unreachable!(),
+        }
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        match &mut self.reader {
+            Input::Blocks(r) => r,
+            Input::Members(d) => d.get_mut().get_mut().1,
+            Input::Moving => unreachable!(),
+        }
+    }
+
+    /// Unwraps this `BgzfReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        match self.reader {
+            Input::Blocks(r) => r,
+            Input::Members(d) => d.into_inner().into_inner().1,
+            Input::Moving => unreachable!(),
+        }
+    }
+
+    // Reads the block into `input` up to `len` bytes.
+    // Returns false at the end of stream before the block.
+    fn fill(&mut self, len: usize) -> Result<bool> {
+        let r = match &mut self.reader {
+            Input::Blocks(r) => r,
+            _ => unreachable!(),
+        };
+        while self.input.len() < len {
+            let start = self.input.len();
+            self.input.resize(len, 0);
+            let result = r.read(&mut self.input[start..]);
+            self.input.truncate(start + *result.as_ref().unwrap_or(&0));
+            match result {
+                Ok(0) if start == 0 => return Ok(false),
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    // Reads the next block into `input`, or switches to the gzip members.
+    fn read_raw(&mut self) -> Result<Raw> {
+        if !self.fill(XLEN_END)? {
+            return Ok(Raw::End);
+        }
+        let header_len = probe_len(&self.input).unwrap_or(XLEN_END);
+        self.fill(header_len)?;
+        let bsize = match parse_gz_header(&self.input)
+            .ok()
+            .and_then(|h| h.subfield(BC))
+        {
+            Some(bc) if bc.len() == 2 => {
+                let bsize = u16::from_le_bytes([bc[0], bc[1]]) as usize + 1;
+                if self.header.is_none() {
+                    self.header = parse_gz_header(&self.input).ok().map(|h| (&h).into());
+                }
+                bsize
+            }
+            Some(_) => return Err(invalid("corrupt BGZF block")),
+            // A gzip member, whose header may continue, e.g. with FNAME.
+            None if self.input.starts_with(&[0x1f, 0x8b]) => {
+                let header = Cursor::new(mem::take(&mut self.input));
+                if let Input::Blocks(r) = mem::replace(&mut self.reader, Input::Moving) {
+                    self.reader = Input::Members(Box::new(MultiGzDecoder::new(header.chain(r))));
+                }
+                return Ok(Raw::Members);
+            }
+            None => return Err(invalid("not a BGZF block")),
+        };
+        if bsize < header_len + 8 {
+            return Err(invalid("corrupt BGZF block"));
+        }
+        self.fill(bsize)?;
+        Ok(Raw::Block(header_len))
+    }
+
+    // Decodes the next block, or switches to the gzip members.
+    // Returns false at the end of stream.
+    fn load(&mut self) -> Result<bool> {
+        if 1 < self.threads {
+            return self.load_parallel();
+        }
+        let header_len = match self.read_raw()? {
+            Raw::Block(header_len) => header_len,
+            Raw::Members => return Ok(true),
+            Raw::End => return Ok(false),
+        };
+        self.block.clear();
+        self.pos = 0;
+        self.block_offset = self.next_offset;
+        self.next_offset += self.input.len() as u64;
+
+        let result = decode(&self.input[header_len..], &mut self.block);
+        self.input.clear();
+        result?;
+        self.eof_block = self.block.is_empty();
+        Ok(true)
+    }
+
+    // Reads blocks ahead and decodes them on the threads into `decoded`.
+    fn load_parallel(&mut self) -> Result<bool> {
+        let mut blocks = Vec::new();
+        while blocks.len() < self.threads * BLOCKS_PER_THREAD {
+            match self.read_raw() {
+                Ok(Raw::Block(header_len)) => {
+                    let input = mem::take(&mut self.input);
+                    blocks.push((self.next_offset, header_len, input));
+                    self.next_offset += blocks[blocks.len() - 1].2.len() as u64;
+                }
+                Ok(Raw::Members) if blocks.is_empty() => return Ok(true),
+                Ok(Raw::End) if blocks.is_empty() => return Ok(false),
+                Ok(_) => break,
+                // The blocks read before the error are delivered first.
+                Err(e) if blocks.is_empty() => return Err(e),
+                Err(e) => {
+                    self.pending = Some(e);
+                    break;
+                }
+            }
+        }
+
+        let results: Vec<Vec<Result<Vec<u8>>>> = thread::scope(|s| {
+            let handles: Vec<_> = blocks
+                .chunks(BLOCKS_PER_THREAD)
+                .map(|chunk| {
+                    s.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|(_, header_len, input)| {
+                                let mut block = Vec::new();
+                                decode(&input[*header_len..], &mut block).map(|_| block)
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                .collect()
+        });
+
+        for ((offset, _, _), result) in blocks.iter().zip(results.into_iter().flatten()) {
+            match result {
+                Ok(block) => self.decoded.push_back((*offset, block)),
+                Err(e) => {
+                    self.pending = Some(e);
+                    break;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    // Moves to the next block decoded in parallel, if any.
+    fn next_decoded(&mut self) -> bool {
+        match self.decoded.pop_front() {
+            Some((offset, block)) => {
+                self.block_offset = offset;
+                self.block = block;
+                self.pos = 0;
+                self.eof_block = self.block.is_empty();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Decodes a block into `block`, from the deflated data followed by CRC32 and ISIZE.
+fn decode(input: &[u8], block: &mut Vec<u8>) -> Result<()> {
+    let (data, trailer) = input.split_at(input.len() - 8);
+    let isize = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    // ISIZE is not trusted for the allocation until the block is decoded.
+    if MAX_BLOCK_LEN < isize as usize {
+        return Err(invalid("corrupt BGZF block"));
+    }
+    // One more byte to detect a block longer than ISIZE.
+    block.reserve_exact(isize as usize + 1);
+    let mut d = Decompress::new(false);
+    let status = d
+        .decompress_vec(data, block, FlushDecompress::Finish)
+        .map_err(|_| invalid("corrupt deflate stream"))?;
+    if status != Status::StreamEnd || block.len() != isize as usize {
+        return Err(invalid("corrupt BGZF block"));
+    }
+    let mut crc = Crc::new();
+    crc.update(block);
+    if crc.sum() != u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) {
+        return Err(DecodeError::checksum("BGZF block checksum mismatch"));
+    }
+    Ok(())
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Moves to `offset`, a virtual offset given by e.g. a BAI or tabix index.
+    ///
+    /// The underlying reader must have been at its start when given to
+    /// [new](BgzfReader::new), so the compressed offset is its position.
+    pub fn seek_virtual(&mut self, offset: u64) -> Result<()> {
+        let (block_offset, pos) = (offset >> 16, (offset & 0xffff) as usize);
+        let mut r = match mem::replace(&mut self.reader, Input::Moving) {
+            Input::Blocks(r) => r,
+            Input::Members(d) => d.into_inner().into_inner().1,
+            Input::Moving => unreachable!(),
+        };
+        let sought = r.seek(SeekFrom::Start(block_offset));
+        self.reader = Input::Blocks(r);
+        sought?;
+        self.input.clear();
+        self.block.clear();
+        self.pos = 0;
+        self.decoded.clear();
+        self.pending = None;
+        self.next_offset = block_offset;
+        let loaded = self.load()?;
+        if !self.next_decoded() {
+            if let Some(e) = self.pending.take() {
+                return Err(e);
+            }
+        }
+        if loaded && matches!(self.reader, Input::Members(_)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "virtual offset not at a BGZF block",
+            ));
+        }
+        if self.block.len() < pos {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "virtual offset beyond the block",
+            ));
+        }
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        while self.pos == self.block.len() {
+            if self.next_decoded() {
+                continue;
+            }
+            if let Some(e) = self.pending.take() {
+                return Err(e);
+            }
+            if let Input::Members(d) = &mut self.reader {
+                return d.read(buf);
+            }
+            if !self.load()? {
+                if self.require_eof_block && !self.eof_block {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "missing BGZF EOF block (possibly truncated)",
+                    ));
+                }
+                return Ok(0);
+            }
+        }
+        let n = (&self.block[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use flate2::write::DeflateEncoder;
+    use flate2::{Compression, Crc, GzBuilder};
+    use std::io::{Cursor, ErrorKind, Read, Write};
+
+    use super::{is_bgzf, BgzfReader};
+    use crate::test_support::ShortRead;
+
+    // The EOF block defined by the SAM/BAM specification.
+    pub(crate) const EOF_BLOCK: [u8; 28] = [
+        0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, 0x42, 0x43, 0x02, 0, 0x1b, 0, 0x03,
+        0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    // Compresses `data` into BGZF blocks of `block_len` bytes, followed by the EOF block.
+    pub(crate) fn bgzf(data: &[u8], block_len: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for chunk in data.chunks(block_len) {
+            let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+            e.write_all(chunk).unwrap();
+            let body = e.finish().unwrap();
+            let bsize = (18 + body.len() + 8 - 1) as u16;
+            out.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0]);
+            out.extend_from_slice(b"BC\x02\x00");
+            out.extend_from_slice(&bsize.to_le_bytes());
+            out.extend_from_slice(&body);
+            let mut crc = Crc::new();
+            crc.update(chunk);
+            out.extend_from_slice(&crc.sum().to_le_bytes());
+            out.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        }
+        out.extend_from_slice(&EOF_BLOCK);
+        out
+    }
+
+    #[test]
+    fn bgzf_virtual_offset() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let z = bgzf(&data, 1000);
+        assert!(is_bgzf(&z));
+
+        let mut r = BgzfReader::new(Cursor::new(&z));
+        let mut buf = vec![0; 2500];
+        r.read_exact(&mut buf).unwrap();
+        let offset = r.virtual_offset();
+        assert_eq!(offset & 0xffff, 500);
+
+        let mut rest = Vec::new();
+        r.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[2500..]);
+
+        r.seek_virtual(offset).unwrap();
+        let mut rest = Vec::new();
+        r.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[2500..]);
+    }
+    #[test]
+    fn bgzf_truncated() {
+        let data = vec![0x41; 5000];
+        let z = bgzf(&data, 1000);
+        let z = &z[..z.len() - EOF_BLOCK.len()];
+        let e = BgzfReader::new(z).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+
+        let mut out = Vec::new();
+        BgzfReader::new(z)
+            .lenient(true)
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, data);
+    }
+    #[test]
+    fn bgzf_followed_by_gzip() {
+        let data = vec![0x41; 5000];
+        let mut e = GzBuilder::new()
+            .filename("b.txt")
+            .write(Vec::new(), Compression::default());
+        e.write_all(b"appended").unwrap();
+        let gz = e.finish().unwrap();
+
+        let z = bgzf(&data, 1000);
+        let truncated = &z[..z.len() - EOF_BLOCK.len()];
+        for z in [&z[..], truncated] {
+            let input = [z, &gz, &gz].concat();
+            let mut out = Vec::new();
+            BgzfReader::new(ShortRead::new(&input[..], 7))
+                .read_to_end(&mut out)
+                .unwrap();
+            assert_eq!(out, [&data[..], b"appendedappended"].concat());
+        }
+
+        let input = [&z[..], b"garbage!!!!!!"].concat();
+        let e = BgzfReader::new(&input[..])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+    #[test]
+    fn bgzf_oversized() {
+        // ISIZE beyond the limit of a block is rejected before decoding.
+        let mut z = bgzf(b"Hello", 1000);
+        let isize_at = z.len() - EOF_BLOCK.len() - 4;
+        z[isize_at..isize_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        for &threads in [1, 2].iter() {
+            let e = BgzfReader::new(&z[..])
+                .threads(threads)
+                .read_to_end(&mut Vec::new())
+                .unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+        }
+
+        // So is a block decoded to more than ISIZE.
+        let mut z = bgzf(&[0x41; 1000], 1000);
+        let isize_at = z.len() - EOF_BLOCK.len() - 4;
+        z[isize_at..isize_at + 4].copy_from_slice(&10u32.to_le_bytes());
+        let e = BgzfReader::new(&z[..])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+    #[test]
+    fn bgzf_threads() {
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let z = bgzf(&data, 1000);
+        for &threads in &[0, 2, 3, 16] {
+            let mut out = Vec::new();
+            BgzfReader::new(ShortRead::new(&z[..], 7))
+                .threads(threads)
+                .read_to_end(&mut out)
+                .unwrap();
+            assert_eq!(out, data);
+        }
+
+        let mut r = BgzfReader::new(Cursor::new(&z)).threads(3);
+        let mut buf = vec![0; 123_456];
+        r.read_exact(&mut buf).unwrap();
+        let offset = r.virtual_offset();
+        r.seek_virtual(0).unwrap();
+        r.seek_virtual(offset).unwrap();
+        let mut rest = Vec::new();
+        r.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[123_456..]);
+
+        // The blocks before a corrupt block are given first.
+        let mut z = z.clone();
+        let at = z.len() / 2;
+        z[at] ^= 0xff;
+        let mut r = BgzfReader::new(&z[..]).threads(4);
+        let mut out = Vec::new();
+        assert!(r.read_to_end(&mut out).is_err());
+        assert!(100_000 < out.len());
+        assert_eq!(out, &data[..out.len()]);
+    }
+}
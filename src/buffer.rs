@@ -0,0 +1,109 @@
+use std::io::{Read, Result};
+
+// Read-ahead buffer resized by the observed reads.
+//
+// The buffer grows while the underlying reader fills it completely (bulk transfer),
+// and shrinks while the reader returns much less than requested (trickle stream).
+#[derive(Debug)]
+pub(crate) struct AdaptiveBuffer {
+    buf: Vec<u8>,
+    pos: usize,
+
+    cap: usize,
+    min: usize,
+    max: usize,
+}
+
+impl AdaptiveBuffer {
+    pub(crate) fn new(min: usize, max: usize) -> AdaptiveBuffer {
+        debug_assert!(0 < min && min <= max);
+        AdaptiveBuffer {
+            buf: Vec::new(),
+            pos: 0,
+            cap: min,
+            min,
+            max,
+        }
+    }
+
+    // Bytes read ahead and not yet returned.
+    pub(crate) fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    #[cfg(test)]
+    pub(crate) fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    fn adapt(&mut self, requested: usize, n: usize) {
+        if n == requested {
+            self.cap = (self.cap * 2).min(self.max);
+        } else if n < requested / 4 {
+            self.cap = (self.cap / 2).max(self.min);
+        }
+    }
+
+    pub(crate) fn read<F>(&mut self, mut read: F, out: &mut [u8]) -> Result<usize>
+    where
+        F: FnMut(&mut [u8]) -> Result<usize>,
+    {
+        if self.pos == self.buf.len() {
+            // Large reads need no buffering.
+            if self.cap <= out.len() {
+                let n = read(out)?;
+                self.adapt(out.len(), n);
+                return Ok(n);
+            }
+
+            self.buf.resize(self.cap, 0);
+            let n = match read(&mut self.buf) {
+                Ok(n) => n,
+                Err(e) => {
+                    self.buf.clear();
+                    self.pos = 0;
+                    return Err(e);
+                }
+            };
+            let requested = self.buf.len();
+            self.buf.truncate(n);
+            self.pos = 0;
+            self.adapt(requested, n);
+        }
+
+        let n = (&self.buf[self.pos..]).read(out)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::AdaptiveBuffer;
+
+    #[test]
+    fn adaptive() {
+        let data = vec![0x41; 100_000];
+        let mut b = AdaptiveBuffer::new(16, 1024);
+        let mut src = &data[..];
+        let mut out = [0; 8];
+        let mut total = 0;
+        for _ in 0..100 {
+            total += b.read(|buf| src.read(buf), &mut out).unwrap();
+        }
+        assert_eq!(total, 800);
+        assert_eq!(b.capacity(), 1024);
+
+        // Trickle reads shrink the buffer.
+        let mut b = AdaptiveBuffer::new(16, 1024);
+        let mut big = [0; 2048];
+        b.read(|buf| src.read(buf), &mut big).unwrap();
+        assert_eq!(b.capacity(), 32);
+        for _ in 0..10 {
+            b.read(|buf| (&data[..1]).read(buf), &mut out).unwrap();
+        }
+        assert_eq!(b.capacity(), 16);
+    }
+}
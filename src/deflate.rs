@@ -0,0 +1,250 @@
+use flate2::{Decompress, FlushDecompress, Status};
+#[cfg(feature = "zlib-dictionary")]
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result};
+#[cfg(feature = "zlib-dictionary")]
+use std::sync::Arc;
+
+#[cfg(feature = "zlib-dictionary")]
+use crate::error::DecodeError;
+
+// Number of bytes tried to inflate before a stream is accepted as deflate.
+pub(crate) const PROBE_LEN: usize = 4 * 1024;
+
+// zlib header: CMF of deflate with a window up to 32 KiB, FLG without a preset dictionary,
+// and FCHECK making CMF * 256 + FLG a multiple of 31, e.g. 0x78 0x9c.
+pub(crate) fn is_zlib(preread: &[u8]) -> bool {
+    is_zlib_header(preread) && preread[1] & FDICT == 0
+}
+
+// FLG bit of a preset dictionary, followed by its Adler-32 (DICTID).
+const FDICT: u8 = 0x20;
+
+fn is_zlib_header(preread: &[u8]) -> bool {
+    2 <= preread.len()
+        && preread[0] & 0x0f == 8
+        && preread[0] >> 4 <= 7
+        && (u16::from(preread[0]) << 8 | u16::from(preread[1])) % 31 == 0
+}
+
+// zlib header requiring the preset dictionary whose Adler-32 is `dict_id`.
+#[cfg(feature = "zlib-dictionary")]
+pub(crate) fn is_zlib_with_dictionary(preread: &[u8], dict_id: u32) -> bool {
+    6 <= preread.len()
+        && is_zlib_header(preread)
+        && preread[1] & FDICT != 0
+        && preread[2..6] == dict_id.to_be_bytes()
+}
+
+// Adler-32 of `data`, as DICTID of a zlib header.
+#[cfg(feature = "zlib-dictionary")]
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1, 0);
+    // 5552 bytes are summed without overflow.
+    for chunk in data.chunks(5552) {
+        for &c in chunk {
+            a += u32::from(c);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
+
+/// A preset dictionary of zlib streams,
+/// given by [EgzReaderBuilder::zlib_dictionary](crate::EgzReaderBuilder::zlib_dictionary).
+#[cfg(feature = "zlib-dictionary")]
+#[derive(Debug, Clone)]
+pub(crate) struct ZlibDictionary {
+    pub(crate) id: u32,
+    data: Arc<[u8]>,
+}
+
+#[cfg(feature = "zlib-dictionary")]
+impl ZlibDictionary {
+    pub(crate) fn new(data: &[u8]) -> ZlibDictionary {
+        ZlibDictionary {
+            id: adler32(data),
+            data: data.into(),
+        }
+    }
+}
+
+// Decoder of a zlib stream, giving the preset dictionary when the stream requires it.
+#[cfg(feature = "zlib-dictionary")]
+#[derive(Debug)]
+pub(crate) struct ZlibDictDecoder<R: Read> {
+    input: BufReader<R>,
+    d: Decompress,
+    dictionary: ZlibDictionary,
+    done: bool,
+}
+
+#[cfg(feature = "zlib-dictionary")]
+impl<R: Read> ZlibDictDecoder<R> {
+    pub(crate) fn new(r: R, dictionary: ZlibDictionary) -> ZlibDictDecoder<R> {
+        ZlibDictDecoder {
+            input: BufReader::new(r),
+            d: Decompress::new(true),
+            dictionary,
+            done: false,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        self.input.get_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        self.input.get_mut()
+    }
+
+    // Note that the buffered bytes are lost.
+    pub(crate) fn into_inner(self) -> R {
+        self.input.into_inner()
+    }
+}
+
+#[cfg(feature = "zlib-dictionary")]
+impl<R: Read> Read for ZlibDictDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        while !self.done && !buf.is_empty() {
+            let input = self.input.fill_buf()?;
+            let eof = input.is_empty();
+            let (before_in, before_out) = (self.d.total_in(), self.d.total_out());
+            let result = self.d.decompress(input, buf, FlushDecompress::None);
+            self.input.consume((self.d.total_in() - before_in) as usize);
+            let produced = (self.d.total_out() - before_out) as usize;
+
+            match result {
+                Ok(Status::StreamEnd) => self.done = true,
+                Ok(_) if produced == 0 && eof => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "incomplete zlib stream",
+                    ))
+                }
+                Ok(_) => {}
+                Err(e) if e.needs_dictionary().is_some() => {
+                    self.d.set_dictionary(&self.dictionary.data).map_err(|_| {
+                        DecodeError::corrupt("zlib stream requires another dictionary")
+                    })?;
+                }
+                Err(_) => return Err(DecodeError::corrupt("corrupt deflate stream")),
+            }
+            if produced != 0 {
+                return Ok(produced);
+            }
+        }
+        Ok(0)
+    }
+}
+
+// Returns true if `probe`, the beginning of a stream, can be inflated without an error.
+//
+// A probe shorter than PROBE_LEN must be the complete stream, which must be inflated to the end.
+pub(crate) fn is_deflate(probe: &[u8], zlib: bool) -> bool {
+    let mut d = Decompress::new(zlib);
+    let mut out = Vec::with_capacity(32 * 1024);
+    loop {
+        out.clear();
+        let consumed = d.total_in() as usize;
+        let before = d.total_out();
+        match d.decompress_vec(&probe[consumed..], &mut out, FlushDecompress::None) {
+            Err(_) => return false,
+            Ok(Status::StreamEnd) => return true,
+            Ok(_) if d.total_in() as usize == consumed && d.total_out() == before => {
+                // All bytes are accepted, and the stream continues.
+                return d.total_in() as usize == probe.len() && PROBE_LEN <= probe.len();
+            }
+            Ok(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use flate2::write::{DeflateEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[cfg(feature = "zlib-dictionary")]
+    use std::io::{ErrorKind, Read};
+
+    #[cfg(feature = "zlib-dictionary")]
+    use super::{adler32, is_zlib_with_dictionary, ZlibDictDecoder, ZlibDictionary};
+    use super::{is_deflate, is_zlib};
+
+    #[test]
+    fn deflate() {
+        let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let data = e.finish().unwrap();
+
+        assert!(is_deflate(&data, false));
+        assert!(!is_deflate(&data[..data.len() - 1], false));
+        assert!(!is_deflate(b"Hello, World!", false));
+        assert!(!is_deflate(&data, true));
+    }
+    // zlib of `data` with the preset dictionary `dictionary`
+    #[cfg(feature = "zlib-dictionary")]
+    pub(crate) fn zlib_with_dictionary(data: &[u8], dictionary: &[u8]) -> Vec<u8> {
+        let mut c = flate2::Compress::new(Compression::default(), true);
+        c.set_dictionary(dictionary).unwrap();
+        let mut out = Vec::with_capacity(data.len() + 64);
+        c.compress_vec(data, &mut out, flate2::FlushCompress::Finish)
+            .unwrap();
+        out
+    }
+
+    #[cfg(feature = "zlib-dictionary")]
+    #[test]
+    fn zlib_dictionary() {
+        let dictionary = b"Hello, ";
+        let data = zlib_with_dictionary(b"Hello, World!", dictionary);
+        let id = adler32(dictionary);
+        assert!(!is_zlib(&data));
+        assert!(is_zlib_with_dictionary(&data, id));
+        assert!(!is_zlib_with_dictionary(&data, id + 1));
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        let long = vec![0xff; 100_000];
+        let (a, b) = long.iter().fold((1u64, 0u64), |(a, b), &c| {
+            let a = (a + u64::from(c)) % 65521;
+            (a, (b + a) % 65521)
+        });
+        assert_eq!(adler32(&long), (b << 16 | a) as u32);
+
+        let mut s = String::new();
+        ZlibDictDecoder::new(&data[..], ZlibDictionary::new(dictionary))
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello, World!");
+
+        let mut r = ZlibDictDecoder::new(&data[..], ZlibDictionary::new(b"Hi"));
+        let e = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        let mut r = ZlibDictDecoder::new(&data[..data.len() - 2], ZlibDictionary::new(dictionary));
+        let e = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+    #[test]
+    fn zlib() {
+        for level in [
+            Compression::fast(),
+            Compression::default(),
+            Compression::best(),
+        ]
+        .iter()
+        {
+            let mut e = ZlibEncoder::new(Vec::new(), *level);
+            e.write_all(b"Hello!").unwrap();
+            let data = e.finish().unwrap();
+            assert!(is_zlib(&data));
+            assert!(is_deflate(&data, true));
+        }
+        assert!(is_zlib(b"x^"));
+        assert!(!is_zlib(b"Hello!"));
+        assert!(!is_zlib(b"x"));
+    }
+}
@@ -0,0 +1,278 @@
+use flate2::{Decompress, FlushDecompress, Status};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+
+use crate::error::DecodeError;
+use crate::header::{parse_gz_header, HeaderError};
+
+// Subfield ID of the dictzip chunk table.
+const RA: [u8; 2] = *b"RA";
+
+fn invalid(msg: &str) -> Error {
+    DecodeError::corrupt(msg)
+}
+
+/// A reader of dictzip (`.dz`) files supporting random access.
+///
+/// dictzip is a gzip file compressed in chunks, whose sizes are stored in the `RA` subfield
+/// of the header. Any offset of the decoded content is reached by decoding one chunk,
+/// without building an index.
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::prelude::*;
+/// use egzreader::DictZipReader;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut r = DictZipReader::new(File::open("words.dict.dz")?)?;
+/// r.seek_to_uncompressed_offset(123_456)?;
+/// let mut buf = [0; 100];
+/// r.read_exact(&mut buf)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DictZipReader<R: Read + Seek> {
+    reader: R,
+
+    chunk_len: u64,
+    // Offsets of the chunks in the file, followed by the end of the last chunk.
+    offsets: Vec<u64>,
+
+    // Decoded bytes of the current chunk.
+    chunk: Vec<u8>,
+    index: usize,
+    pos: usize,
+}
+
+impl<R: Read + Seek> DictZipReader<R> {
+    /// Reads the header of `reader` at its current position.
+    ///
+    /// Returns an error of `InvalidData` if the stream is not dictzip.
+    pub fn new(mut reader: R) -> Result<DictZipReader<R>> {
+        let start = reader.stream_position()?;
+        let mut buf = Vec::new();
+        let (header_len, table) = loop {
+            match parse_gz_header(&buf) {
+                Ok(h) => {
+                    let ra = h
+                        .subfield(RA)
+                        .ok_or_else(|| invalid("not a dictzip file"))?;
+                    break (h.len() as u64, ra.to_vec());
+                }
+                Err(HeaderError::NeedMoreBytes) => {
+                    let len = buf.len();
+                    buf.resize(len + 512, 0);
+                    let n = reader.read(&mut buf[len..])?;
+                    buf.truncate(len + n);
+                    if n == 0 {
+                        return Err(ErrorKind::UnexpectedEof.into());
+                    }
+                }
+                Err(e) => return Err(invalid(&e.to_string())),
+            }
+        };
+
+        // VER, CHLEN, CHCNT and the sizes of the chunks
+        let u16_at = |i: usize| u16::from_le_bytes([table[i], table[i + 1]]) as u64;
+        if table.len() < 6 || u16_at(0) != 1 {
+            return Err(invalid("unsupported dictzip table"));
+        }
+        let (chunk_len, count) = (u16_at(2), u16_at(4) as usize);
+        if chunk_len == 0 || table.len() < 6 + 2 * count {
+            return Err(invalid("corrupt dictzip table"));
+        }
+        let mut offsets = vec![start + header_len];
+        for i in 0..count {
+            offsets.push(offsets[i] + u16_at(6 + 2 * i));
+        }
+
+        let mut r = DictZipReader {
+            reader,
+            chunk_len,
+            offsets,
+            chunk: Vec::new(),
+            index: 0,
+            pos: 0,
+        };
+        r.load(0)?;
+        Ok(r)
+    }
+
+    /// Returns the number of chunks.
+    pub fn chunk_count(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Returns the decoded size of each chunk but the last.
+    pub fn chunk_len(&self) -> u64 {
+        self.chunk_len
+    }
+
+    /// Moves to `offset` of the decoded content, decoding the chunk containing it.
+    ///
+    /// An offset at or beyond the end of content results in EOF.
+    pub fn seek_to_uncompressed_offset(&mut self, offset: u64) -> Result<()> {
+        let index = (offset / self.chunk_len).min(self.chunk_count() as u64) as usize;
+        self.load(index)?;
+        self.pos = ((offset - index as u64 * self.chunk_len) as usize).min(self.chunk.len());
+        Ok(())
+    }
+
+    // Decodes the chunk at `index`, or empties the chunk if it is beyond the last.
+    fn load(&mut self, index: usize) -> Result<()> {
+        self.index = index;
+        self.pos = 0;
+        self.chunk.clear();
+        if self.chunk_count() <= index {
+            return Ok(());
+        }
+
+        let (start, end) = (self.offsets[index], self.offsets[index + 1]);
+        let mut input = vec![0; (end - start) as usize];
+        self.reader.seek(SeekFrom::Start(start))?;
+        self.reader.read_exact(&mut input)?;
+
+        // Each chunk ends at a full flush, so it can be decoded independently.
+        // One more byte than a chunk is decoded at most, to detect a longer chunk.
+        let mut d = Decompress::new(false);
+        self.chunk.reserve_exact(self.chunk_len as usize + 1);
+        let last = index + 1 == self.chunk_count();
+        let ended = loop {
+            let consumed = d.total_in() as usize;
+            let before = self.chunk.len();
+            let status = d
+                .decompress_vec(&input[consumed..], &mut self.chunk, FlushDecompress::Sync)
+                .map_err(|_| invalid("corrupt deflate stream"))?;
+            if status == Status::StreamEnd {
+                break true;
+            }
+            if d.total_in() as usize == input.len() || self.chunk_len < self.chunk.len() as u64 {
+                break false;
+            }
+            if self.chunk.len() == before && consumed == d.total_in() as usize {
+                return Err(invalid("corrupt deflate stream"));
+            }
+        };
+
+        // Every chunk but the last has the chunk length, and the last ends the deflate stream.
+        // Otherwise the offsets of the following chunks would be wrong.
+        let len = self.chunk.len() as u64;
+        match last {
+            false if len != self.chunk_len => Err(invalid("corrupt dictzip chunk")),
+            true if self.chunk_len < len || !ended => Err(invalid("corrupt dictzip chunk")),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for DictZipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.pos == self.chunk.len() && self.index < self.chunk_count() {
+            self.load(self.index + 1)?;
+        }
+        let n = (&self.chunk[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{Compress, Compression, Crc, FlushCompress};
+    use std::io::{Cursor, ErrorKind, Read};
+
+    use super::DictZipReader;
+
+    // Compresses `data` in chunks of `chunk_len` as dictzip does.
+    fn dictzip(data: &[u8], chunk_len: usize) -> Vec<u8> {
+        let mut c = Compress::new(Compression::default(), false);
+        let mut body = Vec::new();
+        let mut sizes = Vec::new();
+        let chunks: Vec<&[u8]> = data.chunks(chunk_len).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let flush = if i + 1 == chunks.len() {
+                FlushCompress::Finish
+            } else {
+                FlushCompress::Full
+            };
+            let before = body.len();
+            body.reserve(chunk.len() * 2 + 64);
+            c.compress_vec(chunk, &mut body, flush).unwrap();
+            sizes.push((body.len() - before) as u16);
+        }
+
+        let mut table = vec![1, 0];
+        table.extend_from_slice(&(chunk_len as u16).to_le_bytes());
+        table.extend_from_slice(&(sizes.len() as u16).to_le_bytes());
+        for s in sizes.iter() {
+            table.extend_from_slice(&s.to_le_bytes());
+        }
+        let mut extra = b"RA".to_vec();
+        extra.extend_from_slice(&(table.len() as u16).to_le_bytes());
+        extra.extend_from_slice(&table);
+
+        let mut out = vec![0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff];
+        out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        out.extend_from_slice(&extra);
+        out.extend_from_slice(&body);
+        let mut crc = Crc::new();
+        crc.update(data);
+        out.extend_from_slice(&crc.sum().to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn dictzip_random_access() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let dz = dictzip(&data, 1000);
+
+        // It is a valid gzip file.
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(&dz[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let mut r = DictZipReader::new(Cursor::new(&dz)).unwrap();
+        assert_eq!(r.chunk_count(), 40);
+        let mut all = Vec::new();
+        r.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+
+        for &offset in [0, 999, 1000, 12_345, 39_999].iter() {
+            r.seek_to_uncompressed_offset(offset).unwrap();
+            let mut buf = [0; 1];
+            r.read_exact(&mut buf).unwrap();
+            assert_eq!(buf[0], data[offset as usize]);
+        }
+        r.seek_to_uncompressed_offset(40_000).unwrap();
+        assert_eq!(r.read(&mut [0; 1]).unwrap(), 0);
+    }
+    #[test]
+    fn dictzip_invalid() {
+        let mut e = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        std::io::Write::write_all(&mut e, b"Hello").unwrap();
+        let gz = e.finish().unwrap();
+        let e = DictZipReader::new(Cursor::new(&gz)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        // The RA table moves 100 bytes of the second chunk to the third,
+        // so the second chunk is truncated.
+        let data: Vec<u8> = (0..1000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut dz = dictzip(&data, 1000);
+        let size_at = 10 + 2 + 4 + 6;
+        let size = |dz: &[u8], i: usize| {
+            u16::from_le_bytes([dz[size_at + 2 * i], dz[size_at + 2 * i + 1]])
+        };
+        let (first, second) = (size(&dz, 1) - 100, size(&dz, 2) + 100);
+        dz[size_at + 2..size_at + 4].copy_from_slice(&first.to_le_bytes());
+        dz[size_at + 4..size_at + 6].copy_from_slice(&second.to_le_bytes());
+        let mut r = DictZipReader::new(Cursor::new(&dz)).unwrap();
+        let e = r.seek_to_uncompressed_offset(1500).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        let e = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,149 @@
+use std::error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+
+use crate::Format;
+
+/// The class of an error of reading an [EgzReader](crate::EgzReader).
+///
+/// `EgzReader` implements [Read](std::io::Read), so this is given inside the
+/// `io::Error`, whose kind is kept, and found by [of](EgzError::of).
+///
+/// # Examples
+/// ```
+/// use std::io::prelude::*;
+/// use egzreader::{EgzError, EgzReader, Format};
+///
+/// # let data: &[u8] = &[
+/// #     0x1f, 0x8b, 0x08, 0x00, 0xeb, 0x47, 0x74, 0x60, 0x00, 0x03, 0xf3, 0x48, 0xcd, 0xc9,
+/// #     0xc9, 0x57, 0x04, 0x00,
+/// # ];
+/// // gzip of "Hello!" without the trailer
+/// let e = EgzReader::new(data).read_to_end(&mut Vec::new()).unwrap_err();
+/// match EgzError::of(&e) {
+///     Some(EgzError::UnexpectedEof(format, _)) => assert_eq!(*format, Format::Gzip),
+///     _ => unreachable!(),
+/// }
+/// ```
+#[derive(Debug)]
+pub enum EgzError {
+    /// Reading the underlying reader failed while detecting the format.
+    Detection(io::Error),
+    /// The compressed data of the detected format is corrupt.
+    Corrupt(Format, io::Error),
+    /// The checksum of the decoded data does not match.
+    ///
+    /// The decoders of this crate, e.g. of BGZF and ZIP, tell a mismatch by the type of
+    /// the error. Mismatches of gzip and zlib given by flate2 are found by the message
+    /// containing "checksum" as a best effort; other mismatches are classified as
+    /// [Corrupt](EgzError::Corrupt).
+    Checksum(Format, io::Error),
+    /// The stream ends in the middle of the compressed data.
+    UnexpectedEof(Format, io::Error),
+}
+
+impl EgzError {
+    /// Returns the class of `e`, if `e` is an error of decoding given by an `EgzReader`.
+    pub fn of(e: &io::Error) -> Option<&EgzError> {
+        e.get_ref()?.downcast_ref()
+    }
+
+    /// Returns the detected format, or `None` if the detection failed.
+    pub fn format(&self) -> Option<Format> {
+        match self {
+            EgzError::Detection(_) => None,
+            EgzError::Corrupt(format, _)
+            | EgzError::Checksum(format, _)
+            | EgzError::UnexpectedEof(format, _) => Some(*format),
+        }
+    }
+
+    /// Returns the error given by the underlying reader or the decoder.
+    pub fn io_error(&self) -> &io::Error {
+        match self {
+            EgzError::Detection(e)
+            | EgzError::Corrupt(_, e)
+            | EgzError::Checksum(_, e)
+            | EgzError::UnexpectedEof(_, e) => e,
+        }
+    }
+
+    // Classifies an error of reading ReaderType, whose format is `format` after the read.
+    //
+    // Errors not of decoding, e.g. of the underlying reader after the detection,
+    // and transient errors are given as they are.
+    pub(crate) fn classify(e: io::Error, format: Option<Format>) -> io::Error {
+        match e.kind() {
+            ErrorKind::WouldBlock | ErrorKind::Interrupted => return e,
+            _ if e.get_ref().map_or(false, |e| e.is::<EgzError>()) => return e,
+            _ => {}
+        }
+        let class = match format {
+            None => EgzError::Detection(e),
+            Some(Format::Raw) | Some(Format::Empty) => return e,
+            Some(format) => match e.kind() {
+                ErrorKind::UnexpectedEof => EgzError::UnexpectedEof(format, e),
+                ErrorKind::InvalidData | ErrorKind::InvalidInput => {
+                    match e.get_ref().and_then(|e| e.downcast_ref()) {
+                        Some(DecodeError::Checksum(_)) => EgzError::Checksum(format, e),
+                        Some(DecodeError::Corrupt(_)) => EgzError::Corrupt(format, e),
+                        // flate2 tells a checksum mismatch only by the message.
+                        None if e.to_string().contains("checksum") => EgzError::Checksum(format, e),
+                        None => EgzError::Corrupt(format, e),
+                    }
+                }
+                _ => return e,
+            },
+        };
+        class.into()
+    }
+}
+
+// An error of the decoders of this crate, given inside an `io::Error` of `InvalidData`.
+#[derive(Debug)]
+pub(crate) enum DecodeError {
+    Corrupt(String),
+    Checksum(String),
+}
+
+impl DecodeError {
+    pub(crate) fn corrupt(msg: &str) -> io::Error {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            DecodeError::Corrupt(msg.to_string()),
+        )
+    }
+
+    pub(crate) fn checksum(msg: &str) -> io::Error {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            DecodeError::Checksum(msg.to_string()),
+        )
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Corrupt(msg) | DecodeError::Checksum(msg) => f.write_str(msg),
+        }
+    }
+}
+impl error::Error for DecodeError {}
+
+impl fmt::Display for EgzError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.io_error().fmt(f)
+    }
+}
+impl error::Error for EgzError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.io_error())
+    }
+}
+
+impl From<EgzError> for io::Error {
+    fn from(e: EgzError) -> io::Error {
+        io::Error::new(e.io_error().kind(), e)
+    }
+}
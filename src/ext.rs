@@ -0,0 +1,62 @@
+use std::io::Read;
+
+use crate::{EgzReader, EgzReaderBuilder};
+
+/// An extension trait to adapt any reader into an [EgzReader](EgzReader) fluently.
+///
+/// # Examples
+/// ```
+/// use std::fs::File;
+/// use std::io::prelude::*;
+/// use egzreader::EgzReadExt;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut s = String::new();
+/// File::open("examples/hello.txt.gz")?
+///     .auto_decompress()
+///     .read_to_string(&mut s)?;
+/// assert_eq!(s, "Hello!");
+/// # Ok(())
+/// # }
+/// ```
+pub trait EgzReadExt: Read + Sized {
+    /// Creates an [EgzReader](EgzReader) of this reader.
+    ///
+    /// Equivalent to `EgzReader::new(self)`.
+    fn auto_decompress(self) -> EgzReader<Self> {
+        EgzReader::new(self)
+    }
+
+    /// Creates an [EgzReader](EgzReader) of this reader with the options of `builder`.
+    ///
+    /// Equivalent to `builder.build(self)`.
+    fn auto_decompress_with(self, builder: &EgzReaderBuilder) -> EgzReader<Self> {
+        builder.build(self)
+    }
+}
+
+impl<R: Read> EgzReadExt for R {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::EgzReadExt;
+    use crate::test_support::HELLO_GZ;
+    use crate::{EgzReaderBuilder, Format};
+
+    #[test]
+    fn auto_decompress() {
+        let mut s = String::new();
+        HELLO_GZ.auto_decompress().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        let builder = EgzReaderBuilder::new().force(Format::Raw);
+        let mut out = Vec::new();
+        HELLO_GZ
+            .auto_decompress_with(&builder)
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, HELLO_GZ);
+    }
+}
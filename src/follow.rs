@@ -0,0 +1,338 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::{EgzReader, EgzReaderBuilder};
+
+// Error telling the follower to read the file again from the beginning.
+#[derive(Debug)]
+struct Restart;
+impl fmt::Display for Restart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("file was truncated or rotated")
+    }
+}
+impl error::Error for Restart {}
+
+fn is_restart(e: &Error) -> bool {
+    e.get_ref().map_or(false, |e| e.is::<Restart>())
+}
+
+#[cfg(unix)]
+fn same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        // Keep reading the current file until a new one appears.
+        _ => true,
+    }
+}
+#[cfg(not(unix))]
+fn same_file(_file: &File, _path: &Path) -> bool {
+    true
+}
+
+/// A handle to stop an [EgzFollower](EgzFollower) from another thread.
+#[derive(Debug, Clone)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    /// Stops following. The follower returns EOF instead of waiting for the file to grow.
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+// File reader which waits for the file to grow at its end.
+#[derive(Debug)]
+struct Growing {
+    file: File,
+    pos: u64,
+
+    path: PathBuf,
+    poll_interval: Duration,
+    stop: Arc<AtomicBool>,
+}
+
+impl Read for Growing {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let n = self.file.read(buf)?;
+            if 0 < n || buf.is_empty() || self.stop.load(Ordering::Relaxed) {
+                self.pos += n as u64;
+                return Ok(n);
+            }
+
+            if self.file.metadata()?.len() < self.pos || !same_file(&self.file, &self.path) {
+                return Err(Error::new(ErrorKind::Other, Restart));
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+/// A reader following a growing file, like `tail -f`, for gzip and non-gzip files.
+///
+/// When the reader reaches the end of file, it waits for the file to grow instead of returning EOF,
+/// polling the file every `poll_interval`.
+/// Gzip members appended to the file are decoded one after another.
+/// When the file is truncated or rotated (replaced by a new file at the same path),
+/// the follower reads the new content from the beginning, detecting its format again.
+/// Truncation is noticed when the file gets shorter than the position already read,
+/// and rotation is noticed on Unix only.
+///
+/// The file is read by an [EgzReader](EgzReader) with the options of the builder given to
+/// [EgzReaderBuilder::follow](EgzReaderBuilder::follow), or the default options,
+/// with [multi_member](EgzReaderBuilder::multi_member) always enabled.
+/// Since the format is detected from the beginning of the file, content shorter than the
+/// detection is not returned until the file grows.
+/// Use [stop_handle](EgzFollower::stop_handle) to stop following.
+///
+/// # Examples
+/// ```no_run
+/// use std::io::prelude::*;
+/// use std::io::BufReader;
+/// use std::time::Duration;
+/// use egzreader::EgzFollower;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let f = EgzFollower::new("/var/log/app.log.gz", Duration::from_secs(1))?;
+/// for line in BufReader::new(f).lines() {
+///     println!("{}", line?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct EgzFollower {
+    path: PathBuf,
+    poll_interval: Duration,
+    stop: Arc<AtomicBool>,
+    restarts: u64,
+
+    builder: EgzReaderBuilder,
+    followed: EgzReader<Growing>,
+}
+
+impl EgzFollower {
+    /// Opens the file at `path` to follow with the default options.
+    pub fn new<P: AsRef<Path>>(path: P, poll_interval: Duration) -> Result<EgzFollower> {
+        EgzReaderBuilder::new().follow(path, poll_interval)
+    }
+
+    pub(crate) fn with_builder(
+        path: &Path,
+        poll_interval: Duration,
+        builder: EgzReaderBuilder,
+    ) -> Result<EgzFollower> {
+        let builder = builder.multi_member(true);
+        let stop = Arc::new(AtomicBool::new(false));
+        let file = Growing {
+            file: File::open(path)?,
+            pos: 0,
+            path: path.to_path_buf(),
+            poll_interval,
+            stop: stop.clone(),
+        };
+
+        Ok(EgzFollower {
+            path: path.to_path_buf(),
+            poll_interval,
+            stop,
+            restarts: 0,
+            followed: builder.build(file),
+            builder,
+        })
+    }
+
+    /// Returns a handle to stop following.
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(self.stop.clone())
+    }
+
+    /// Returns how many times the file has been read again from the beginning
+    /// because of truncation or rotation.
+    pub fn restarts(&self) -> u64 {
+        self.restarts
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    // Opens the file again, waiting for it to be created.
+    fn reopen(&mut self) -> Result<()> {
+        self.restarts += 1;
+        loop {
+            match File::open(&self.path) {
+                Ok(file) => {
+                    self.followed = self.builder.build(Growing {
+                        file,
+                        pos: 0,
+                        path: self.path.clone(),
+                        poll_interval: self.poll_interval,
+                        stop: self.stop.clone(),
+                    });
+                    return Ok(());
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound && !self.is_stopped() => {
+                    thread::sleep(self.poll_interval);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Read for EgzFollower {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.followed.read(buf) {
+                Ok(n) => return Ok(n),
+                Err(e) if is_restart(&e) => self.reopen()?,
+                // A gzip member is cut off by stopping.
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof && self.is_stopped() => {
+                    return Ok(0)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{ErrorKind, Read, Write};
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::Duration;
+
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+
+    use super::EgzFollower;
+    use crate::EgzReaderBuilder;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("egzreader-{}-{}", process::id(), name))
+    }
+
+    fn append(path: &PathBuf, data: &[u8]) {
+        let mut f = OpenOptions::new().append(true).open(path).unwrap();
+        f.write_all(data).unwrap();
+    }
+
+    fn read_n(f: &mut EgzFollower, n: usize) -> String {
+        let mut buf = vec![0; n];
+        f.read_exact(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn follow_gz_members() {
+        let path = temp_path("follow-gz");
+        fs::write(&path, gzip(b"Hello, ")).unwrap();
+
+        let mut f = EgzFollower::new(&path, Duration::from_millis(5)).unwrap();
+        assert_eq!(read_n(&mut f, 7), "Hello, ");
+
+        append(&path, &gzip(b"World!"));
+        assert_eq!(read_n(&mut f, 6), "World!");
+
+        f.stop_handle().stop();
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "");
+
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn follow_stopped() {
+        // A member cut off by stopping ends the stream.
+        let path = temp_path("follow-stopped");
+        let data = gzip("Hello, World!".repeat(100).as_bytes());
+        fs::write(&path, &data[..data.len() - 8]).unwrap();
+        let mut f = EgzFollower::new(&path, Duration::from_millis(5)).unwrap();
+        f.stop_handle().stop();
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello, World!".repeat(100));
+
+        // Other errors are returned even after stopping.
+        let mut corrupt = data.clone();
+        let len = corrupt.len();
+        corrupt[len - 8] ^= 0xff;
+        fs::write(&path, &corrupt).unwrap();
+        let mut f = EgzFollower::new(&path, Duration::from_millis(5)).unwrap();
+        f.stop_handle().stop();
+        let e = f.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_ne!(e.kind(), ErrorKind::UnexpectedEof);
+
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn follow_options() {
+        let path = temp_path("follow-options");
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello, World!").unwrap();
+        fs::write(&path, e.finish().unwrap()).unwrap();
+
+        let mut f = EgzReaderBuilder::new()
+            .zlib(true)
+            .follow(&path, Duration::from_millis(5))
+            .unwrap();
+        f.stop_handle().stop();
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello, World!");
+
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn follow_truncated() {
+        let path = temp_path("follow-truncated");
+        let text = "Hello, World!".repeat(4);
+        fs::write(&path, &text).unwrap();
+
+        let mut f = EgzFollower::new(&path, Duration::from_millis(5)).unwrap();
+        assert_eq!(read_n(&mut f, text.len()), text);
+
+        File::create(&path).unwrap();
+        append(&path, &gzip(b"Good bye!"));
+        assert_eq!(read_n(&mut f, 9), "Good bye!");
+        assert_eq!(f.restarts(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+    #[cfg(unix)]
+    #[test]
+    fn follow_rotated() {
+        let path = temp_path("follow-rotated");
+        let rotated = temp_path("follow-rotated.1");
+        fs::write(&path, gzip(b"Hello, World!")).unwrap();
+
+        let mut f = EgzFollower::new(&path, Duration::from_millis(5)).unwrap();
+        assert_eq!(read_n(&mut f, 13), "Hello, World!");
+
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, "Good bye, World!").unwrap();
+        assert_eq!(read_n(&mut f, 16), "Good bye, World!");
+        assert_eq!(f.restarts(), 1);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated).unwrap();
+    }
+}
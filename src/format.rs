@@ -0,0 +1,83 @@
+use std::io::{Read, Result, Seek, SeekFrom};
+
+use crate::EgzReader;
+
+/// Format of a stream, detected by [EgzReader](crate::EgzReader).
+///
+/// Returned by [EgzReader::format](crate::EgzReader::format).
+/// Variants may be added with new formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Format {
+    /// The stream has no bytes.
+    Empty,
+    /// The stream is read as it is.
+    Raw,
+    /// gzip, including multi-member streams when
+    /// [EgzReaderBuilder::multi_member](crate::EgzReaderBuilder::multi_member) is enabled.
+    Gzip,
+    /// Blocked gzip (BGZF) used by BAM and tabix.
+    ///
+    /// Reported for any gzip stream whose first header has a `BC` subfield of 2 bytes,
+    /// which is decoded block by block.
+    Bgzf,
+    /// Base64-encoded gzip, enabled by [EgzReaderBuilder::base64](crate::EgzReaderBuilder::base64).
+    Base64Gzip,
+    /// zlib (RFC 1950), enabled by [EgzReaderBuilder::zlib](crate::EgzReaderBuilder::zlib).
+    Zlib,
+    /// Raw deflate, enabled by [EgzReaderBuilder::deflate](crate::EgzReaderBuilder::deflate).
+    Deflate,
+    /// `.Z` of the Unix `compress` command, with the `lzw` feature.
+    #[cfg(feature = "lzw")]
+    Lzw,
+    /// The first entry of a ZIP archive, with the `zip` feature.
+    #[cfg(feature = "zip")]
+    Zip,
+    /// A format of [FormatRegistry](crate::FormatRegistry).
+    Custom,
+}
+
+/// Detects the format of `r` with the default options, and moves `r` back to where it was.
+///
+/// This classifies a file without constructing a reader to decode it.
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use egzreader::{detect_format, Format};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut f = File::open("data.gz")?;
+/// if detect_format(&mut f)? == Format::Gzip {
+///     println!("gzip");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn detect_format<R: Read + Seek + ?Sized>(r: &mut R) -> Result<Format> {
+    let start = r.stream_position()?;
+    let format = EgzReader::new(&mut *r).detect();
+    r.seek(SeekFrom::Start(start))?;
+    format
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek};
+
+    use super::{detect_format, Format};
+    use crate::test_support::{HELLO, HELLO_GZ};
+
+    #[test]
+    fn detect_and_rewind() {
+        let mut r = Cursor::new(HELLO_GZ);
+        assert_eq!(detect_format(&mut r).unwrap(), Format::Gzip);
+        assert_eq!(r.stream_position().unwrap(), 0);
+
+        let mut r = Cursor::new(HELLO);
+        r.set_position(2);
+        assert_eq!(detect_format(&mut r).unwrap(), Format::Raw);
+        assert_eq!(r.stream_position().unwrap(), 2);
+        assert_eq!(detect_format(&mut Cursor::new(b"")).unwrap(), Format::Empty);
+    }
+}
@@ -0,0 +1,385 @@
+//! Standalone parser of gzip headers.
+//!
+//! The header can be inspected without constructing a reader,
+//! e.g. to find the extra subfields of BGZF or dictzip.
+//!
+//! # Examples
+//! ```
+//! use egzreader::header::{parse_gz_header, HeaderError};
+//!
+//! let data = [0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0x00, 0x03, 0x03, 0x00];
+//! let header = parse_gz_header(&data).unwrap();
+//! assert_eq!(header.len(), 10);
+//! assert_eq!(header.os(), 3);
+//!
+//! assert!(matches!(parse_gz_header(&data[..4]), Err(HeaderError::NeedMoreBytes)));
+//! ```
+use flate2::Crc;
+use std::error;
+use std::fmt;
+
+// Flags in the gzip header.
+const FTEXT: u8 = 1;
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+const RESERVED: u8 = 0xe0;
+
+/// An error of [parse_gz_header](parse_gz_header).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The input ends in the middle of the header.
+    NeedMoreBytes,
+    /// The input does not begin with a gzip header.
+    NotGzip,
+    /// The header CRC (FHCRC) does not match.
+    BadCrc,
+}
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HeaderError::NeedMoreBytes => "incomplete gzip header",
+            HeaderError::NotGzip => "not a gzip header",
+            HeaderError::BadCrc => "corrupt gzip header",
+        })
+    }
+}
+impl error::Error for HeaderError {}
+
+/// A gzip header parsed by [parse_gz_header](parse_gz_header), borrowing the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GzHeaderInfo<'a> {
+    len: usize,
+    flags: u8,
+    mtime: u32,
+    xfl: u8,
+    os: u8,
+    extra: Option<&'a [u8]>,
+    filename: Option<&'a [u8]>,
+    comment: Option<&'a [u8]>,
+}
+
+impl<'a> GzHeaderInfo<'a> {
+    /// Length of the header in bytes, where the deflate stream begins.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether FTEXT is set, telling the content is probably text.
+    pub fn is_text(&self) -> bool {
+        self.flags & FTEXT != 0
+    }
+
+    /// Whether the header has its CRC (FHCRC), which has been verified.
+    pub fn has_crc(&self) -> bool {
+        self.flags & FHCRC != 0
+    }
+
+    /// Modification time of the original file in Unix time, or 0 if not available.
+    pub fn mtime(&self) -> u32 {
+        self.mtime
+    }
+
+    /// Extra flags (XFL).
+    pub fn xfl(&self) -> u8 {
+        self.xfl
+    }
+
+    /// Operating system on which the stream was created.
+    pub fn os(&self) -> u8 {
+        self.os
+    }
+
+    /// The extra field (FEXTRA).
+    pub fn extra(&self) -> Option<&'a [u8]> {
+        self.extra
+    }
+
+    /// Iterates over the subfields of the extra field.
+    ///
+    /// The iteration stops at a malformed subfield.
+    pub fn subfields(&self) -> Subfields<'a> {
+        Subfields(self.extra.unwrap_or(&[]))
+    }
+
+    /// Finds the first subfield with `id` in the extra field.
+    pub fn subfield(&self, id: [u8; 2]) -> Option<&'a [u8]> {
+        self.subfields().find(|s| s.id() == id).map(|s| s.data())
+    }
+
+    /// The original file name (FNAME), without the terminating zero.
+    pub fn filename(&self) -> Option<&'a [u8]> {
+        self.filename
+    }
+
+    /// The comment (FCOMMENT), without the terminating zero.
+    pub fn comment(&self) -> Option<&'a [u8]> {
+        self.comment
+    }
+}
+
+/// A gzip header owned by a reader, given by [EgzReader::gz_header](crate::EgzReader::gz_header).
+///
+/// This has the fields of [GzHeaderInfo](GzHeaderInfo) which describe the original file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OwnedGzHeader {
+    mtime: u32,
+    os: u8,
+    extra: Option<Vec<u8>>,
+    filename: Option<Vec<u8>>,
+    comment: Option<Vec<u8>>,
+}
+
+impl OwnedGzHeader {
+    /// Modification time of the original file in Unix time, or 0 if not available.
+    pub fn mtime(&self) -> u32 {
+        self.mtime
+    }
+
+    /// Operating system on which the stream was created.
+    pub fn os(&self) -> u8 {
+        self.os
+    }
+
+    /// The extra field (FEXTRA).
+    pub fn extra(&self) -> Option<&[u8]> {
+        self.extra.as_deref()
+    }
+
+    /// Iterates over the subfields of the extra field.
+    ///
+    /// The iteration stops at a malformed subfield.
+    pub fn subfields(&self) -> Subfields<'_> {
+        Subfields(self.extra().unwrap_or(&[]))
+    }
+
+    /// Finds the first subfield with `id` in the extra field.
+    pub fn subfield(&self, id: [u8; 2]) -> Option<&[u8]> {
+        self.subfields().find(|s| s.id() == id).map(|s| s.data())
+    }
+
+    /// The original file name (FNAME), without the terminating zero.
+    pub fn filename(&self) -> Option<&[u8]> {
+        self.filename.as_deref()
+    }
+
+    /// The comment (FCOMMENT), without the terminating zero.
+    pub fn comment(&self) -> Option<&[u8]> {
+        self.comment.as_deref()
+    }
+}
+
+impl From<&GzHeaderInfo<'_>> for OwnedGzHeader {
+    fn from(h: &GzHeaderInfo<'_>) -> OwnedGzHeader {
+        OwnedGzHeader {
+            mtime: h.mtime,
+            os: h.os,
+            extra: h.extra.map(<[u8]>::to_vec),
+            filename: h.filename.map(<[u8]>::to_vec),
+            comment: h.comment.map(<[u8]>::to_vec),
+        }
+    }
+}
+
+// Header parsed by flate2
+impl From<&flate2::GzHeader> for OwnedGzHeader {
+    fn from(h: &flate2::GzHeader) -> OwnedGzHeader {
+        OwnedGzHeader {
+            mtime: h.mtime(),
+            os: h.operating_system(),
+            extra: h.extra().map(<[u8]>::to_vec),
+            filename: h.filename().map(<[u8]>::to_vec),
+            comment: h.comment().map(<[u8]>::to_vec),
+        }
+    }
+}
+
+/// A subfield of the gzip extra field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subfield<'a> {
+    id: [u8; 2],
+    data: &'a [u8],
+}
+
+impl<'a> Subfield<'a> {
+    /// Subfield ID (SI1 and SI2), e.g. `*b"BC"` for BGZF.
+    pub fn id(&self) -> [u8; 2] {
+        self.id
+    }
+
+    /// Data of the subfield.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// An iterator over the subfields, created by [GzHeaderInfo::subfields](GzHeaderInfo::subfields)
+/// or [OwnedGzHeader::subfields](OwnedGzHeader::subfields).
+#[derive(Debug, Clone)]
+pub struct Subfields<'a>(&'a [u8]);
+
+impl<'a> Iterator for Subfields<'a> {
+    type Item = Subfield<'a>;
+
+    fn next(&mut self) -> Option<Subfield<'a>> {
+        if self.0.len() < 4 {
+            return None;
+        }
+        let len = u16::from_le_bytes([self.0[2], self.0[3]]) as usize;
+        let data = match self.0.get(4..4 + len) {
+            Some(data) => data,
+            None => {
+                self.0 = &[];
+                return None;
+            }
+        };
+        let id = [self.0[0], self.0[1]];
+        self.0 = &self.0[4 + len..];
+        Some(Subfield { id, data })
+    }
+}
+
+fn zero_terminated(buf: &[u8]) -> Result<&[u8], HeaderError> {
+    let end = buf
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(HeaderError::NeedMoreBytes)?;
+    Ok(&buf[..end])
+}
+
+/// Parses the gzip header at the beginning of `buf`.
+///
+/// Returns [NeedMoreBytes](HeaderError::NeedMoreBytes) when `buf` ends in the middle of the header,
+/// in which case it should be called again with more bytes.
+/// The header CRC is verified if present.
+pub fn parse_gz_header(buf: &[u8]) -> Result<GzHeaderInfo<'_>, HeaderError> {
+    let magic_len = buf.len().min(3);
+    if buf[..magic_len] != [0x1f, 0x8b, 0x08][..magic_len] {
+        return Err(HeaderError::NotGzip);
+    }
+    if buf.len() < 10 {
+        return Err(HeaderError::NeedMoreBytes);
+    }
+
+    let flags = buf[3];
+    if flags & RESERVED != 0 {
+        return Err(HeaderError::NotGzip);
+    }
+    let mut pos = 10;
+
+    let extra = if flags & FEXTRA != 0 {
+        let xlen = buf.get(pos..pos + 2).ok_or(HeaderError::NeedMoreBytes)?;
+        let xlen = u16::from_le_bytes([xlen[0], xlen[1]]) as usize;
+        let extra = buf
+            .get(pos + 2..pos + 2 + xlen)
+            .ok_or(HeaderError::NeedMoreBytes)?;
+        pos += 2 + xlen;
+        Some(extra)
+    } else {
+        None
+    };
+    let filename = if flags & FNAME != 0 {
+        let s = zero_terminated(&buf[pos..])?;
+        pos += s.len() + 1;
+        Some(s)
+    } else {
+        None
+    };
+    let comment = if flags & FCOMMENT != 0 {
+        let s = zero_terminated(&buf[pos..])?;
+        pos += s.len() + 1;
+        Some(s)
+    } else {
+        None
+    };
+    if flags & FHCRC != 0 {
+        let sum = buf.get(pos..pos + 2).ok_or(HeaderError::NeedMoreBytes)?;
+        let mut crc = Crc::new();
+        crc.update(&buf[..pos]);
+        if crc.sum() as u16 != u16::from_le_bytes([sum[0], sum[1]]) {
+            return Err(HeaderError::BadCrc);
+        }
+        pos += 2;
+    }
+
+    Ok(GzHeaderInfo {
+        len: pos,
+        flags,
+        mtime: u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        xfl: buf[8],
+        os: buf[9],
+        extra,
+        filename,
+        comment,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{Compression, GzBuilder};
+    use std::io::Write;
+
+    use super::{parse_gz_header, HeaderError, OwnedGzHeader, Subfield};
+
+    #[test]
+    fn parse() {
+        let mut e = GzBuilder::new()
+            .filename("hello.txt")
+            .comment("greeting")
+            .extra(b"AB\x02\x00xyCD\x00\x00".to_vec())
+            .mtime(1234)
+            .write(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let data = e.finish().unwrap();
+
+        let h = parse_gz_header(&data).unwrap();
+        assert_eq!(h.filename(), Some(&b"hello.txt"[..]));
+        assert_eq!(h.comment(), Some(&b"greeting"[..]));
+        assert_eq!(h.mtime(), 1234);
+        assert_eq!(h.len(), 10 + 12 + 10 + 9);
+        assert_eq!(
+            h.subfields().collect::<Vec<_>>(),
+            [
+                Subfield {
+                    id: *b"AB",
+                    data: b"xy"
+                },
+                Subfield {
+                    id: *b"CD",
+                    data: b""
+                }
+            ]
+        );
+        assert_eq!(h.subfield(*b"CD"), Some(&b""[..]));
+        assert_eq!(h.subfield(*b"EF"), None);
+
+        let owned = OwnedGzHeader::from(&h);
+        assert_eq!(owned.filename(), h.filename());
+        assert_eq!(owned.comment(), h.comment());
+        assert_eq!(owned.mtime(), 1234);
+        assert_eq!(owned.subfield(*b"AB"), Some(&b"xy"[..]));
+        let flate2 = flate2::read::GzDecoder::new(&data[..]);
+        assert_eq!(OwnedGzHeader::from(flate2.header().unwrap()), owned);
+
+        for n in 0..h.len() {
+            assert_eq!(parse_gz_header(&data[..n]), Err(HeaderError::NeedMoreBytes));
+        }
+        assert_eq!(parse_gz_header(b"Hello!"), Err(HeaderError::NotGzip));
+    }
+    #[test]
+    fn parse_crc() {
+        let mut data = vec![0x1f, 0x8b, 0x08, 0x02, 0, 0, 0, 0, 0, 0xff];
+        let mut crc = flate2::Crc::new();
+        crc.update(&data);
+        data.extend_from_slice(&(crc.sum() as u16).to_le_bytes());
+
+        let h = parse_gz_header(&data).unwrap();
+        assert!(h.has_crc());
+        assert_eq!(h.len(), 12);
+
+        data[11] ^= 0xff;
+        assert_eq!(parse_gz_header(&data), Err(HeaderError::BadCrc));
+    }
+}
@@ -0,0 +1,56 @@
+use crate::Format;
+
+// Format implied by the Content-Encoding and Content-Type headers, if known.
+//
+// The last coding of Content-Encoding is the one applied last, to be decoded first.
+pub(crate) fn format_of(
+    content_encoding: Option<&str>,
+    content_type: Option<&str>,
+) -> Option<Format> {
+    let coding = content_encoding
+        .and_then(|e| e.rsplit(',').map(str::trim).find(|c| !c.is_empty()))
+        .map(str::to_ascii_lowercase);
+    match coding.as_deref() {
+        Some("gzip") | Some("x-gzip") => return Some(Format::Gzip),
+        Some("deflate") => return Some(Format::Deflate),
+        Some("identity") | None => {}
+        // Codings which are not supported, e.g. br and zstd
+        Some(_) => return None,
+    }
+
+    // Parameters, e.g. charset, are ignored.
+    let mime = content_type?.split(';').next()?.trim().to_ascii_lowercase();
+    match mime.as_str() {
+        "application/gzip" | "application/x-gzip" | "application/gzip-compressed" => {
+            Some(Format::Gzip)
+        }
+        "application/zlib" => Some(Format::Zlib),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_of;
+    use crate::Format;
+
+    #[test]
+    fn http_hints() {
+        assert_eq!(format_of(Some("gzip"), None), Some(Format::Gzip));
+        assert_eq!(
+            format_of(Some("identity, X-GZIP"), None),
+            Some(Format::Gzip)
+        );
+        assert_eq!(
+            format_of(Some("deflate"), Some("text/plain")),
+            Some(Format::Deflate)
+        );
+        assert_eq!(format_of(Some("br"), Some("application/gzip")), None);
+        assert_eq!(
+            format_of(None, Some("application/x-gzip; charset=binary")),
+            Some(Format::Gzip)
+        );
+        assert_eq!(format_of(Some("identity"), Some("text/html")), None);
+        assert_eq!(format_of(None, None), None);
+    }
+}
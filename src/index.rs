@@ -0,0 +1,863 @@
+use flate2::bufread::{GzDecoder, MultiGzDecoder};
+use flate2::Crc;
+use miniz_oxide::inflate::core::inflate_flags::{
+    TINFL_FLAG_HAS_MORE_INPUT, TINFL_FLAG_STOP_ON_BLOCK_BOUNDARY,
+};
+use miniz_oxide::inflate::core::{decompress, BlockBoundaryState, DecompressorOxide};
+use miniz_oxide::inflate::TINFLStatus;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{
+    self, BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write,
+};
+use std::mem;
+use std::ops::Range;
+
+use crate::header::{parse_gz_header, HeaderError};
+use crate::trailing::is_member;
+
+// Size of the deflate window, kept at each checkpoint.
+const WINDOW_SIZE: usize = 32 * 1024;
+
+// Decoded bytes between the access points of MemberIndex::build, as zran of zlib.
+const DEFAULT_SPAN: u64 = 1024 * 1024;
+
+/// A position where decoding can start, recorded in a [MemberIndex](MemberIndex):
+/// the start of a gzip member, or a checkpoint inside a member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessPoint {
+    compressed: u64,
+    decoded: u64,
+}
+
+impl AccessPoint {
+    /// Offset in the compressed stream, i.e. the position in the underlying reader.
+    ///
+    /// The block after a checkpoint may begin in the middle of the byte before the offset,
+    /// whose bits are recorded in the index.
+    pub fn compressed_offset(&self) -> u64 {
+        self.compressed
+    }
+
+    /// Offset in the decoded stream.
+    pub fn decoded_offset(&self) -> u64 {
+        self.decoded
+    }
+}
+
+/// An index of a seekable gzip file for random access by decoded offsets, as zran of zlib.
+///
+/// The index is built by scanning the file once, and is given to
+/// [IndexedReader](IndexedReader) to read from arbitrary decoded offsets.
+///
+/// The access points are at the starts of the gzip members, and at checkpoints between
+/// the deflate blocks inside the members, every [span](MemberIndex::build_with_span)
+/// decoded bytes. A checkpoint keeps the last 32 KiB decoded before it, the window which
+/// the following blocks refer to, so decoding resumes there without decoding the member
+/// from its start. Reading from an offset decodes from the nearest access point before it,
+/// so even a large file of a single member is accessed fast.
+///
+/// Bytes following the last member which do not begin another member, e.g. zero padding
+/// of a tape archive, are ignored as [TrailingData::Ignore](crate::TrailingData::Ignore) does.
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::prelude::*;
+/// use std::io::SeekFrom;
+/// use egzreader::{MemberIndex, IndexedReader};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut f = File::open("archive.gz")?;
+/// let index = MemberIndex::build(&mut f)?;
+///
+/// let mut r = IndexedReader::new(index, f);
+/// r.seek(SeekFrom::Start(1_000_000))?;
+/// let mut buf = vec![0; 4096];
+/// r.read_exact(&mut buf)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberIndex {
+    points: Vec<AccessPoint>,
+    checkpoints: Vec<AccessPoint>,
+    windows: Vec<Window>,
+    decoded_size: u64,
+}
+
+// State of the decoder at a checkpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Window {
+    // Bits of the byte before the checkpoint which belong to the next block
+    num_bits: u8,
+    bit_buf: u8,
+    // Bytes decoded before the checkpoint in the member, up to 32 KiB
+    data: Vec<u8>,
+}
+
+impl MemberIndex {
+    /// Builds the index of the gzip stream from the current position of `r`,
+    /// verifying every member. `r` is moved back to the position.
+    ///
+    /// The stream must begin with a gzip member.
+    /// Checkpoints are recorded every 1 MiB of decoded bytes, as zran does.
+    pub fn build<R: Read + Seek>(r: &mut R) -> Result<MemberIndex> {
+        MemberIndex::build_with_span(r, DEFAULT_SPAN)
+    }
+
+    /// Builds the index as [build](MemberIndex::build), with checkpoints every `span`
+    /// decoded bytes inside the members.
+    ///
+    /// Each checkpoint takes 32 KiB of memory, and a shorter span decodes fewer bytes
+    /// to read from an offset. `u64::MAX` records no checkpoints, e.g. for BGZF files,
+    /// whose members are small.
+    pub fn build_with_span<R: Read + Seek>(r: &mut R, span: u64) -> Result<MemberIndex> {
+        let start = r.stream_position()?;
+        let index = MemberIndex::scan(r, Vec::new(), span);
+        r.seek(SeekFrom::Start(start))?;
+        index
+    }
+
+    // Adds the access points of the members from the current position of `r`,
+    // which is the last of `points` if any.
+    fn scan<R: Read + Seek>(
+        r: &mut R,
+        mut points: Vec<AccessPoint>,
+        span: u64,
+    ) -> Result<MemberIndex> {
+        let mut input = BufReader::new(r);
+        let decoded_size = points.pop().map_or(0, |p| p.decoded);
+        let mut index = MemberIndex {
+            points,
+            checkpoints: Vec::new(),
+            windows: Vec::new(),
+            decoded_size,
+        };
+
+        loop {
+            let rest = input.fill_buf()?;
+            if rest.is_empty() || (!index.points.is_empty() && !is_member(rest)) {
+                break;
+            }
+            index.points.push(AccessPoint {
+                compressed: input.stream_position()?,
+                decoded: index.decoded_size,
+            });
+            index.scan_member(&mut input, span)?;
+        }
+        Ok(index)
+    }
+
+    // Decodes the member at the current position of `input` and verifies it,
+    // adding a checkpoint at the first block boundary after every `span` decoded bytes.
+    fn scan_member<B: BufRead + Seek>(&mut self, input: &mut B, span: u64) -> Result<()> {
+        skip_header(input)?;
+        let start = self.decoded_size;
+        let mut last = start;
+        let mut d = Box::new(DecompressorOxide::new());
+        let mut ring = vec![0; WINDOW_SIZE];
+        let mut pos = 0;
+        let mut crc = Crc::new();
+
+        loop {
+            let buf = input.fill_buf()?;
+            let mut flags = TINFL_FLAG_STOP_ON_BLOCK_BOUNDARY;
+            if !buf.is_empty() {
+                flags |= TINFL_FLAG_HAS_MORE_INPUT;
+            }
+            let (status, consumed, n) = decompress(&mut d, buf, &mut ring, pos, flags);
+            input.consume(consumed);
+            crc.update(&ring[pos..pos + n]);
+            pos = (pos + n) % WINDOW_SIZE;
+            self.decoded_size += n as u64;
+
+            match status {
+                TINFLStatus::Done => break,
+                TINFLStatus::BlockBoundary if span <= self.decoded_size - last => {
+                    let state = d.block_boundary_state().unwrap();
+                    // The ring has wrapped around once 32 KiB are decoded.
+                    let data = if self.decoded_size - start < WINDOW_SIZE as u64 {
+                        ring[..pos].to_vec()
+                    } else {
+                        [&ring[pos..], &ring[..pos]].concat()
+                    };
+                    self.checkpoints.push(AccessPoint {
+                        compressed: input.stream_position()?,
+                        decoded: self.decoded_size,
+                    });
+                    self.windows.push(Window {
+                        num_bits: state.num_bits,
+                        bit_buf: state.bit_buf,
+                        data,
+                    });
+                    last = self.decoded_size;
+                }
+                TINFLStatus::BlockBoundary
+                | TINFLStatus::NeedsMoreInput
+                | TINFLStatus::HasMoreOutput => {}
+                TINFLStatus::FailedCannotMakeProgress => {
+                    return Err(ErrorKind::UnexpectedEof.into())
+                }
+                _ => return Err(Error::new(ErrorKind::InvalidData, "corrupt deflate stream")),
+            }
+        }
+
+        let mut trailer = [0; 8];
+        input.read_exact(&mut trailer)?;
+        let sum = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let amount = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if sum != crc.sum() || amount != crc.amount() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "corrupt gzip stream does not have a matching checksum",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads an index written by [write_gzi](MemberIndex::write_gzi) or by `bgzip -i`
+    /// for the gzip file `r`.
+    ///
+    /// The decoded size is not recorded in the `.gzi` format, so the members from the last
+    /// access point of `r` are verified to get it, and checkpoints are recorded in them
+    /// as [build](MemberIndex::build) does. `r` is moved back to its position.
+    pub fn read_gzi<I: Read, R: Read + Seek>(gzi: I, r: &mut R) -> Result<MemberIndex> {
+        let mut gzi = BufReader::new(gzi);
+        let mut u64_le = || -> Result<u64> {
+            let mut buf = [0; 8];
+            gzi.read_exact(&mut buf)?;
+            Ok(u64::from_le_bytes(buf))
+        };
+
+        let len = u64_le()?;
+        // The start of the file is implied.
+        let mut points = vec![AccessPoint {
+            compressed: 0,
+            decoded: 0,
+        }];
+        for _ in 0..len {
+            let p = AccessPoint {
+                compressed: u64_le()?,
+                decoded: u64_le()?,
+            };
+            let last = points[points.len() - 1];
+            if p.compressed < last.compressed || p.decoded < last.decoded {
+                return Err(Error::new(ErrorKind::InvalidData, "unordered gzi entries"));
+            }
+            if p != last {
+                points.push(p);
+            }
+        }
+
+        let start = r.stream_position()?;
+        let last = points[points.len() - 1];
+        let index = r
+            .seek(SeekFrom::Start(last.compressed))
+            .and_then(|_| MemberIndex::scan(r, points, DEFAULT_SPAN));
+        r.seek(SeekFrom::Start(start))?;
+        index
+    }
+
+    /// Writes the index in the `.gzi` format of `bgzip -i`:
+    /// the number of entries and the pairs of the compressed and the decoded offsets,
+    /// in 64-bit little endian, without the entry of the start of the file.
+    ///
+    /// The offsets are of the positions in the file, so the index should be built
+    /// from the start of the file to be read by other tools.
+    /// The checkpoints inside the members are not written, as the format has no windows.
+    pub fn write_gzi<W: Write>(&self, w: W) -> Result<()> {
+        let mut w = BufWriter::new(w);
+        let start = AccessPoint {
+            compressed: 0,
+            decoded: 0,
+        };
+        let points: Vec<_> = self.points.iter().filter(|&&p| p != start).collect();
+        w.write_all(&(points.len() as u64).to_le_bytes())?;
+        for p in points {
+            w.write_all(&p.compressed.to_le_bytes())?;
+            w.write_all(&p.decoded.to_le_bytes())?;
+        }
+        w.flush()
+    }
+
+    /// Returns the access points at the starts of the members in order of the offsets.
+    pub fn points(&self) -> &[AccessPoint] {
+        &self.points
+    }
+
+    /// Returns the checkpoints inside the members in order of the offsets.
+    pub fn checkpoints(&self) -> &[AccessPoint] {
+        &self.checkpoints
+    }
+
+    /// Returns the total size of the decoded stream.
+    pub fn decoded_size(&self) -> u64 {
+        self.decoded_size
+    }
+
+    // Index of the access point to decode `pos` from.
+    fn point_of(&self, pos: u64) -> Option<usize> {
+        self.points
+            .partition_point(|p| p.decoded <= pos)
+            .checked_sub(1)
+    }
+
+    // Index of the last checkpoint before `pos` in the member of the access point.
+    fn checkpoint_of(&self, pos: u64, point: usize) -> Option<usize> {
+        let start = self.points[point].decoded;
+        let i = self
+            .checkpoints
+            .partition_point(|p| p.decoded <= pos)
+            .checked_sub(1)?;
+        if start < self.checkpoints[i].decoded {
+            Some(i)
+        } else {
+            None
+        }
+    }
+}
+
+// Skips the gzip header at the current position of `input`.
+fn skip_header<B: BufRead>(input: &mut B) -> Result<()> {
+    let mut header = Vec::new();
+    loop {
+        let buf = input.fill_buf()?;
+        if buf.is_empty() {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let len = header.len();
+        header.extend_from_slice(buf);
+        match parse_gz_header(&header) {
+            Ok(h) => {
+                input.consume(h.len() - len);
+                return Ok(());
+            }
+            Err(HeaderError::NeedMoreBytes) => input.consume(header.len() - len),
+            Err(e) => return Err(Error::new(ErrorKind::InvalidData, e)),
+        }
+    }
+}
+
+// Decoder of a member from a checkpoint, ending at the end of its deflate stream.
+// The trailer is not verified, as the bytes before the checkpoint are not decoded.
+struct Resumed<B: BufRead> {
+    input: B,
+    d: Box<DecompressorOxide>,
+    // Decoded bytes following the window, wrapping around
+    ring: Vec<u8>,
+    pos: usize,
+    unread: Range<usize>,
+    done: bool,
+}
+
+impl<B: BufRead> Resumed<B> {
+    // `input` is at the compressed offset of the checkpoint.
+    fn new(input: B, window: &Window) -> Resumed<B> {
+        let state = BlockBoundaryState {
+            num_bits: window.num_bits,
+            bit_buf: window.bit_buf,
+            ..BlockBoundaryState::default()
+        };
+        let mut ring = vec![0; WINDOW_SIZE];
+        ring[..window.data.len()].copy_from_slice(&window.data);
+        let pos = window.data.len() % WINDOW_SIZE;
+        Resumed {
+            input,
+            d: Box::new(DecompressorOxide::from_block_boundary_state(&state)),
+            ring,
+            pos,
+            unread: pos..pos,
+            done: false,
+        }
+    }
+
+    fn into_inner(self) -> B {
+        self.input
+    }
+}
+
+impl<B: BufRead> Read for Resumed<B> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            if !self.unread.is_empty() {
+                let n = (&self.ring[self.unread.clone()]).read(buf)?;
+                self.unread.start += n;
+                return Ok(n);
+            }
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+
+            let input = self.input.fill_buf()?;
+            let flags = if input.is_empty() {
+                0
+            } else {
+                TINFL_FLAG_HAS_MORE_INPUT
+            };
+            let (status, consumed, n) =
+                decompress(&mut self.d, input, &mut self.ring, self.pos, flags);
+            self.input.consume(consumed);
+            self.unread = self.pos..self.pos + n;
+            self.pos = (self.pos + n) % WINDOW_SIZE;
+
+            match status {
+                TINFLStatus::Done => self.done = true,
+                TINFLStatus::NeedsMoreInput | TINFLStatus::HasMoreOutput => {}
+                TINFLStatus::FailedCannotMakeProgress => {
+                    return Err(ErrorKind::UnexpectedEof.into())
+                }
+                _ => return Err(Error::new(ErrorKind::InvalidData, "corrupt deflate stream")),
+            }
+        }
+    }
+}
+
+impl<B: BufRead> fmt::Debug for Resumed<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resumed")
+            .field("pos", &self.pos)
+            .field("unread", &self.unread)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+enum State<R: Read> {
+    Idle(R),
+    Decoding(Box<MultiGzDecoder<BufReader<R>>>),
+    // Decoding the member of a checkpoint
+    Resumed(Box<Resumed<BufReader<R>>>),
+    Moving,
+}
+
+// Decoded members by their access points, the most recently used first.
+#[derive(Debug, Default)]
+struct Cache {
+    capacity: usize,
+    len: usize,
+    members: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl Cache {
+    // Moves the member to the front, returning false if not cached.
+    fn touch(&mut self, point: usize) -> bool {
+        match self.members.iter().position(|(p, _)| *p == point) {
+            Some(i) => {
+                let member = self.members.remove(i).unwrap();
+                self.members.push_front(member);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, point: usize, member: Vec<u8>) {
+        while self.capacity < self.len + member.len() {
+            match self.members.pop_back() {
+                Some((_, evicted)) => self.len -= evicted.len(),
+                None => break,
+            }
+        }
+        self.len += member.len();
+        self.members.push_front((point, member));
+    }
+}
+
+/// A reader of a gzip file seekable by decoded offsets, using a [MemberIndex](MemberIndex).
+///
+/// Decoded members can be kept in a cache of the least recently used members by
+/// [cache_capacity](IndexedReader::cache_capacity), so reading the same regions
+/// repeatedly does not decode their members again.
+#[derive(Debug)]
+pub struct IndexedReader<R: Read + Seek> {
+    index: MemberIndex,
+    state: State<R>,
+    cache: Cache,
+
+    // Decoded offsets requested by seek() and given by the decoder.
+    pos: u64,
+    decoded: u64,
+}
+
+impl<R: Read + Seek> IndexedReader<R> {
+    /// Creates a reader of the file `r` whose index is `index`.
+    pub fn new(index: MemberIndex, r: R) -> IndexedReader<R> {
+        IndexedReader {
+            index,
+            state: State::Idle(r),
+            cache: Cache::default(),
+            pos: 0,
+            decoded: 0,
+        }
+    }
+
+    /// Caches decoded members up to `bytes` in total. Disabled, i.e. 0, by default.
+    ///
+    /// Members decoded to more than `bytes` are not cached, and are decoded as they are read.
+    pub fn cache_capacity(mut self, bytes: usize) -> IndexedReader<R> {
+        self.cache.capacity = bytes;
+        self
+    }
+
+    /// Returns the index.
+    pub fn index(&self) -> &MemberIndex {
+        &self.index
+    }
+
+    // Decoded size of the member at the access point.
+    fn member_len(&self, point: usize) -> u64 {
+        let end = match self.index.points.get(point + 1) {
+            Some(next) => next.decoded,
+            None => self.index.decoded_size,
+        };
+        end - self.index.points[point].decoded
+    }
+
+    fn take_reader(&mut self) -> R {
+        match mem::replace(&mut self.state, State::Moving) {
+            State::Idle(r) => r,
+            State::Decoding(d) => d.into_inner().into_inner(),
+            State::Resumed(d) => d.into_inner().into_inner(),
+            State::Moving => unreachable!(),
+        }
+    }
+
+    // Puts the member at the access point to the front of the cache.
+    fn load_cached(&mut self, point: usize) -> Result<()> {
+        if self.cache.touch(point) {
+            return Ok(());
+        }
+        let mut input = BufReader::new(self.take_reader());
+        let start = self.index.points[point];
+        let mut member = Vec::with_capacity(self.member_len(point) as usize);
+        let result = input.seek(SeekFrom::Start(start.compressed)).and_then(|_| {
+            let mut d = GzDecoder::new(&mut input);
+            d.read_to_end(&mut member)
+        });
+        self.state = State::Idle(input.into_inner());
+        result?;
+        if (member.len() as u64) < self.member_len(point) {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        self.cache.insert(point, member);
+        Ok(())
+    }
+
+    // Starts decoding at the access point.
+    fn reposition(&mut self, point: usize) -> Result<()> {
+        let mut input = BufReader::new(self.take_reader());
+        let start = self.index.points[point];
+        let sought = input.seek(SeekFrom::Start(start.compressed));
+        self.state = match sought {
+            Ok(_) => State::Decoding(Box::new(MultiGzDecoder::new(input))),
+            Err(_) => State::Idle(input.into_inner()),
+        };
+        sought?;
+        self.decoded = start.decoded;
+        Ok(())
+    }
+
+    // Starts decoding at the checkpoint.
+    fn resume(&mut self, checkpoint: usize) -> Result<()> {
+        let mut input = BufReader::new(self.take_reader());
+        let start = self.index.checkpoints[checkpoint];
+        let sought = input.seek(SeekFrom::Start(start.compressed));
+        self.state = match sought {
+            Ok(_) => {
+                let window = &self.index.windows[checkpoint];
+                State::Resumed(Box::new(Resumed::new(input, window)))
+            }
+            Err(_) => State::Idle(input.into_inner()),
+        };
+        sought?;
+        self.decoded = start.decoded;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for IndexedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let point = match self.index.point_of(self.pos) {
+            Some(point) if self.pos < self.index.decoded_size => point,
+            _ => return Ok(0),
+        };
+        if self.member_len(point) <= self.cache.capacity as u64 {
+            self.load_cached(point)?;
+            let member = &self.cache.members[0].1;
+            let start = (self.pos - self.index.points[point].decoded) as usize;
+            let n = (&member[start..]).read(buf)?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+
+        // Decoding starts at the last checkpoint before the position, or at its member,
+        // and continues if the position is ahead of the decoder after the start.
+        let checkpoint = self.index.checkpoint_of(self.pos, point);
+        let start = match checkpoint {
+            Some(c) => self.index.checkpoints[c].decoded,
+            None => self.index.points[point].decoded,
+        };
+        let ahead = start <= self.decoded
+            && self.decoded <= self.pos
+            && self.index.point_of(self.decoded) == Some(point);
+        if !ahead || matches!(self.state, State::Idle(_)) {
+            match checkpoint {
+                Some(c) => self.resume(c)?,
+                None => self.reposition(point)?,
+            }
+        }
+
+        loop {
+            let d: &mut dyn Read = match &mut self.state {
+                State::Decoding(d) => d,
+                State::Resumed(d) => d,
+                _ => unreachable!(),
+            };
+            let skip = self.pos - self.decoded;
+            let skipped = io::copy(&mut d.take(skip), &mut io::sink())?;
+            self.decoded += skipped;
+            let n = if skipped < skip { 0 } else { d.read(buf)? };
+            self.decoded += n as u64;
+
+            let resumed = matches!(self.state, State::Resumed(_));
+            if skipped == skip && (n != 0 || buf.is_empty() || !resumed) {
+                self.pos = self.decoded;
+                return Ok(n);
+            }
+            // The member of the checkpoint has ended, and decoding continues at the next one.
+            match self.index.point_of(self.decoded) {
+                Some(next) if resumed && self.index.points[next].decoded == self.decoded => {
+                    self.reposition(next)?
+                }
+                _ => return Err(ErrorKind::UnexpectedEof.into()),
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> Seek for IndexedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(n) => (self.index.decoded_size, n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+        match base.checked_add_signed(offset) {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use super::{IndexedReader, MemberIndex, State};
+    use crate::bgzf::tests::bgzf;
+
+    #[test]
+    fn indexed_read() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let gz = bgzf(&data, 1000);
+        let mut f = Cursor::new(&gz);
+        let index = MemberIndex::build(&mut f).unwrap();
+        assert_eq!(f.position(), 0);
+        assert_eq!(index.decoded_size(), data.len() as u64);
+        // 40 blocks and the EOF block
+        assert_eq!(index.points().len(), 41);
+        assert_eq!(index.points()[1].decoded_offset(), 1000);
+
+        let mut r = IndexedReader::new(index, f);
+        let mut buf = vec![0; 3000];
+        for &pos in &[12_345, 500, 39_000, 501] {
+            r.seek(SeekFrom::Start(pos)).unwrap();
+            r.read_exact(&mut buf[..1000]).unwrap();
+            assert_eq!(&buf[..1000], &data[pos as usize..pos as usize + 1000]);
+        }
+        r.seek(SeekFrom::End(-3000)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, &data[data.len() - 3000..]);
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+        assert!(r.seek(SeekFrom::Current(-50_000)).is_err());
+
+        // A single member
+        let mut e = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut e, &data).unwrap();
+        let mut f = Cursor::new(e.finish().unwrap());
+        let index = MemberIndex::build(&mut f).unwrap();
+        assert_eq!(index.points().len(), 1);
+        let mut r = IndexedReader::new(index, f);
+        r.seek(SeekFrom::Start(20_000)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, &data[20_000..23_000]);
+    }
+
+    // Reader counting the bytes read from it.
+    struct Counting<R> {
+        inner: R,
+        count: u64,
+    }
+    impl<R: Read> Read for Counting<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.count += n as u64;
+            Ok(n)
+        }
+    }
+    impl<R: Seek> Seek for Counting<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn checkpoint_read() {
+        // Words of a small vocabulary, compressed in many deflate blocks
+        let mut x = 1u32;
+        let mut data = Vec::new();
+        while data.len() < 2_000_000 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            data.extend_from_slice(format!("w{} ", (x >> 16) % 5000).as_bytes());
+        }
+        let mut e = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut e, &data).unwrap();
+        let gz = [e.finish().unwrap(), bgzf(b"tail", 1000)].concat();
+        let data = [&data[..], b"tail"].concat();
+
+        let mut f = Cursor::new(&gz);
+        let index = MemberIndex::build_with_span(&mut f, 100_000).unwrap();
+        assert_eq!(f.position(), 0);
+        assert_eq!(index.decoded_size(), data.len() as u64);
+        assert_eq!(index.points().len(), 3);
+        let checkpoints = index.checkpoints();
+        assert!(5 <= checkpoints.len() && checkpoints.len() <= 20);
+        for w in checkpoints.windows(2) {
+            assert!(100_000 <= w[1].decoded_offset() - w[0].decoded_offset());
+        }
+        assert!(checkpoints.iter().all(|c| c.decoded_offset() < 2_000_000));
+
+        let f = Counting {
+            inner: Cursor::new(&gz),
+            count: 0,
+        };
+        let mut r = IndexedReader::new(index.clone(), f);
+        let count = |r: &IndexedReader<Counting<_>>| match &r.state {
+            State::Idle(f) => f.count,
+            State::Decoding(d) => d.get_ref().get_ref().count,
+            State::Resumed(d) => d.input.get_ref().count,
+            State::Moving => unreachable!(),
+        };
+        let mut buf = vec![0; 3000];
+        let end = data.len() as u64 - 3000;
+        for &pos in &[1_900_000, 12_345, 1_234_567, 1_234_000, end, 550_000] {
+            let before = count(&r);
+            r.seek(SeekFrom::Start(pos)).unwrap();
+            r.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, &data[pos as usize..pos as usize + 3000]);
+            // Decoded from the checkpoint, not from the start of the member
+            assert!(count(&r) - before < gz.len() as u64 / 4);
+        }
+
+        // Reading across the end of the member of a checkpoint
+        r.seek(SeekFrom::End(-10)).unwrap();
+        let mut rest = Vec::new();
+        r.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[data.len() - 10..]);
+
+        // No checkpoints
+        let index = MemberIndex::build_with_span(&mut Cursor::new(&gz), u64::MAX).unwrap();
+        assert!(index.checkpoints().is_empty());
+        let index = MemberIndex::build(&mut Cursor::new(&gz)).unwrap();
+        assert_eq!(index.checkpoints().len(), 1);
+
+        // A corrupt member
+        let mut corrupt = gz.clone();
+        corrupt[gz.len() / 2] ^= 0xff;
+        assert!(MemberIndex::build(&mut Cursor::new(&corrupt)).is_err());
+    }
+    #[test]
+    fn cached_read() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let gz = bgzf(&data, 1000);
+        let mut f = Cursor::new(&gz);
+        let index = MemberIndex::build(&mut f).unwrap();
+        let mut r = IndexedReader::new(index, f).cache_capacity(3000);
+
+        let mut buf = vec![0; 1500];
+        for &pos in &[12_345, 500, 12_000, 38_400, 501, 12_345, 38_500] {
+            r.seek(SeekFrom::Start(pos)).unwrap();
+            r.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, &data[pos as usize..pos as usize + 1500]);
+            assert!(r.cache.len <= 3000);
+        }
+        // The members at 39_000, 38_000 and 13_000, the most recently used first
+        let points: Vec<usize> = r.cache.members.iter().map(|(p, _)| *p).collect();
+        assert_eq!(points, [39, 38, 13]);
+        r.seek(SeekFrom::End(-10)).unwrap();
+        let mut rest = Vec::new();
+        r.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[data.len() - 10..]);
+
+        // Members larger than the capacity are not cached.
+        let mut r = IndexedReader::new(r.index().clone(), Cursor::new(&gz)).cache_capacity(999);
+        r.seek(SeekFrom::Start(12_345)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, &data[12_345..13_845]);
+        assert!(r.cache.members.is_empty());
+    }
+    #[test]
+    fn gzi() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let gz = bgzf(&data, 1000);
+        let mut f = Cursor::new(&gz);
+        let index = MemberIndex::build(&mut f).unwrap();
+
+        let mut gzi = Vec::new();
+        index.write_gzi(&mut gzi).unwrap();
+        // 40 entries except the first block
+        assert_eq!(gzi.len(), 8 + 16 * 40);
+        let p = index.points()[1];
+        assert_eq!(&gzi[8..16], &p.compressed_offset().to_le_bytes());
+        assert_eq!(&gzi[16..24], &p.decoded_offset().to_le_bytes());
+        let read = MemberIndex::read_gzi(&gzi[..], &mut f).unwrap();
+        assert_eq!(read, index);
+        assert_eq!(f.position(), 0);
+
+        // The second entry at the start
+        gzi[24..40].copy_from_slice(&[0; 16]);
+        assert!(MemberIndex::read_gzi(&gzi[..], &mut f).is_err());
+        assert!(MemberIndex::read_gzi(&gzi[..20], &mut f).is_err());
+    }
+    #[test]
+    fn trailing_bytes() {
+        let data: Vec<u8> = (0..1000u32).flat_map(|i| i.to_le_bytes()).collect();
+        for tail in &[&[0u8; 512][..], b"garbage", &[0x00]] {
+            let mut gz = bgzf(&data, 1000);
+            gz.extend_from_slice(tail);
+            let mut f = Cursor::new(&gz);
+            let index = MemberIndex::build(&mut f).unwrap();
+            assert_eq!(index.decoded_size(), data.len() as u64);
+            assert_eq!(index.points().len(), 5);
+
+            let mut r = IndexedReader::new(index, f);
+            r.seek(SeekFrom::Start(3500)).unwrap();
+            let mut buf = Vec::new();
+            r.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf, &data[3500..]);
+        }
+        // Not a gzip stream
+        assert!(MemberIndex::build(&mut Cursor::new(b"garbage")).is_err());
+    }
+}
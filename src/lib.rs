@@ -35,117 +35,781 @@
 //!     Ok(())
 //! }
 //! ```
-use flate2::read::GzDecoder;
-use std::io::Read;
-use std::io::Result;
+use flate2::read::{DeflateDecoder, GzDecoder, MultiGzDecoder, ZlibDecoder};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, StdinLock};
+use std::io::{Error, ErrorKind, Result};
 use std::mem;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
+mod base64;
+mod bgzf;
+mod buffer;
+mod deflate;
+mod dictzip;
+mod error;
+mod ext;
+mod follow;
+mod format;
+pub mod header;
+mod http;
+mod index;
+mod lines;
+#[cfg(feature = "lzw")]
+mod lzw;
+mod member;
+mod nested;
+pub mod niffler;
+mod oneshot;
+mod path;
+mod push;
+mod registry;
+mod replay;
+mod retry;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+mod text;
+mod throttle;
+mod trailing;
+mod transform;
+#[cfg(feature = "zip")]
+mod zip;
+
+use base64::{is_base64_gzip, Base64Decoder};
+use bgzf::is_bgzf;
+pub use bgzf::BgzfReader;
+use buffer::AdaptiveBuffer;
+use deflate::{is_deflate, is_zlib, PROBE_LEN};
+#[cfg(feature = "zlib-dictionary")]
+use deflate::{is_zlib_with_dictionary, ZlibDictDecoder, ZlibDictionary};
+pub use dictzip::DictZipReader;
+pub use error::EgzError;
+pub use ext::EgzReadExt;
+pub use follow::{EgzFollower, StopHandle};
+pub use format::{detect_format, Format};
+use header::{parse_gz_header, HeaderError, OwnedGzHeader};
+pub use index::{AccessPoint, IndexedReader, MemberIndex};
+pub use lines::{process_lines_parallel, Split};
+#[cfg(feature = "lzw")]
+use lzw::{is_lzw, LzwDecoder};
+pub use member::{
+    concat_members, measure, split_members, DecodedSize, Member, MemberInfo, Members,
+};
+use nested::{Nested, Pipe};
+pub use oneshot::{read_to_string, read_to_vec};
+pub use path::ExtensionPolicy;
+pub use push::PushDecoder;
+use registry::{CustomDecoder, Detector};
+pub use registry::{Decoder, FormatRegistry};
+pub use replay::ReplayReader;
+pub use retry::RetryPolicy;
+use text::TextFilter;
+use throttle::Limiter;
+use trailing::GzStream;
+pub use trailing::TrailingData;
+pub use transform::Transform;
+use transform::{Pipeline, Stages};
+#[cfg(feature = "zip")]
+use zip::{is_zip, ZipReader};
+#[cfg(feature = "zip")]
+pub use zip::{ZipEntries, ZipEntry};
+
+// Options given by EgzReaderBuilder.
+#[derive(Debug, Clone, Default)]
+struct Options {
+    live: bool,
+    crlf: bool,
+    utf8: bool,
+    base64: bool,
+    compressed_rate: Option<u64>,
+    decoded_rate: Option<u64>,
+    max_decoded_size: Option<u64>,
+    retry: Option<RetryPolicy>,
+    sniff_timeout: Option<Duration>,
+    sniff_window: Option<usize>,
+    buffer_bounds: Option<(usize, usize)>,
+    deflate: bool,
+    zlib: bool,
+    #[cfg(feature = "zlib-dictionary")]
+    dictionary: Option<ZlibDictionary>,
+    #[cfg(feature = "zip")]
+    zip: bool,
+    formats: FormatRegistry,
+    detector: Option<Detector>,
+    force: Option<Format>,
+    lenient_length: bool,
+    strict: bool,
+    bgzf_eof: bool,
+    bgzf_threads: Option<usize>,
+    fallback: bool,
+    multi_member: bool,
+    trailing: TrailingData,
+    // Maximum number of levels decoded, 1 if 0.
+    depth: usize,
+    // Options of a nested level.
+    nested: bool,
+}
+
+// Number of bytes read for detection by default.
+const DETECT_LEN: usize = 11;
+// Maximum length of a gzip header validated in the strict mode.
+pub(crate) const STRICT_HEADER_LIMIT: usize = 64 * 1024;
+
+// Interval to poll a reader returning WouldBlock while sniffing.
+const SNIFF_POLL_INTERVAL: Duration = Duration::from_millis(10);
+// Number of decoded bytes buffered by fill_buf() at once, the default of BufReader.
+const FILL_LEN: usize = 8 * 1024;
+// Maximum number of decoded bytes peeked at once by peek_decoded().
+const PEEK_LIMIT: usize = 1024 * 1024;
+
+/// A reader which yields the bytes pre-read for detection, followed by the underlying reader.
+///
+/// This is the reader given to the decoder after detection,
+/// which appears in e.g. [EgzReader::into_gz_decoder](EgzReader::into_gz_decoder).
 #[derive(Debug)]
-struct RawReader<R: Read> {
-    preread: [u8; 11],
+pub struct Prefixed<R: Read> {
+    preread: Vec<u8>,
     pos: usize,
-    size: usize,
 
     reader: R,
+    limiter: Option<Limiter>,
+    retry: Option<RetryPolicy>,
+    buffer: Option<AdaptiveBuffer>,
 }
-impl<R: Read> RawReader<R> {
-    fn new(preread: [u8; 11], size: usize, r: R) -> RawReader<R> {
-        debug_assert!(size <= preread.len());
-        RawReader {
+impl<R: Read> Prefixed<R> {
+    fn new(preread: Vec<u8>, r: R) -> Prefixed<R> {
+        Prefixed {
             preread,
             pos: 0,
-            size,
             reader: r,
+            limiter: None,
+            retry: None,
+            buffer: None,
+        }
+    }
+
+    // Limits the rate of reading the underlying reader.
+    fn limit(mut self, rate: Option<u64>) -> Prefixed<R> {
+        self.limiter = rate.map(Limiter::new);
+        self
+    }
+
+    // Reads ahead from the underlying reader with a buffer of adaptive size.
+    fn buffered(mut self, bounds: Option<(usize, usize)>) -> Prefixed<R> {
+        self.buffer = bounds.map(|(min, max)| AdaptiveBuffer::new(min, max));
+        self
+    }
+
+    // Retries reading the underlying reader on transient errors.
+    fn retry(mut self, policy: Option<RetryPolicy>) -> Prefixed<R> {
+        self.retry = policy;
+        self
+    }
+
+    // Applies the options of reading the underlying reader.
+    fn with_options(self, opts: &Options) -> Prefixed<R> {
+        self.limit(opts.compressed_rate)
+            .retry(opts.retry.clone())
+            .buffered(opts.buffer_bounds)
+    }
+
+    /// Returns the pre-read bytes which have not been read yet.
+    ///
+    /// With [adaptive_buffer](EgzReaderBuilder::adaptive_buffer), the bytes read ahead
+    /// from the underlying reader are returned after the pre-read bytes have been read.
+    pub fn buffer(&self) -> &[u8] {
+        match &self.buffer {
+            Some(buffer) if self.preread.len() <= self.pos => buffer.buffer(),
+            _ => &self.preread[self.pos..],
         }
     }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Unwraps this `Prefixed`, returning the underlying reader.
+    ///
+    /// Note that the bytes in [buffer](Prefixed::buffer) are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
 }
-impl<R: Read> Read for RawReader<R> {
+impl<R: Read> Read for Prefixed<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         debug_assert!(self.pos <= self.preread.len());
 
-        if self.size <= self.pos {
-            self.reader.read(buf)
+        if self.preread.len() <= self.pos {
+            let reader = &mut self.reader;
+            let limiter = &mut self.limiter;
+            let retry = &self.retry;
+            let mut read_inner = |buf: &mut [u8]| {
+                let buf = match limiter {
+                    Some(limiter) => {
+                        let len = limiter.chunk_size(buf.len());
+                        &mut buf[..len]
+                    }
+                    None => buf,
+                };
+
+                let n = match retry {
+                    Some(policy) => policy.read(|| reader.read(buf))?,
+                    None => reader.read(buf)?,
+                };
+
+                if let Some(limiter) = limiter {
+                    limiter.consume(n);
+                }
+                Ok(n)
+            };
+
+            match &mut self.buffer {
+                Some(buffer) => buffer.read(read_inner, buf),
+                None => read_inner(buf),
+            }
         } else {
-            debug_assert!(self.pos < self.size);
-            let n = (&self.preread[self.pos..self.size]).read(buf)?;
+            let n = (&self.preread[self.pos..]).read(buf)?;
             self.pos += n;
             Ok(n)
         }
     }
 }
 
-// Wrapper for flate2::GzDecoder
+// Wrapper for flate2::GzDecoder or GzStream
 #[derive(Debug)]
-struct GzReader<R: Read>(GzDecoder<RawReader<R>>);
+struct GzReader<D: Read> {
+    decoder: D,
 
-impl<R: Read> GzReader<R> {
-    fn new(preread: [u8; 11], r: R) -> GzReader<R> {
-        GzReader(GzDecoder::new(RawReader::new(preread, 11, r)))
+    // Treat a missing trailer as the end of stream.
+    live: bool,
+    eof: bool,
+}
+
+impl<D: Read> GzReader<D> {
+    fn new(decoder: D, opts: &Options) -> GzReader<D> {
+        GzReader {
+            decoder,
+            live: opts.live,
+            eof: false,
+        }
     }
 }
-impl<R: Read> Read for GzReader<R> {
+impl<D: Read> Read for GzReader<D> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.0.read(buf)
+        if self.eof {
+            return Ok(0);
+        }
+
+        match self.decoder.read(buf) {
+            // The underlying stream was closed before the gzip trailer.
+            Err(e) if self.live && e.kind() == ErrorKind::UnexpectedEof => {
+                self.eof = true;
+                Ok(0)
+            }
+            r => r,
+        }
+    }
+}
+
+// The underlying stream is assumed as gzip when
+// - more than 10 bytes (=header size) can be read.
+// - it begins with magic number '0x1f0x8b'.
+// - its third byte, specifying compression method, would be '0x08'.
+fn is_gzip(preread: &[u8]) -> bool {
+    DETECT_LEN <= preread.len() && has_gzip_magic(preread)
+}
+
+// The magic number and the compression method only, for streams shorter than the header.
+fn has_gzip_magic(preread: &[u8]) -> bool {
+    3 <= preread.len() && preread[..2] == [0x1f, 0x8b] && preread[2] <= 0x08
+}
+
+impl Options {
+    fn sniff_window(&self) -> usize {
+        self.sniff_window.unwrap_or(DETECT_LEN)
+    }
+
+    // Decoder of the registered format, if `format` is Format::Custom.
+    fn decoder_of(&self, format: Format, preread: &[u8]) -> Option<CustomDecoder> {
+        match format {
+            Format::Custom => self.formats.decoder(preread),
+            _ => None,
+        }
+    }
+
+    // is_gzip() for the sniff window, which may be shorter than the header.
+    fn is_gzip(&self, preread: &[u8]) -> bool {
+        match self.sniff_window() {
+            len if len < DETECT_LEN => len <= preread.len() && has_gzip_magic(preread),
+            _ => is_gzip(preread),
+        }
+    }
+
+    fn is_zlib(&self, preread: &[u8]) -> bool {
+        #[cfg(feature = "zlib-dictionary")]
+        if let Some(dictionary) = &self.dictionary {
+            if self.zlib && is_zlib_with_dictionary(preread, dictionary.id) {
+                return true;
+            }
+        }
+        self.zlib && is_zlib(preread)
     }
 }
 
 #[derive(Debug)]
 enum ReaderType<R: Read> {
-    // Initial state
-    Init(R),
+    // Initial state with the bytes read for detection so far
+    Init(R, Options, Vec<u8>),
+    // Initial state after EgzReader::reset, keeping the gzip decoder to reuse.
+    // The bytes read for detection are in its Prefixed.
+    Reset(GzReader<GzDecoder<Prefixed<R>>>, Box<Options>),
 
     // Actual reader states
-    Zero,
-    Raw(RawReader<R>), // non-gzip stream
-    Gz(GzReader<R>),   // gzip stream
+    Zero(R),
+    Raw(Prefixed<R>),                                          // non-gzip stream
+    Gz(GzReader<GzDecoder<Prefixed<R>>>),                      // gzip stream
+    GzStream(GzReader<GzStream<Prefixed<R>>>),                 // gzip stream read as members
+    Bgzf(BgzfReader<Prefixed<R>>),                             // BGZF stream
+    Base64Gz(GzReader<GzDecoder<Base64Decoder<Prefixed<R>>>>), // base64-encoded gzip stream
+    Zlib(ZlibDecoder<Prefixed<R>>),                            // zlib stream
+    #[cfg(feature = "zlib-dictionary")]
+    ZlibDict(ZlibDictDecoder<Prefixed<R>>), // zlib stream with a preset dictionary
+    Deflate(DeflateDecoder<Prefixed<R>>),                      // raw deflate stream
+    Custom(CustomDecoder, Prefixed<R>),                        // format of FormatRegistry
+    Nested(Box<Nested<R>>),                                    // stream decoded twice or more
+    #[cfg(feature = "lzw")]
+    Lzw(LzwDecoder<Prefixed<R>>),   // compress(1) stream
+    #[cfg(feature = "zip")]
+    Zip(ZipReader<Prefixed<R>>),    // first entry of ZIP archive
+
+    // Decoders converted from flate2
+    Flate(GzDecoder<R>),
+    MultiFlate(MultiGzDecoder<R>),
+
+    // Transient state while the underlying reader is moved to the next state.
+    Moving,
 }
 
 impl<R: Read> ReaderType<R> {
     fn is_init(&self) -> bool {
-        matches!(self, ReaderType::Init(_))
+        matches!(self, ReaderType::Init(..) | ReaderType::Reset(..))
     }
 
-    fn make_reader(mut reader: R) -> Result<ReaderType<R>> {
-        let mut buf = [0; 11];
+    fn format(&self) -> Option<Format> {
+        Some(match self {
+            ReaderType::Init(..) | ReaderType::Reset(..) | ReaderType::Moving => return None,
+            ReaderType::Zero(_) => Format::Empty,
+            ReaderType::Raw(_) => Format::Raw,
+            ReaderType::Gz(_)
+            | ReaderType::GzStream(_)
+            | ReaderType::Flate(_)
+            | ReaderType::MultiFlate(_) => Format::Gzip,
+            ReaderType::Bgzf(_) => Format::Bgzf,
+            ReaderType::Base64Gz(_) => Format::Base64Gzip,
+            ReaderType::Zlib(_) => Format::Zlib,
+            #[cfg(feature = "zlib-dictionary")]
+            ReaderType::ZlibDict(_) => Format::Zlib,
+            ReaderType::Deflate(_) => Format::Deflate,
+            ReaderType::Custom(..) => Format::Custom,
+            ReaderType::Nested(n) => return n.format(),
+            #[cfg(feature = "lzw")]
+            ReaderType::Lzw(_) => Format::Lzw,
+            #[cfg(feature = "zip")]
+            ReaderType::Zip(_) => Format::Zip,
+        })
+    }
 
-        let n = {
-            let mut nread = 0;
-            loop {
-                let bytes = reader.read(&mut buf[nread..])?;
-                if bytes == 0 {
-                    break;
-                }
+    // Reads bytes for detection into `buf`, until it fills the sniff window or EOF.
+    fn preread(reader: &mut R, opts: &Options, buf: &mut Vec<u8>) -> Result<()> {
+        Self::preread_to(reader, opts, buf, opts.sniff_window())
+    }
 
-                nread += bytes;
-                if buf.len() <= nread {
-                    break;
+    fn preread_to(reader: &mut R, opts: &Options, buf: &mut Vec<u8>, len: usize) -> Result<()> {
+        let mut chunk = [0; 512];
+        let start = Instant::now();
+
+        while buf.len() < len {
+            let chunk = &mut chunk[..(len - buf.len()).min(512)];
+            let result = match &opts.retry {
+                Some(policy) => policy.read(|| reader.read(chunk)),
+                None => reader.read(chunk),
+            };
+            let bytes = match (result, opts.sniff_timeout) {
+                (Ok(bytes), _) => bytes,
+                (Err(e), _) if e.kind() == ErrorKind::Interrupted => continue,
+                // Wait for more bytes until the timeout.
+                (Err(e), Some(timeout))
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut =>
+                {
+                    match timeout.checked_sub(start.elapsed()) {
+                        Some(rest) => {
+                            thread::sleep(rest.min(SNIFF_POLL_INTERVAL));
+                            continue;
+                        }
+                        None if !buf.is_empty() => break,
+                        None => return Err(e),
+                    }
                 }
+                (Err(e), _) => return Err(e),
+            };
+            if bytes == 0 {
+                break;
+            }
+
+            buf.extend_from_slice(&chunk[..bytes]);
+            if opts.sniff_timeout.map_or(false, |t| t <= start.elapsed()) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn make_reader(buf: Vec<u8>, reader: R, opts: &Options) -> ReaderType<R> {
+        let format = match opts.force {
+            Some(format) => format,
+            None => Self::detect_format(&buf, opts),
+        };
+        let custom = opts.decoder_of(format, &buf);
+        Self::make_reader_as(format, custom, buf, reader, opts)
+    }
+
+    // Creates the reader of `format`, whose decoder is `custom` for Format::Custom.
+    fn make_reader_as(
+        format: Format,
+        custom: Option<CustomDecoder>,
+        buf: Vec<u8>,
+        reader: R,
+        opts: &Options,
+    ) -> ReaderType<R> {
+        if format == Format::Empty {
+            return ReaderType::Zero(reader);
+        }
+
+        let prefixed = Prefixed::new(buf, reader).with_options(opts);
+        let reader = match format {
+            Format::Empty => unreachable!(),
+            Format::Raw => ReaderType::Raw(prefixed),
+            Format::Gzip if opts.multi_member || opts.trailing != TrailingData::Ignore => {
+                let d = GzStream::new(prefixed, opts.multi_member, opts.trailing);
+                let d = match opts.trailing {
+                    TrailingData::Detect => d.detect_with(opts),
+                    _ => d,
+                };
+                ReaderType::GzStream(GzReader::new(d, opts))
             }
-            debug_assert!(nread <= buf.len());
-            nread
+            Format::Gzip => ReaderType::Gz(GzReader::new(GzDecoder::new(prefixed), opts)),
+            Format::Bgzf => {
+                let lenient = opts.live || !opts.bgzf_eof;
+                let r = BgzfReader::new(prefixed).lenient(lenient);
+                ReaderType::Bgzf(match opts.bgzf_threads {
+                    Some(threads) => r.threads(threads),
+                    None => r,
+                })
+            }
+            Format::Base64Gzip => {
+                let input = Base64Decoder::new(prefixed);
+                ReaderType::Base64Gz(GzReader::new(GzDecoder::new(input), opts))
+            }
+            Format::Zlib => Self::zlib_decoder(prefixed, opts),
+            Format::Deflate => ReaderType::Deflate(DeflateDecoder::new(prefixed)),
+            #[cfg(feature = "lzw")]
+            Format::Lzw => ReaderType::Lzw(LzwDecoder::new(prefixed)),
+            #[cfg(feature = "zip")]
+            Format::Zip => ReaderType::Zip(ZipReader::new(prefixed)),
+            Format::Custom => match custom {
+                Some(d) => ReaderType::Custom(d, prefixed),
+                None => ReaderType::Raw(prefixed),
+            },
+        };
+        match reader {
+            ReaderType::Raw(_) => reader,
+            _ if 1 < opts.depth => ReaderType::Nested(Box::new(Nested::new(reader, opts))),
+            _ => reader,
+        }
+    }
+
+    #[cfg(feature = "zlib-dictionary")]
+    fn zlib_decoder(r: Prefixed<R>, opts: &Options) -> ReaderType<R> {
+        match &opts.dictionary {
+            Some(dictionary) => ReaderType::ZlibDict(ZlibDictDecoder::new(r, dictionary.clone())),
+            None => ReaderType::Zlib(ZlibDecoder::new(r)),
+        }
+    }
+    #[cfg(not(feature = "zlib-dictionary"))]
+    fn zlib_decoder(r: Prefixed<R>, _: &Options) -> ReaderType<R> {
+        ReaderType::Zlib(ZlibDecoder::new(r))
+    }
+
+    fn detect_format(buf: &[u8], opts: &Options) -> Format {
+        if buf.is_empty() {
+            return Format::Empty;
+        }
+        let gzip = if opts.strict {
+            parse_gz_header(buf).is_ok()
+        } else {
+            opts.is_gzip(buf) || opts.lenient_length && has_gzip_magic(buf)
         };
+        // Nested levels are fed through a pipe, so only the decoders which resume
+        // after WouldBlock are used.
+        if opts.nested {
+            return if gzip && is_bgzf(buf) {
+                Format::Bgzf
+            } else if gzip {
+                Format::Gzip
+            } else if opts.is_zlib(buf) {
+                Format::Zlib
+            } else if opts.deflate && is_deflate(buf, false) {
+                Format::Deflate
+            } else {
+                Format::Raw
+            };
+        }
+        if let Some(format) = opts.detector.as_ref().and_then(|d| d.detect(buf)) {
+            return format;
+        }
 
-        if n == 0 {
-            Ok(ReaderType::Zero)
-        } else if n == 11 && buf[..2] == [0x1f, 0x8b] && buf[2] <= 0x08 {
-            // The underlying stream is assumed as gzip when
-            // - more than 10 bytes (=header size) can be read.
-            // - it begins with magic number '0x1f0x8b'.
-            // - its third byte, specifying compression method, would be '0x08'.
-            Ok(ReaderType::Gz(GzReader::new(buf, reader)))
+        #[cfg(feature = "lzw")]
+        if is_lzw(buf) {
+            return Format::Lzw;
+        }
+        #[cfg(feature = "zip")]
+        if opts.zip && is_zip(buf) {
+            return Format::Zip;
+        }
+        if gzip {
+            if is_bgzf(buf) {
+                Format::Bgzf
+            } else {
+                Format::Gzip
+            }
+        } else if opts.base64 && is_base64_gzip(buf) {
+            Format::Base64Gzip
+        } else if opts.is_zlib(buf) {
+            Format::Zlib
+        } else if opts.deflate && is_deflate(buf, false) {
+            Format::Deflate
+        } else if opts.formats.matches(buf) {
+            Format::Custom
         } else {
-            Ok(ReaderType::Raw(RawReader::new(buf, n, reader)))
+            Format::Raw
+        }
+    }
+
+    // Detects the format from `peek`, bytes available without reading the stream,
+    // or returns None if more bytes could change the result.
+    fn sniff(peek: &[u8], opts: &Options) -> Option<(Format, Option<CustomDecoder>)> {
+        let mut input = peek;
+        let mut buf = Vec::new();
+        ReaderType::<&[u8]>::preread_all(&mut input, opts, &mut buf).ok()?;
+        // Detection reaching the end of `peek` may depend on the bytes after it.
+        if input.is_empty() {
+            return None;
         }
+        let format = Self::detect_format(&buf, opts);
+        let custom = opts.decoder_of(format, &buf);
+        Some((format, custom))
     }
 
     // Determine actual type of reader.
     // This method is called at first read().
-    fn into_actual_reader(self) -> Result<Self> {
+    fn detect(&mut self) -> Result<()> {
         debug_assert!(self.is_init());
-        if let ReaderType::Init(r) = self {
-            Self::make_reader(r)
-        } else {
-            Ok(self)
+        // Bytes read before an error are kept in `buf` for the next detection.
+        match self {
+            // No bytes are read ahead when the format is given.
+            ReaderType::Init(_, opts, _) if opts.force.is_some() => {}
+            ReaderType::Init(r, opts, buf) => Self::preread_all(r, opts, buf)?,
+            ReaderType::Reset(_, opts) if opts.force.is_some() => {}
+            ReaderType::Reset(gz, opts) => {
+                let prefixed = gz.decoder.get_mut();
+                Self::preread_all(&mut prefixed.reader, opts, &mut prefixed.preread)?
+            }
+            _ => return Ok(()),
+        };
+
+        *self = match mem::replace(self, ReaderType::Moving) {
+            ReaderType::Init(r, opts, buf) => Self::make_reader(buf, r, &opts),
+            ReaderType::Reset(gz, opts) => Self::make_reader_reusing(gz, &opts),
+            _ => unreachable!(),
+        };
+        Ok(())
+    }
+
+    // make_reader() reusing the gzip decoder, left by EgzReader::reset.
+    fn make_reader_reusing(gz: GzReader<GzDecoder<Prefixed<R>>>, opts: &Options) -> ReaderType<R> {
+        let format = match opts.force {
+            Some(format) => format,
+            None => Self::detect_format(&gz.decoder.get_ref().preread, opts),
+        };
+        // The same decoder as make_reader_as() gives.
+        let plain = !opts.multi_member && opts.trailing == TrailingData::Ignore && opts.depth <= 1;
+        if format == Format::Gzip && plain {
+            return ReaderType::Gz(GzReader::new(gz.decoder, opts));
+        }
+        let prefixed = gz.decoder.into_inner();
+        let custom = opts.decoder_of(format, &prefixed.preread);
+        Self::make_reader_as(format, custom, prefixed.preread, prefixed.reader, opts)
+    }
+
+    // Init state for the bytes after the gzip stream, read with TrailingData::Detect.
+    fn detect_next(d: GzStream<Prefixed<R>>) -> ReaderType<R> {
+        let (r, opts) = d.into_next().unwrap();
+        let mut buf = r.buffer().to_vec();
+        let prefixed = r.into_inner();
+        buf.extend_from_slice(prefixed.buffer());
+        ReaderType::Init(prefixed.into_inner(), opts, buf)
+    }
+
+    // Converts Reset into Init, dropping the decoder.
+    fn into_init(self) -> ReaderType<R> {
+        match self {
+            ReaderType::Reset(gz, opts) => {
+                let prefixed = gz.decoder.into_inner();
+                ReaderType::Init(prefixed.reader, *opts, prefixed.preread)
+            }
+            reader => reader,
+        }
+    }
+
+    // Reads all bytes needed to detect the format into `buf`.
+    fn preread_all(r: &mut R, opts: &Options, buf: &mut Vec<u8>) -> Result<()> {
+        Self::preread(r, opts, buf)?;
+        // Read the whole header to validate it.
+        if opts.strict && opts.is_gzip(buf) {
+            while let Err(HeaderError::NeedMoreBytes) = parse_gz_header(buf) {
+                let len = buf.len();
+                if STRICT_HEADER_LIMIT <= len {
+                    break;
+                }
+                Self::preread_to(r, opts, buf, len + 512)?;
+                if buf.len() == len {
+                    break;
+                }
+            }
+        }
+        // Read the extra field to tell BGZF from other gzip streams:
+        // XLEN first, then the field of the length.
+        if opts.is_gzip(buf) {
+            for _ in 0..2 {
+                if let Some(len) = bgzf::probe_len(buf) {
+                    Self::preread_to(r, opts, buf, len)?;
+                }
+            }
+        }
+        // Inflate more bytes to tell deflate streams from others.
+        if opts.deflate && !(opts.is_gzip(buf) || opts.is_zlib(buf)) {
+            Self::preread_to(r, opts, buf, PROBE_LEN)?;
+        }
+        // Read the longest magic bytes of the registered formats.
+        if !opts.is_gzip(buf) {
+            Self::preread_to(r, opts, buf, opts.formats.probe_len())?;
+        }
+        Ok(())
+    }
+
+    // Header of the gzip stream, if it has been read.
+    fn gz_header(&self) -> Option<OwnedGzHeader> {
+        let header = match self {
+            ReaderType::Gz(gz) => gz.decoder.header(),
+            ReaderType::GzStream(gz) => gz.decoder.header(),
+            ReaderType::Base64Gz(gz) => gz.decoder.header(),
+            // The first block is in the bytes read for the detection until it is read.
+            ReaderType::Bgzf(b) => {
+                return b.header().cloned().or_else(|| {
+                    let preread = &b.get_ref().preread;
+                    parse_gz_header(preread).ok().map(|h| (&h).into())
+                })
+            }
+            ReaderType::Nested(n) => return n.gz_header(),
+            ReaderType::Flate(d) => d.header(),
+            ReaderType::MultiFlate(d) => d.header(),
+            _ => None,
+        };
+        header.map(OwnedGzHeader::from)
+    }
+
+    // Unwraps into the bytes read ahead and not consumed yet, and the underlying reader.
+    // Bytes buffered by the decoders are lost.
+    fn into_parts(self) -> (Vec<u8>, R) {
+        let prefixed = match self {
+            ReaderType::Init(r, _, buf) => return (buf, r),
+            ReaderType::Zero(r) => return (Vec::new(), r),
+            ReaderType::Raw(raw) | ReaderType::Custom(_, raw) => raw,
+            ReaderType::Gz(gz) | ReaderType::Reset(gz, _) => gz.decoder.into_inner(),
+            ReaderType::GzStream(gz) => gz.decoder.into_inner(),
+            ReaderType::Bgzf(b) => b.into_inner(),
+            ReaderType::Base64Gz(gz) => gz.decoder.into_inner().inner,
+            ReaderType::Zlib(d) => d.into_inner(),
+            #[cfg(feature = "zlib-dictionary")]
+            ReaderType::ZlibDict(d) => d.into_inner(),
+            ReaderType::Deflate(d) => d.into_inner(),
+            ReaderType::Nested(n) => return n.into_inner().into_parts(),
+            #[cfg(feature = "lzw")]
+            ReaderType::Lzw(d) => d.into_inner(),
+            #[cfg(feature = "zip")]
+            ReaderType::Zip(z) => z.into_inner(),
+            ReaderType::Flate(d) => return (Vec::new(), d.into_inner()),
+            ReaderType::MultiFlate(d) => return (Vec::new(), d.into_inner()),
+            ReaderType::Moving => unreachable!(),
+        };
+        (prefixed.buffer().to_vec(), prefixed.into_inner())
+    }
+
+    fn inner_ref(&self) -> &R {
+        match self {
+            ReaderType::Init(r, ..) | ReaderType::Zero(r) => r,
+            ReaderType::Raw(raw) => &raw.reader,
+            ReaderType::Gz(gz) | ReaderType::Reset(gz, _) => &gz.decoder.get_ref().reader,
+            ReaderType::GzStream(gz) => &gz.decoder.get_ref().reader,
+            ReaderType::Bgzf(b) => &b.get_ref().reader,
+            ReaderType::Base64Gz(gz) => &gz.decoder.get_ref().inner.reader,
+            ReaderType::Zlib(d) => &d.get_ref().reader,
+            #[cfg(feature = "zlib-dictionary")]
+            ReaderType::ZlibDict(d) => &d.get_ref().reader,
+            ReaderType::Deflate(d) => &d.get_ref().reader,
+            ReaderType::Custom(_, raw) => &raw.reader,
+            ReaderType::Nested(n) => n.get_ref(),
+            #[cfg(feature = "lzw")]
+            ReaderType::Lzw(d) => &d.get_ref().reader,
+            #[cfg(feature = "zip")]
+            ReaderType::Zip(z) => &z.get_ref().reader,
+            ReaderType::Flate(d) => d.get_ref(),
+            ReaderType::MultiFlate(d) => d.get_ref(),
+            ReaderType::Moving => unreachable!(),
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut R {
+        match self {
+            ReaderType::Init(r, ..) | ReaderType::Zero(r) => r,
+            ReaderType::Raw(raw) => &mut raw.reader,
+            ReaderType::Gz(gz) | ReaderType::Reset(gz, _) => &mut gz.decoder.get_mut().reader,
+            ReaderType::GzStream(gz) => &mut gz.decoder.get_mut().reader,
+            ReaderType::Bgzf(b) => &mut b.get_mut().reader,
+            ReaderType::Base64Gz(gz) => &mut gz.decoder.get_mut().inner.reader,
+            ReaderType::Zlib(d) => &mut d.get_mut().reader,
+            #[cfg(feature = "zlib-dictionary")]
+            ReaderType::ZlibDict(d) => &mut d.get_mut().reader,
+            ReaderType::Deflate(d) => &mut d.get_mut().reader,
+            ReaderType::Custom(_, raw) => &mut raw.reader,
+            ReaderType::Nested(n) => n.get_mut(),
+            #[cfg(feature = "lzw")]
+            ReaderType::Lzw(d) => &mut d.get_mut().reader,
+            #[cfg(feature = "zip")]
+            ReaderType::Zip(z) => &mut z.get_mut().reader,
+            ReaderType::Flate(d) => d.get_mut(),
+            ReaderType::MultiFlate(d) => d.get_mut(),
+            ReaderType::Moving => unreachable!(),
         }
     }
 }
@@ -153,100 +817,3190 @@ impl<R: Read> ReaderType<R> {
 impl<R: Read> Read for ReaderType<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         match self {
-            ReaderType::Init(_) => {
+            ReaderType::Init(..) | ReaderType::Reset(..) => {
                 // Update reader state.
-                let init = mem::replace(self, ReaderType::Zero);
-                *self = init.into_actual_reader()?;
+                self.detect()?;
 
                 // Then, call read().
                 debug_assert!(!self.is_init());
                 self.read(buf)
             }
-            ReaderType::Zero => Ok(0),
+            ReaderType::Zero(_) => Ok(0),
             ReaderType::Raw(raw) => raw.read(buf),
             ReaderType::Gz(gz) => gz.read(buf),
+            ReaderType::GzStream(gz) => {
+                let n = gz.read(buf)?;
+                if n != 0 || buf.is_empty() || !gz.decoder.detects_next() {
+                    return Ok(n);
+                }
+                // Detect the format of the trailing data, then read it.
+                *self = match mem::replace(self, ReaderType::Moving) {
+                    ReaderType::GzStream(gz) => Self::detect_next(gz.decoder),
+                    _ => unreachable!(),
+                };
+                self.read(buf)
+            }
+            ReaderType::Bgzf(b) => b.read(buf),
+            ReaderType::Base64Gz(gz) => gz.read(buf),
+            ReaderType::Zlib(d) => d.read(buf),
+            #[cfg(feature = "zlib-dictionary")]
+            ReaderType::ZlibDict(d) => d.read(buf),
+            ReaderType::Deflate(d) => d.read(buf),
+            ReaderType::Custom(d, raw) => d.read(raw, buf),
+            ReaderType::Nested(n) => n.read(buf),
+            #[cfg(feature = "lzw")]
+            ReaderType::Lzw(d) => d.read(buf),
+            #[cfg(feature = "zip")]
+            ReaderType::Zip(z) => z.read(buf),
+            ReaderType::Flate(d) => d.read(buf),
+            ReaderType::MultiFlate(d) => d.read(buf),
+            ReaderType::Moving => unreachable!(),
         }
     }
 }
 
 /// A gzip and non-gzip pholymorphic reader.
+///
+/// `EgzReader` implements [BufRead](std::io::BufRead) with a buffer of the decoded bytes,
+/// so `lines()` and `read_until()` can be used without wrapping it in a `BufReader`.
+///
+/// # Errors
+/// An error of detecting or decoding the stream is given with an [EgzError](EgzError)
+/// inside, which tells e.g. a corrupt stream from a checksum mismatch.
+///
+/// # Non-blocking readers
+/// The underlying reader may return `WouldBlock`, e.g. a non-blocking socket.
+/// The error is returned from `read()`, and reading can be retried when the reader is ready.
+/// Bytes consumed for the detection before the error are kept,
+/// so the detection resumes where it stopped without corrupting the stream.
+///
+/// # Other formats
+/// With the `lzw` feature, `.Z` files made by the Unix `compress` command are also decoded.
+/// With the `zip` feature, the first entry of a ZIP archive can be read
+/// by [EgzReaderBuilder::zip](EgzReaderBuilder::zip).
+///
+/// # Trait objects
+/// `EgzReader` owns its underlying reader, so `R` must be sized.
+/// Unsized readers can be used through `&mut dyn Read` or `Box<dyn Read>`,
+/// both of which implement [Read](std::io::Read).
+/// ```
+/// use std::io::prelude::*;
+/// use egzreader::EgzReader;
+///
+/// fn read_all<R: Read + ?Sized>(r: &mut R) -> std::io::Result<String> {
+///     let mut s = String::new();
+///     EgzReader::new(r).read_to_string(&mut s)?;
+///     Ok(s)
+/// }
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut data: &[u8] = b"Hello!";
+/// let r: &mut dyn Read = &mut data;
+/// assert_eq!(read_all(r)?, "Hello!");
+///
+/// let r: Box<dyn Read> = Box::new(&b"Hello!"[..]);
+/// let mut s = String::new();
+/// EgzReader::new(r).read_to_string(&mut s)?;
+/// assert_eq!(s, "Hello!");
+/// # Ok(())
+/// # }
+/// ```
 #[derive(Debug)]
-pub struct EgzReader<R: Read>(ReaderType<R>);
+pub struct EgzReader<R: Read> {
+    reader: ReaderType<R>,
+    text: Option<TextFilter>,
+    pipeline: Option<Pipeline>,
+    limiter: Option<Limiter>,
+    // Number of decoded bytes allowed to read further.
+    size_limit: Option<u64>,
+    fallback: Option<Fallback<R>>,
+    // Builder of this reader, to start over by reset().
+    builder: EgzReaderBuilder,
 
-impl<R: Read> EgzReader<R> {
-    pub fn new(r: R) -> EgzReader<R> {
-        EgzReader(ReaderType::Init(r))
+    // Decoded bytes read by peek_decoded() and not yet returned.
+    peeked: Vec<u8>,
+    peeked_pos: usize,
+}
+
+// Rewinds the underlying reader to pass the stream through, when it fails to be decoded
+// as gzip before any decoded byte. Given by EgzReaderBuilder::build_seekable.
+#[derive(Debug)]
+struct Fallback<R: Read> {
+    rewind: fn(&mut R, u64) -> Result<()>,
+    start: u64,
+    opts: Options,
+}
+
+fn rewind<R: Seek>(r: &mut R, start: u64) -> Result<()> {
+    r.seek(SeekFrom::Start(start)).map(|_| ())
+}
+
+/// An [EgzReader](EgzReader) over a boxed reader, which can be sent to other threads.
+///
+/// `EgzReader<R>` is `Send` when `R` is `Send`, and `Sync` when `R` is `Sync`.
+pub type DynEgzReader = EgzReader<Box<dyn Read + Send>>;
+
+/// An [EgzReader](EgzReader) over a boxed reader, which can be sent to and shared
+/// between threads.
+pub type DynSyncEgzReader = EgzReader<Box<dyn Read + Send + Sync>>;
+
+impl EgzReader<BufReader<File>> {
+    /// Opens the file at `path` with a buffer, using its extension as a hint of the format.
+    ///
+    /// Equivalent to `EgzReaderBuilder::new().open(path)`.
+    /// See [EgzReaderBuilder::open](EgzReaderBuilder::open).
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut s = String::new();
+    /// EgzReader::open("examples/hello.txt.gz")?.read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<EgzReader<BufReader<File>>> {
+        EgzReaderBuilder::new().open(path)
     }
 }
-impl<R: Read> Read for EgzReader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.0.read(buf)
+
+impl EgzReader<StdinLock<'static>> {
+    /// Creates a reader of the locked standard input, e.g. for a command line tool
+    /// accepting both piped gzip and plain text.
+    ///
+    /// Equivalent to `EgzReader::new(std::io::stdin().lock())`.
+    /// The standard input is locked until the reader is dropped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut s = String::new();
+    /// EgzReader::stdin().read_to_string(&mut s)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stdin() -> EgzReader<StdinLock<'static>> {
+        EgzReader::new(io::stdin().lock())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::io::Read;
+impl<R: Read> EgzReader<R> {
+    pub fn new(r: R) -> EgzReader<R> {
+        EgzReaderBuilder::new().build(r)
+    }
 
-    use super::EgzReader;
+    /// Creates a reader and detects the format immediately, instead of at the first read.
+    ///
+    /// An error of reading the underlying reader is returned here,
+    /// and [format](EgzReader::format) is known after this.
+    ///
+    /// # Examples
+    /// ```
+    /// use egzreader::{EgzReader, Format};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let r = EgzReader::new_eager(&b"Hello!"[..])?;
+    /// assert_eq!(r.format(), Some(Format::Raw));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_eager(r: R) -> Result<EgzReader<R>> {
+        EgzReaderBuilder::new().build_eager(r)
+    }
 
-    // "Hello!"
-    const HELLO: &[u8] = &[0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x21];
+    /// Creates a reader of an HTTP body, using the `Content-Encoding` and `Content-Type`
+    /// headers as hints of the format.
+    ///
+    /// Equivalent to `EgzReaderBuilder::new().http_hints(content_encoding, content_type).build(r)`.
+    /// See [EgzReaderBuilder::http_hints](EgzReaderBuilder::http_hints).
+    pub fn with_http_hints(
+        r: R,
+        content_encoding: Option<&str>,
+        content_type: Option<&str>,
+    ) -> EgzReader<R> {
+        EgzReaderBuilder::new()
+            .http_hints(content_encoding, content_type)
+            .build(r)
+    }
 
-    // "Hello!" encoded by gzip
-    const HELLO_GZ: &[u8] = &[
-        0x1f, 0x8b, 0x08, 0x00, 0xeb, 0x47, 0x74, 0x60, 0x00, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9,
-        0x57, 0x04, 0x00, 0x56, 0xcc, 0x2a, 0x9d, 0x06, 0x00, 0x00, 0x00,
-    ];
+    /// Creates a reader decoding `r` as gzip without the detection.
+    ///
+    /// Equivalent to `EgzReaderBuilder::new().force(Format::Gzip).build(r)`.
+    pub fn gzip(r: R) -> EgzReader<R> {
+        EgzReaderBuilder::new().force(Format::Gzip).build(r)
+    }
 
-    // Zero-byte file encoded by gzip
-    const ZERO_GZ: &[u8] = &[
-        0x1f, 0x8b, 0x08, 0x08, 0x32, 0xb1, 0x90, 0x63, 0x00, 0x03, 0x7a, 0x65, 0x72, 0x6f, 0x00,
-        0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    ];
+    /// Creates a reader yielding `r` as it is without the detection.
+    ///
+    /// Equivalent to `EgzReaderBuilder::new().force(Format::Raw).build(r)`.
+    pub fn raw(r: R) -> EgzReader<R> {
+        EgzReaderBuilder::new().force(Format::Raw).build(r)
+    }
 
-    #[test]
-    fn read_zero() {
-        let data: &[u8] = &[0; 0];
-        let mut r = EgzReader::new(data);
-        let mut s = String::new();
-        r.read_to_string(&mut s).unwrap();
-        assert_eq!(s, "");
+    /// Creates a reader of `prefix` followed by `r`, as if they were one stream.
+    ///
+    /// This is for callers which have already consumed the leading bytes of the stream,
+    /// e.g. a protocol demultiplexer reading a frame header. The detection considers `prefix` too.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut r = EgzReader::with_initial_bytes(b"Hel".to_vec(), &b"lo!"[..]);
+    /// let mut s = String::new();
+    /// r.read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_initial_bytes(prefix: Vec<u8>, r: R) -> EgzReader<R> {
+        EgzReaderBuilder::new().build_with_initial_bytes(prefix, r)
     }
-    #[test]
-    fn read_zero_gz() {
-        let mut r = EgzReader::new(ZERO_GZ);
-        let mut s = String::new();
-        r.read_to_string(&mut s).unwrap();
-        assert_eq!(s, "");
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.reader.inner_ref()
     }
-    #[test]
-    fn read_long() {
-        let data: &[u8] = &[0x41; 20];
-        let mut r = EgzReader::new(data);
-        let mut s = String::new();
-        r.read_to_string(&mut s).unwrap();
-        assert_eq!(s, "AAAAAAAAAAAAAAAAAAAA");
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// Note that reading from the underlying reader directly corrupts the stream,
+    /// e.g. by skipping bytes which are not decoded yet.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.reader.inner_mut()
     }
-    #[test]
-    fn read_hello_txt() {
-        let mut r = EgzReader::new(HELLO);
-        let mut s = String::new();
-        r.read_to_string(&mut s).unwrap();
-        assert_eq!(s, "Hello!");
+
+    /// Replaces the underlying reader with `r`, returning the previous one.
+    ///
+    /// The detection and decoder states are preserved,
+    /// so `r` must continue the stream exactly where the previous reader stopped
+    /// (e.g. a reconnected socket resuming at the same compressed offset).
+    /// Bytes already read from the previous reader are not lost.
+    pub fn replace_inner(&mut self, r: R) -> R {
+        mem::replace(self.reader.inner_mut(), r)
     }
-    #[test]
-    fn read_hello_gz() {
-        let mut r = EgzReader::new(HELLO_GZ);
-        let mut s = String::new();
-        r.read_to_string(&mut s).unwrap();
-        assert_eq!(s, "Hello!");
+
+    /// Starts over with a new stream `r`, returning the previous underlying reader.
+    ///
+    /// Unlike [replace_inner](EgzReader::replace_inner), the format of `r` is detected again,
+    /// and the decoded bytes not read yet are discarded. The gzip decoder and the buffers
+    /// are reused when `r` is gzip too, which saves their allocation for each of many
+    /// small files. The options given to the builder are applied to `r`, except
+    /// [fallback_to_raw](EgzReaderBuilder::fallback_to_raw).
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut r = EgzReader::new(&b"Hello!"[..]);
+    /// let mut s = String::new();
+    /// for file in [&b"one "[..], b"two"] {
+    ///     r.reset(file);
+    ///     r.read_to_string(&mut s)?;
+    /// }
+    /// assert_eq!(s, "one two");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reset(&mut self, r: R) -> R {
+        let (reader, old) = match mem::replace(&mut self.reader, ReaderType::Moving) {
+            ReaderType::Gz(mut gz) | ReaderType::Reset(mut gz, _) => {
+                let opts = &self.builder.opts;
+                let mut prev = gz
+                    .decoder
+                    .reset(Prefixed::new(Vec::new(), r).with_options(opts));
+                prev.preread.clear();
+                gz.decoder.get_mut().preread = prev.preread;
+                (ReaderType::Reset(gz, Box::new(opts.clone())), prev.reader)
+            }
+            reader => {
+                let (mut buf, old) = reader.into_parts();
+                buf.clear();
+                (ReaderType::Init(r, self.builder.opts.clone(), buf), old)
+            }
+        };
+
+        let mut peeked = mem::take(&mut self.peeked);
+        peeked.clear();
+        *self = EgzReader {
+            peeked,
+            ..self.builder.build_from(reader)
+        };
+        old
     }
-    #[test]
-    fn read_fake_gz() {
-        let mut r = EgzReader::new(&HELLO_GZ[..10]);
-        let mut buf = [0; 11];
-        let n = r.read(&mut buf).unwrap();
-        assert_eq!(buf[..n], HELLO_GZ[..10]);
+
+    /// Converts into the underlying [GzDecoder](flate2::read::GzDecoder)
+    /// when the stream has been detected as gzip.
+    ///
+    /// Returns `Err(self)` when the stream is not gzip or has not been read yet,
+    /// or bytes peeked by [peek_decoded](EgzReader::peek_decoded) have not been read.
+    /// The text mode is not applied to the returned decoder.
+    ///
+    /// A reader created from a [GzDecoder](flate2::read::GzDecoder) or a
+    /// [MultiGzDecoder](flate2::read::MultiGzDecoder) by `From` is converted back to it
+    /// by `TryFrom` instead, as the decoder reads `R` without [Prefixed](Prefixed).
+    #[allow(clippy::result_large_err)]
+    pub fn into_gz_decoder(self) -> std::result::Result<GzDecoder<Prefixed<R>>, EgzReader<R>> {
+        match self.reader {
+            ReaderType::Gz(gz) if self.peeked_pos == self.peeked.len() => Ok(gz.decoder),
+            reader => Err(EgzReader { reader, ..self }),
+        }
+    }
+
+    /// Converts into the reader of the bytes following the gzip stream, once the stream
+    /// has been read to the end with [TrailingData::Expose](TrailingData::Expose).
+    ///
+    /// Returns `Err(self)` otherwise.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::{EgzReaderBuilder, TrailingData};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// # let data: &[u8] = &[
+    /// #     0x1f, 0x8b, 0x08, 0x00, 0xeb, 0x47, 0x74, 0x60, 0x00, 0x03, 0xf3, 0x48, 0xcd, 0xc9,
+    /// #     0xc9, 0x57, 0x04, 0x00, 0x56, 0xcc, 0x2a, 0x9d, 0x06, 0x00, 0x00, 0x00,
+    /// # ];
+    /// // gzip of "Hello!" followed by a payload
+    /// let data = [data, b"payload"].concat();
+    /// let mut r = EgzReaderBuilder::new()
+    ///     .trailing_data(TrailingData::Expose)
+    ///     .build(&data[..]);
+    /// let mut s = String::new();
+    /// r.read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello!");
+    ///
+    /// let mut payload = String::new();
+    /// r.into_trailing().unwrap().read_to_string(&mut payload)?;
+    /// assert_eq!(payload, "payload");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn into_trailing(self) -> std::result::Result<BufReader<Prefixed<R>>, EgzReader<R>> {
+        match self.reader {
+            ReaderType::GzStream(gz) if self.peeked_pos == self.peeked.len() => {
+                match gz.decoder.into_trailing() {
+                    Ok(r) => Ok(r),
+                    Err(decoder) => Err(EgzReader {
+                        reader: ReaderType::GzStream(GzReader { decoder, ..gz }),
+                        ..self
+                    }),
+                }
+            }
+            reader => Err(EgzReader { reader, ..self }),
+        }
+    }
+
+    /// Unwraps this `EgzReader`, returning the detected format, the bytes read ahead from
+    /// the underlying reader and not consumed yet, and the underlying reader.
+    ///
+    /// The format is `None` if it has not been detected yet, when the bytes are those
+    /// read for the detection so far. Reading the bytes followed by the reader gives the
+    /// rest of the underlying stream, e.g. to reuse a connection for the next message.
+    /// Note that bytes buffered by a decoder are lost, as with the `into_inner` of
+    /// flate2 decoders, and so are decoded bytes not read yet.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::{EgzReader, Format};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut r = EgzReader::new(&b"Hello!"[..]);
+    /// let mut buf = [0; 2];
+    /// r.read_exact(&mut buf)?;
+    ///
+    /// let (format, rest, inner) = r.into_parts();
+    /// assert_eq!(format, Some(Format::Raw));
+    /// assert_eq!(rest, b"llo!");
+    /// assert!(inner.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_parts(self) -> (Option<Format>, Vec<u8>, R) {
+        let format = self.reader.format();
+        let (rest, r) = self.reader.into_parts();
+        (format, rest, r)
+    }
+
+    /// Unwraps this `EgzReader`, returning the underlying reader.
+    ///
+    /// Note that the bytes read ahead and not consumed yet are lost.
+    /// Use [into_parts](EgzReader::into_parts) to get them too.
+    pub fn into_inner(self) -> R {
+        self.into_parts().2
+    }
+
+    /// Converts into an iterator over the gzip members,
+    /// each of which reads the decoded bytes of one member and ends at its boundary.
+    ///
+    /// This is useful for record-per-member formats like WARC.
+    /// The live, text, base64 and transform options are not applied to the members.
+    ///
+    /// Returns `Err(self)` when the reader has already been read.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// // Two gzip members of "Hello!"
+    /// let gz = std::fs::read("examples/hello.txt.gz")?;
+    /// let data = [&gz[..], &gz[..]].concat();
+    ///
+    /// let mut records = Vec::new();
+    /// for member in EgzReader::new(&data[..]).into_members().unwrap() {
+    ///     let mut record = String::new();
+    ///     member?.read_to_string(&mut record)?;
+    ///     records.push(record);
+    /// }
+    /// assert_eq!(records, ["Hello!", "Hello!"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn into_members(self) -> std::result::Result<Members<R>, EgzReader<R>> {
+        match self.reader.into_init() {
+            ReaderType::Init(r, opts, buf) if self.peeked.is_empty() => {
+                Ok(Members::new(r, opts, buf))
+            }
+            reader => Err(EgzReader { reader, ..self }),
+        }
+    }
+
+    /// Converts into an iterator over the entries of a ZIP archive.
+    ///
+    /// The beginning of the underlying reader is read to detect a ZIP archive.
+    /// Returns `self` if the stream is not a ZIP archive, or has already been read.
+    /// An error while reading for the detection is not returned: `self` is returned
+    /// keeping the bytes read before the error, and the following read reads the
+    /// underlying reader again after them.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// if let Ok(entries) = EgzReader::new(File::open("data.zip")?).zip_entries() {
+    ///     for entry in entries {
+    ///         let mut entry = entry?;
+    ///         let mut s = String::new();
+    ///         entry.read_to_string(&mut s)?;
+    ///         println!("{}: {} bytes", entry.name(), s.len());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "zip")]
+    #[allow(clippy::result_large_err)]
+    pub fn zip_entries(mut self) -> std::result::Result<ZipEntries<R>, EgzReader<R>> {
+        self.reader = mem::replace(&mut self.reader, ReaderType::Moving).into_init();
+        match &mut self.reader {
+            ReaderType::Init(r, opts, buf) if self.peeked.is_empty() => {
+                if ReaderType::preread(r, opts, buf).is_err() || !is_zip(buf) {
+                    return Err(self);
+                }
+            }
+            _ => return Err(self),
+        }
+        match mem::replace(&mut self.reader, ReaderType::Moving) {
+            ReaderType::Init(r, opts, buf) => Ok(ZipEntries::new(
+                Prefixed::new(buf, r)
+                    .limit(opts.compressed_rate)
+                    .retry(opts.retry)
+                    .buffered(opts.buffer_bounds),
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Decodes up to `n` bytes ahead without consuming them.
+    ///
+    /// The returned bytes are given again by the following reads,
+    /// so the decoded content can be sniffed (e.g. JSON or CSV) before reading.
+    /// Fewer than `n` bytes are returned only at the end of stream.
+    ///
+    /// Since the peeked bytes are buffered, `n` is limited to 1 MiB.
+    /// An error of `ErrorKind::InvalidInput` is returned for a larger `n`.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut r = EgzReader::new(&b"{\"key\": 1}"[..]);
+    /// assert!(r.peek_decoded(1)?.starts_with(b"{"));
+    ///
+    /// let mut s = String::new();
+    /// r.read_to_string(&mut s)?;
+    /// assert_eq!(s, "{\"key\": 1}");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn peek_decoded(&mut self, n: usize) -> Result<&[u8]> {
+        if PEEK_LIMIT < n {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("cannot peek more than {} bytes", PEEK_LIMIT),
+            ));
+        }
+        if self.peeked_pos == self.peeked.len() {
+            self.peeked.clear();
+            self.peeked_pos = 0;
+        }
+
+        while self.peeked.len() - self.peeked_pos < n {
+            let len = self.peeked.len();
+            // The buffer grows as the decoded bytes arrive, not to `n` at once.
+            let want = (n - (len - self.peeked_pos)).min(FILL_LEN);
+            // The buffer is taken out, as read_decoded() borrows `self`.
+            let mut peeked = mem::take(&mut self.peeked);
+            peeked.resize(len + want, 0);
+            let result = self.read_decoded(&mut peeked[len..]);
+            let read = *result.as_ref().unwrap_or(&0);
+            peeked.truncate(len + read);
+            self.peeked = peeked;
+            if result? == 0 {
+                break;
+            }
+        }
+
+        let end = self.peeked.len().min(self.peeked_pos + n);
+        Ok(&self.peeked[self.peeked_pos..end])
+    }
+
+    /// Returns the underlying [GzDecoder](flate2::read::GzDecoder)
+    /// when the stream has been detected as gzip, e.g. to access its header.
+    ///
+    /// Only a single gzip member decoded by one `GzDecoder` is exposed.
+    /// Returns `None` when:
+    /// - the stream has not been read yet, or is not gzip;
+    /// - the gzip members are read as one stream, by
+    ///   [multi_member](EgzReaderBuilder::multi_member) or
+    ///   [trailing_data](EgzReaderBuilder::trailing_data) other than `Ignore`;
+    /// - the stream is BGZF, or base64-encoded gzip;
+    /// - the stream is decoded at more than one level, by
+    ///   [max_depth](EgzReaderBuilder::max_depth);
+    /// - the reader was created from a flate2 decoder by `From`.
+    ///
+    /// [gz_header](EgzReader::gz_header) gives the header of a gzip stream in these cases too.
+    pub fn as_gz(&mut self) -> Option<&mut GzDecoder<Prefixed<R>>> {
+        match &mut self.reader {
+            ReaderType::Gz(gz) => Some(&mut gz.decoder),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying [Prefixed](Prefixed) reader
+    /// when the stream has been detected as non-gzip.
+    ///
+    /// Returns `None` when the stream is gzip or has not been read yet.
+    pub fn as_raw(&mut self) -> Option<&mut Prefixed<R>> {
+        match &mut self.reader {
+            ReaderType::Raw(raw) => Some(raw),
+            _ => None,
+        }
+    }
+
+    /// Returns the format of the stream, or `None` if it has not been detected yet.
+    ///
+    /// The format is detected at the first read, by [detect](EgzReader::detect),
+    /// or at the construction by [new_eager](EgzReader::new_eager).
+    pub fn format(&self) -> Option<Format> {
+        self.reader.format()
+    }
+
+    /// Returns the header of the gzip stream, e.g. to restore the original filename.
+    ///
+    /// The header is available once the format has been detected as gzip,
+    /// by [detect](EgzReader::detect) or the first read. With
+    /// [multi_member](EgzReaderBuilder::multi_member), this is the header of the
+    /// current member, and for BGZF, of the first block.
+    /// Returns `None` for other formats.
+    ///
+    /// # Examples
+    /// ```
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// # let data: &[u8] = &[
+    /// #     0x1f, 0x8b, 0x08, 0x08, 0x32, 0xb1, 0x90, 0x63, 0x00, 0x03, 0x7a, 0x65, 0x72, 0x6f,
+    /// #     0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    /// # ];
+    /// // gzip of a file named "zero"
+    /// let mut r = EgzReader::new(data);
+    /// r.detect()?;
+    /// let header = r.gz_header().unwrap();
+    /// assert_eq!(header.filename(), Some(&b"zero"[..]));
+    /// assert_eq!(header.os(), 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn gz_header(&self) -> Option<OwnedGzHeader> {
+        self.reader.gz_header()
+    }
+
+    /// Detects the format of the stream now, if not yet, without consuming decoded bytes.
+    ///
+    /// # Examples
+    /// ```
+    /// use egzreader::{EgzReader, Format};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut r = EgzReader::new(&b"Hello!"[..]);
+    /// assert_eq!(r.format(), None);
+    /// assert_eq!(r.detect()?, Format::Raw);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn detect(&mut self) -> Result<Format> {
+        if self.reader.is_init() {
+            self.reader.detect()?;
+        }
+        Ok(self.reader.format().unwrap())
+    }
+
+    /// Returns an iterator over the records of the decoded stream separated by `delimiter`,
+    /// like [BufRead::split](std::io::BufRead::split) with a buffer sized for bulk data.
+    ///
+    /// Each record is given without the delimiter.
+    ///
+    /// # Examples
+    /// ```
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut records = EgzReader::new(&b"a\0b"[..]).split(0);
+    /// assert_eq!(records.next().unwrap()?, b"a");
+    /// assert_eq!(records.next().unwrap()?, b"b");
+    /// assert!(records.next().is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split(self, delimiter: u8) -> Split<R> {
+        Split::new(self, delimiter)
+    }
+
+    /// Returns an iterator over the lines of the decoded stream, as
+    /// [BufRead::lines](std::io::BufRead::lines) without importing the trait.
+    ///
+    /// Each line is given without the newline, `\n` or `\r\n`.
+    ///
+    /// # Examples
+    /// ```
+    /// use egzreader::EgzReader;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// for line in EgzReader::open("examples/hello.txt.gz")?.lines() {
+    ///     assert_eq!(line?, "Hello!");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lines(self) -> io::Lines<EgzReader<R>> {
+        BufRead::lines(self)
+    }
+}
+impl<R: Read> From<GzDecoder<R>> for EgzReader<R> {
+    fn from(d: GzDecoder<R>) -> EgzReader<R> {
+        EgzReader {
+            reader: ReaderType::Flate(d),
+            text: None,
+            pipeline: None,
+            limiter: None,
+            size_limit: None,
+            fallback: None,
+            builder: EgzReaderBuilder::new(),
+            peeked: Vec::new(),
+            peeked_pos: 0,
+        }
+    }
+}
+impl<R: Read> From<MultiGzDecoder<R>> for EgzReader<R> {
+    fn from(d: MultiGzDecoder<R>) -> EgzReader<R> {
+        EgzReader {
+            reader: ReaderType::MultiFlate(d),
+            text: None,
+            pipeline: None,
+            limiter: None,
+            size_limit: None,
+            fallback: None,
+            builder: EgzReaderBuilder::new(),
+            peeked: Vec::new(),
+            peeked_pos: 0,
+        }
+    }
+}
+impl<R: Read> TryFrom<EgzReader<R>> for GzDecoder<R> {
+    type Error = EgzReader<R>;
+
+    /// Converts back the reader created from a `GzDecoder`, or returns it as the error.
+    fn try_from(r: EgzReader<R>) -> std::result::Result<GzDecoder<R>, EgzReader<R>> {
+        match r.reader {
+            ReaderType::Flate(d) if r.peeked_pos == r.peeked.len() => Ok(d),
+            reader => Err(EgzReader { reader, ..r }),
+        }
+    }
+}
+impl<R: Read> TryFrom<EgzReader<R>> for MultiGzDecoder<R> {
+    type Error = EgzReader<R>;
+
+    /// Converts back the reader created from a `MultiGzDecoder`, or returns it as the error.
+    fn try_from(r: EgzReader<R>) -> std::result::Result<MultiGzDecoder<R>, EgzReader<R>> {
+        match r.reader {
+            ReaderType::MultiFlate(d) if r.peeked_pos == r.peeked.len() => Ok(d),
+            reader => Err(EgzReader { reader, ..r }),
+        }
+    }
+}
+impl<R: Read> EgzReader<R> {
+    fn read_decoded(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let buf = match &self.limiter {
+            Some(limiter) => {
+                let len = limiter.chunk_size(buf.len());
+                &mut buf[..len]
+            }
+            None => buf,
+        };
+        // One more byte than the limit is read to tell if the limit is exceeded.
+        let buf = match self.size_limit {
+            Some(limit) if limit < buf.len() as u64 => &mut buf[..limit as usize + 1],
+            _ => buf,
+        };
+
+        let reader = &mut self.reader;
+        let fallback = &mut self.fallback;
+        let text = &mut self.text;
+        let mut read_decoded = |buf: &mut [u8]| match text {
+            Some(text) => text.read(&mut FallbackReader(reader, fallback), buf),
+            None => FallbackReader(reader, fallback).read(buf),
+        };
+
+        let n = match &mut self.pipeline {
+            Some(pipeline) => pipeline.read(read_decoded, buf)?,
+            None => read_decoded(buf)?,
+        };
+
+        if let Some(limiter) = &mut self.limiter {
+            limiter.consume(n);
+        }
+        if let Some(limit) = &mut self.size_limit {
+            if *limit < n as u64 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "decoded size limit exceeded",
+                ));
+            }
+            *limit -= n as u64;
+        }
+        Ok(n)
+    }
+}
+// ReaderType falling back to the raw stream on a gzip decoding error.
+struct FallbackReader<'a, R: Read>(&'a mut ReaderType<R>, &'a mut Option<Fallback<R>>);
+
+impl<R: Read> Read for FallbackReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.read_or_fall_back(buf)
+            .map_err(|e| EgzError::classify(e, self.0.format()))
+    }
+}
+impl<R: Read> FallbackReader<'_, R> {
+    fn read_or_fall_back(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let FallbackReader(reader, fallback) = self;
+        if fallback.is_none() {
+            return reader.read(buf);
+        }
+        let e = match reader.read(buf) {
+            Ok(0) => return Ok(0),
+            Ok(n) => {
+                // Decoded bytes are given, so the stream can not be passed through anymore.
+                **fallback = None;
+                return Ok(n);
+            }
+            Err(e) => e,
+        };
+        let decoding_error = matches!(
+            e.kind(),
+            ErrorKind::InvalidData | ErrorKind::InvalidInput | ErrorKind::UnexpectedEof
+        );
+        let mut r = match mem::replace(*reader, ReaderType::Moving) {
+            ReaderType::Gz(gz) if decoding_error => gz.decoder.into_inner().into_inner(),
+            ReaderType::GzStream(gz) if decoding_error => gz.decoder.into_inner().into_inner(),
+            ReaderType::Bgzf(b) if decoding_error => b.into_inner().into_inner(),
+            other => {
+                **reader = other;
+                return Err(e);
+            }
+        };
+        let Fallback {
+            rewind,
+            start,
+            opts,
+        } = fallback.take().unwrap();
+        let rewound = rewind(&mut r, start);
+        **reader = ReaderType::make_reader_as(Format::Raw, None, Vec::new(), r, &opts);
+        rewound?;
+        reader.read(buf)
+    }
+}
+
+impl<R: Read> Read for EgzReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.peeked_pos < self.peeked.len() {
+            let n = (&self.peeked[self.peeked_pos..]).read(buf)?;
+            self.peeked_pos += n;
+            return Ok(n);
+        }
+        self.read_decoded(buf)
+    }
+}
+
+impl<R: Read> BufRead for EgzReader<R> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.peeked_pos == self.peeked.len() {
+            // The buffer of peek_decoded() is reused, filled by one read.
+            let mut peeked = mem::take(&mut self.peeked);
+            peeked.resize(FILL_LEN, 0);
+            let result = self.read_decoded(&mut peeked);
+            peeked.truncate(*result.as_ref().unwrap_or(&0));
+            self.peeked = peeked;
+            self.peeked_pos = 0;
+            result?;
+        }
+        Ok(&self.peeked[self.peeked_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.peeked_pos = self.peeked.len().min(self.peeked_pos + amt);
+    }
+}
+
+/// A builder to configure [EgzReader](EgzReader).
+///
+/// [EgzReader::new](EgzReader::new) is equivalent to `EgzReaderBuilder::new().build(r)`.
+///
+/// The options are:
+/// - formats to detect besides gzip: [base64](EgzReaderBuilder::base64),
+///   [zlib](EgzReaderBuilder::zlib), [deflate](EgzReaderBuilder::deflate)
+///   and [formats](EgzReaderBuilder::formats), or a [detector](EgzReaderBuilder::detector);
+/// - gzip members: [multi_member](EgzReaderBuilder::multi_member) and
+///   [trailing_data](EgzReaderBuilder::trailing_data);
+/// - strictness: [live](EgzReaderBuilder::live), [strict](EgzReaderBuilder::strict),
+///   [lenient_length](EgzReaderBuilder::lenient_length),
+///   [require_bgzf_eof](EgzReaderBuilder::require_bgzf_eof),
+///   [fallback_to_raw](EgzReaderBuilder::fallback_to_raw) and
+///   [validate_utf8](EgzReaderBuilder::validate_utf8);
+/// - buffering: [adaptive_buffer](EgzReaderBuilder::adaptive_buffer),
+///   [sniff_window](EgzReaderBuilder::sniff_window) and
+///   [sniff_timeout](EgzReaderBuilder::sniff_timeout);
+/// - threads: [bgzf_threads](EgzReaderBuilder::bgzf_threads);
+/// - limits: [max_depth](EgzReaderBuilder::max_depth),
+///   [max_decoded_size](EgzReaderBuilder::max_decoded_size),
+///   [max_decoded_rate](EgzReaderBuilder::max_decoded_rate) and
+///   [max_compressed_rate](EgzReaderBuilder::max_compressed_rate).
+///
+/// # Examples
+/// ```
+/// use std::io::prelude::*;
+/// use egzreader::EgzReaderBuilder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let data: &[u8] = b"Hello!";
+/// let mut r = EgzReaderBuilder::new().live(true).build(data);
+///
+/// let mut s = String::new();
+/// r.read_to_string(&mut s)?;
+/// assert_eq!(s, "Hello!");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EgzReaderBuilder {
+    opts: Options,
+    stages: Stages,
+    extension: ExtensionPolicy,
+}
+
+impl EgzReaderBuilder {
+    /// Creates a builder with the default options.
+    pub fn new() -> EgzReaderBuilder {
+        EgzReaderBuilder::default()
+    }
+
+    /// Enables the live-stream mode. Disabled by default.
+    ///
+    /// Some servers stream gzip indefinitely using `Z_SYNC_FLUSH` and never write the trailer.
+    /// In the live-stream mode, decoded data is delivered as flush points arrive, and
+    /// the end of the underlying reader is treated as a clean EOF instead of a truncation error.
+    /// Note that the integrity of such stream can not be verified.
+    pub fn live(mut self, live: bool) -> EgzReaderBuilder {
+        self.opts.live = live;
+        self
+    }
+
+    /// Enables the text mode, which converts CRLF into LF in the decoded output.
+    /// Disabled by default.
+    ///
+    /// A CR which is not followed by LF is kept as it is.
+    pub fn text(mut self, text: bool) -> EgzReaderBuilder {
+        self.opts.crlf = text;
+        self
+    }
+
+    /// Validates that the decoded output is UTF-8. Disabled by default.
+    ///
+    /// When enabled, `read()` fails with [InvalidData](std::io::ErrorKind::InvalidData)
+    /// as soon as an invalid sequence is decoded, instead of leaving it to the consumer.
+    pub fn validate_utf8(mut self, validate: bool) -> EgzReaderBuilder {
+        self.opts.utf8 = validate;
+        self
+    }
+
+    /// Skips the detection and decodes the stream as `format`. Not forced by default.
+    ///
+    /// This is for streams whose encoding is already known, e.g. by an HTTP header
+    /// or a file extension. No bytes are read ahead, and an invalid stream results in
+    /// an error of the decoder instead of passing through.
+    /// The formats enabled by other options need not be enabled.
+    ///
+    /// # Panics
+    /// Panics if `format` is [Empty](Format::Empty) or [Custom](Format::Custom).
+    pub fn force(mut self, format: Format) -> EgzReaderBuilder {
+        assert!(
+            format != Format::Empty && format != Format::Custom,
+            "{:?} can not be forced",
+            format
+        );
+        self.opts.force = Some(format);
+        self
+    }
+
+    /// Biases the detection by the `Content-Encoding` and `Content-Type` headers
+    /// of an HTTP body.
+    ///
+    /// The content still decides, since servers often mislabel bodies: a body labeled gzip
+    /// which does not begin with the gzip magic number (e.g. decoded by a proxy) is passed
+    /// through, and an unlabeled gzip body is decoded. A labeled body is trusted as gzip
+    /// even if it is shorter than the gzip header, as [lenient_length](EgzReaderBuilder::lenient_length).
+    /// `deflate` enables both [zlib](EgzReaderBuilder::zlib) and
+    /// [deflate](EgzReaderBuilder::deflate), since servers send either.
+    /// Unsupported codings, e.g. `br`, and unknown types give no hint.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReaderBuilder;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// // Already decoded by a proxy
+    /// let body: &[u8] = b"Hello!";
+    /// let mut r = EgzReaderBuilder::new()
+    ///     .http_hints(Some("gzip"), Some("text/plain; charset=utf-8"))
+    ///     .build(body);
+    /// let mut s = String::new();
+    /// r.read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn http_hints(
+        mut self,
+        content_encoding: Option<&str>,
+        content_type: Option<&str>,
+    ) -> EgzReaderBuilder {
+        match http::format_of(content_encoding, content_type) {
+            Some(Format::Gzip) => self.opts.lenient_length = true,
+            Some(Format::Zlib) => self.opts.zlib = true,
+            Some(Format::Deflate) => {
+                self.opts.zlib = true;
+                self.opts.deflate = true;
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Decides how [open](EgzReaderBuilder::open) treats a file whose extension and
+    /// content disagree. [Content](ExtensionPolicy::Content) by default.
+    pub fn extension_policy(mut self, policy: ExtensionPolicy) -> EgzReaderBuilder {
+        self.extension = policy;
+        self
+    }
+
+    /// Opens the file at `path` and creates an [EgzReader](EgzReader) of it,
+    /// using the extension (e.g. `.gz`, `.zz` or `.txt`) as a hint of the format.
+    ///
+    /// The file is read through a [BufReader](std::io::BufReader),
+    /// so small reads of a raw file do not make a system call each.
+    ///
+    /// The content is still verified by the detection, unless the
+    /// [extension policy](EgzReaderBuilder::extension_policy) says otherwise.
+    /// Unknown extensions give no hint, and the hint is ignored if the format is
+    /// [forced](EgzReaderBuilder::force).
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::{EgzReaderBuilder, ExtensionPolicy};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut r = EgzReaderBuilder::new()
+    ///     .extension_policy(ExtensionPolicy::Reject)
+    ///     .open("examples/hello.txt.gz")?;
+    /// let mut s = String::new();
+    /// r.read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<EgzReader<BufReader<File>>> {
+        let path = path.as_ref();
+        let file = BufReader::new(File::open(path)?);
+        let hint = match self.opts.force {
+            Some(_) => None,
+            None => path::format_of(path),
+        };
+
+        let mut builder = self.clone();
+        if let Some(format) = hint {
+            match self.extension {
+                ExtensionPolicy::Extension => builder.opts.force = Some(format),
+                _ => match format {
+                    Format::Zlib => builder.opts.zlib = true,
+                    Format::Deflate => builder.opts.deflate = true,
+                    #[cfg(feature = "zip")]
+                    Format::Zip => builder.opts.zip = true,
+                    _ => {}
+                },
+            }
+        }
+
+        let mut r = builder.build(file);
+        if let (Some(hint), ExtensionPolicy::Reject) = (hint, self.extension) {
+            let detected = r.detect()?;
+            if !path::agrees(hint, detected) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{}: {:?} content does not match the extension",
+                        path.display(),
+                        detected
+                    ),
+                ));
+            }
+        }
+        Ok(r)
+    }
+
+    /// Opens the file at `path` to follow, creating an [EgzFollower](EgzFollower)
+    /// which reads the file with the configured options.
+    ///
+    /// [multi_member](EgzReaderBuilder::multi_member) is always enabled
+    /// to decode the gzip members appended to the file.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::io::prelude::*;
+    /// use std::io::BufReader;
+    /// use std::time::Duration;
+    /// use egzreader::EgzReaderBuilder;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let f = EgzReaderBuilder::new()
+    ///     .live(true)
+    ///     .follow("/var/log/app.log.gz", Duration::from_secs(1))?;
+    /// for line in BufReader::new(f).lines() {
+    ///     println!("{}", line?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn follow<P: AsRef<Path>>(&self, path: P, poll_interval: Duration) -> Result<EgzFollower> {
+        EgzFollower::with_builder(path.as_ref(), poll_interval, self.clone())
+    }
+
+    /// Creates an [EgzReader](EgzReader) with the configured options.
+    pub fn build<R: Read>(&self, r: R) -> EgzReader<R> {
+        self.build_with_initial_bytes(Vec::new(), r)
+    }
+
+    /// Creates an [EgzReader](EgzReader) with the configured options,
+    /// detecting the format immediately.
+    ///
+    /// See [EgzReader::new_eager](EgzReader::new_eager).
+    pub fn build_eager<R: Read>(&self, r: R) -> Result<EgzReader<R>> {
+        let mut r = self.build(r);
+        r.detect()?;
+        Ok(r)
+    }
+
+    /// Creates an [EgzReader](EgzReader) of a buffered reader, detecting the format
+    /// by the bytes in the buffer of `r`.
+    ///
+    /// The bytes are peeked by [fill_buf](BufRead::fill_buf) and left in `r`,
+    /// so the decoder reads `r` directly instead of the bytes read ahead followed by `r`.
+    /// If the buffer is too short to tell the format, or `fill_buf` fails,
+    /// the format is detected at the first read as [build](EgzReaderBuilder::build).
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use std::io::BufReader;
+    /// use egzreader::{EgzReaderBuilder, Format};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let data: &[u8] = b"Hello, world!";
+    /// let mut r = EgzReaderBuilder::new().build_buffered(BufReader::new(data));
+    /// assert_eq!(r.format(), Some(Format::Raw));
+    ///
+    /// let mut s = String::new();
+    /// r.read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello, world!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn build_buffered<R: BufRead>(&self, mut r: R) -> EgzReader<R> {
+        let sniffed = match self.opts.force {
+            Some(_) => None,
+            None => r
+                .fill_buf()
+                .ok()
+                .and_then(|peek| ReaderType::<R>::sniff(peek, &self.opts)),
+        };
+        let mut egz = self.build(r);
+        if let Some((format, custom)) = sniffed {
+            if let ReaderType::Init(r, opts, buf) =
+                mem::replace(&mut egz.reader, ReaderType::Moving)
+            {
+                egz.reader = ReaderType::make_reader_as(format, custom, buf, r, &opts);
+            }
+        }
+        egz
+    }
+
+    /// Creates an [EgzReader](EgzReader) of a seekable reader, detecting the format
+    /// immediately and seeking `r` back to where it was.
+    ///
+    /// The decoder reads `r` from the original position instead of the bytes read ahead
+    /// followed by `r`, e.g. a [File](std::fs::File) given as it is.
+    /// An error of reading or seeking `r` is returned here.
+    /// With [fallback_to_raw](EgzReaderBuilder::fallback_to_raw), a stream which fails
+    /// to be decoded as gzip is passed through from the original position.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use std::io::Cursor;
+    /// use egzreader::{EgzReaderBuilder, Format};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut r = EgzReaderBuilder::new().build_seekable(Cursor::new(b"Hello!"))?;
+    /// assert_eq!(r.format(), Some(Format::Raw));
+    ///
+    /// let mut s = String::new();
+    /// r.read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn build_seekable<R: Read + Seek>(&self, mut r: R) -> Result<EgzReader<R>> {
+        let start = r.stream_position()?;
+        let fallback = if self.opts.fallback {
+            Some(Fallback {
+                rewind: rewind::<R>,
+                start,
+                opts: self.opts.clone(),
+            })
+        } else {
+            None
+        };
+        if self.opts.force.is_some() {
+            let mut egz = self.build(r);
+            egz.fallback = fallback;
+            return Ok(egz);
+        }
+
+        let mut buf = Vec::new();
+        let result = ReaderType::preread_all(&mut r, &self.opts, &mut buf);
+        r.seek(SeekFrom::Start(start))?;
+        result?;
+
+        let format = ReaderType::<R>::detect_format(&buf, &self.opts);
+        let custom = self.opts.decoder_of(format, &buf);
+        let mut egz = self.build(r);
+        if let ReaderType::Init(r, opts, buf) = mem::replace(&mut egz.reader, ReaderType::Moving) {
+            egz.reader = ReaderType::make_reader_as(format, custom, buf, r, &opts);
+        }
+        egz.fallback = fallback;
+        Ok(egz)
+    }
+
+    /// Builds an [EgzReader](EgzReader) reading `prefix` followed by `r` as one stream.
+    ///
+    /// See [EgzReader::with_initial_bytes](EgzReader::with_initial_bytes).
+    pub fn build_with_initial_bytes<R: Read>(&self, prefix: Vec<u8>, r: R) -> EgzReader<R> {
+        self.build_from(ReaderType::Init(r, self.opts.clone(), prefix))
+    }
+
+    /// Creates a [PushDecoder](PushDecoder) with the configured options.
+    ///
+    /// The options of reading the underlying reader, e.g.
+    /// [sniff_timeout](EgzReaderBuilder::sniff_timeout), are not used.
+    ///
+    /// # Examples
+    /// ```
+    /// use egzreader::EgzReaderBuilder;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let gz = std::fs::read("examples/hello.txt.gz")?;
+    /// let mut d = EgzReaderBuilder::new().multi_member(true).build_push();
+    ///
+    /// let mut out = d.feed(&gz)?;
+    /// out.extend(d.feed(&gz)?);
+    /// out.extend(d.finish()?);
+    /// assert_eq!(out, b"Hello!Hello!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn build_push(&self) -> PushDecoder {
+        // Only the decoders which resume after WouldBlock are used, as in nested levels.
+        let opts = Options {
+            nested: true,
+            compressed_rate: None,
+            retry: None,
+            sniff_timeout: None,
+            buffer_bounds: None,
+            ..self.opts.clone()
+        };
+        PushDecoder::from_reader(self.build_from(ReaderType::Init(
+            Pipe::default(),
+            opts,
+            Vec::new(),
+        )))
+    }
+
+    // Creates an EgzReader of `reader` in an initial state.
+    fn build_from<R: Read>(&self, reader: ReaderType<R>) -> EgzReader<R> {
+        let text = if self.opts.crlf || self.opts.utf8 {
+            Some(TextFilter::new(self.opts.crlf, self.opts.utf8))
+        } else {
+            None
+        };
+
+        EgzReader {
+            reader,
+            text,
+            pipeline: self.stages.pipeline(),
+            limiter: self.opts.decoded_rate.map(Limiter::new),
+            size_limit: self.opts.max_decoded_size,
+            fallback: None,
+            builder: self.clone(),
+            peeked: Vec::new(),
+            peeked_pos: 0,
+        }
+    }
+
+    /// Detects gzip by the magic number and the compression method (3 bytes),
+    /// even if the stream is shorter than the 10-byte gzip header. Disabled by default.
+    ///
+    /// Such a stream is a truncated gzip stream, so reading it fails with the error of
+    /// the gzip decoder, instead of passing it through as non-gzip.
+    pub fn lenient_length(mut self, lenient: bool) -> EgzReaderBuilder {
+        self.opts.lenient_length = lenient;
+        self
+    }
+
+    /// Validates the whole gzip header before decoding a stream as gzip. Disabled by default.
+    ///
+    /// The compression method must be 8 (deflate), the reserved flags must be zero,
+    /// and the header CRC must match if present. A stream failing the validation,
+    /// or having a header longer than 64 KiB, is passed through as non-gzip.
+    /// Without this, any compression method up to 8 is accepted, which occasionally
+    /// misclassifies binary files.
+    pub fn strict(mut self, strict: bool) -> EgzReaderBuilder {
+        self.opts.strict = strict;
+        self
+    }
+
+    /// Requires a BGZF stream to end with the 28-byte EOF block. Disabled by default.
+    ///
+    /// A BGZF stream without the EOF block fails with `UnexpectedEof`
+    /// ("missing BGZF EOF block"), which catches incomplete transfers of e.g. BAM files.
+    /// Many tools write BGZF without the EOF block, so it is optional by default.
+    /// This is ignored in the [live](EgzReaderBuilder::live) mode.
+    pub fn require_bgzf_eof(mut self, require: bool) -> EgzReaderBuilder {
+        self.opts.bgzf_eof = require;
+        self
+    }
+
+    /// Decodes BGZF blocks on `threads` threads, as [BgzfReader::threads] does.
+    /// 1, i.e. the calling thread, by default.
+    pub fn bgzf_threads(mut self, threads: usize) -> EgzReaderBuilder {
+        self.opts.bgzf_threads = Some(threads);
+        self
+    }
+
+    /// Decodes all members of a multi-member gzip stream, e.g. gzip files concatenated
+    /// by `cat` or written by pigz. Disabled by default, when only the first member is
+    /// decoded and the bytes after it are ignored.
+    ///
+    /// [as_gz](EgzReader::as_gz) and [into_gz_decoder](EgzReader::into_gz_decoder)
+    /// give no decoder in this mode. BGZF streams are always decoded to the end.
+    pub fn multi_member(mut self, multi_member: bool) -> EgzReaderBuilder {
+        self.opts.multi_member = multi_member;
+        self
+    }
+
+    /// Decodes a stream compressed twice or more, e.g. `.gz.gz` or `.tar.gz.gz` made by
+    /// mistake in upload pipelines, up to `depth` levels. 1 by default, i.e. the decoded
+    /// stream is not detected again.
+    ///
+    /// The decoded stream of each level is detected again as gzip, or zlib and deflate
+    /// if enabled, and BGZF. Other formats are not detected in nested levels.
+    /// The limit guards against pathological inputs.
+    ///
+    /// # Panics
+    /// Panics if `depth` is 0.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReaderBuilder;
+    /// use flate2::{write::GzEncoder, Compression};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut data = b"Hello!".to_vec();
+    /// for _ in 0..2 {
+    ///     let mut e = GzEncoder::new(Vec::new(), Compression::default());
+    ///     e.write_all(&data)?;
+    ///     data = e.finish()?;
+    /// }
+    ///
+    /// let mut s = String::new();
+    /// EgzReaderBuilder::new()
+    ///     .max_depth(3)
+    ///     .build(&data[..])
+    ///     .read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_depth(mut self, depth: usize) -> EgzReaderBuilder {
+        assert!(0 < depth, "depth must be at least 1");
+        self.opts.depth = depth;
+        self
+    }
+
+    /// Decides how bytes following the last gzip member are treated.
+    /// [Ignore](TrailingData::Ignore) by default.
+    ///
+    /// With [multi_member](EgzReaderBuilder::multi_member), bytes beginning with
+    /// the gzip magic number are decoded as the next member instead.
+    pub fn trailing_data(mut self, policy: TrailingData) -> EgzReaderBuilder {
+        self.opts.trailing = policy;
+        self
+    }
+
+    /// Passes a stream through as it is, if it looks like gzip but fails to be decoded.
+    /// Disabled by default.
+    ///
+    /// This is for files which happen to begin with the gzip magic number.
+    /// It takes effect on readers created by [build_seekable](EgzReaderBuilder::build_seekable),
+    /// which seek back to the start of the stream on a decoding error.
+    /// The stream is passed through only if the error occurs before any decoded byte
+    /// is returned; an error after that is returned as usual.
+    pub fn fallback_to_raw(mut self, fallback: bool) -> EgzReaderBuilder {
+        self.opts.fallback = fallback;
+        self
+    }
+
+    /// Detects gzip stream encoded by base64 (beginning with `H4sI`) and decodes it.
+    /// Disabled by default.
+    ///
+    /// This is for gzip payloads delivered in text, e.g. JSON fields of log or queue systems.
+    /// Both standard and URL-safe alphabets are accepted, and whitespaces are skipped.
+    pub fn base64(mut self, base64: bool) -> EgzReaderBuilder {
+        self.opts.base64 = base64;
+        self
+    }
+
+    /// Detects zlib streams (e.g. `0x78 0x9c`, used by git objects and many protocols)
+    /// and decodes them. Disabled by default.
+    ///
+    /// A stream beginning with a valid zlib header, i.e. the compression method, the window
+    /// size and FCHECK, is decoded as zlib, so an error of a corrupt or truncated stream,
+    /// including an Adler-32 mismatch, is returned instead of passing the stream through.
+    /// Note that 1 in 31 pairs of bytes is such a header, e.g. `x^` of text.
+    pub fn zlib(mut self, zlib: bool) -> EgzReaderBuilder {
+        self.opts.zlib = zlib;
+        self
+    }
+
+    /// Gives the preset dictionary of zlib streams, with the `zlib-dictionary` feature.
+    ///
+    /// A zlib stream with FDICT, whose DICTID is the Adler-32 of `dictionary`, is also
+    /// detected by [zlib](EgzReaderBuilder::zlib), and decoded with the dictionary.
+    /// The feature uses the zlib-rs backend of flate2, since the default backend can not
+    /// set a dictionary to the decoder.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReaderBuilder;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// // zlib of "Hello, World!" with the preset dictionary "Hello, "
+    /// let data: &[u8] = &[
+    ///     0x78, 0xbb, 0x09, 0xee, 0x02, 0x41, 0xf3, 0x80, 0x50, 0xe1, 0xf9, 0x45, 0x39, 0x29, 0x8a,
+    ///     0x00, 0x1f, 0x9e, 0x04, 0x6a,
+    /// ];
+    /// let mut s = String::new();
+    /// EgzReaderBuilder::new()
+    ///     .zlib(true)
+    ///     .zlib_dictionary(b"Hello, ")
+    ///     .build(data)
+    ///     .read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello, World!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "zlib-dictionary")]
+    pub fn zlib_dictionary(mut self, dictionary: &[u8]) -> EgzReaderBuilder {
+        self.opts.dictionary = Some(ZlibDictionary::new(dictionary));
+        self
+    }
+
+    /// Reads the first entry of a ZIP archive, instead of the archive itself.
+    /// Disabled by default.
+    ///
+    /// Entries must be stored or deflated, and are read from the local headers in order,
+    /// so the underlying reader need not be seekable. The CRC-32 of the entry is verified.
+    #[cfg(feature = "zip")]
+    pub fn zip(mut self, zip: bool) -> EgzReaderBuilder {
+        self.opts.zip = zip;
+        self
+    }
+
+    /// Detects and decodes the formats of `registry`,
+    /// when a stream is none of the built-in formats.
+    pub fn formats(mut self, registry: FormatRegistry) -> EgzReaderBuilder {
+        self.opts.formats = registry;
+        self
+    }
+
+    /// Decides the format of a stream by `detector` before the built-in detection.
+    /// Not set by default.
+    ///
+    /// `detector` is given the bytes read ahead for detection, which fill the
+    /// [sniff window](EgzReaderBuilder::sniff_window) unless the stream is shorter,
+    /// and is not called for an empty stream.
+    /// Returning `None` leaves the stream to the built-in detection.
+    /// [Custom](Format::Custom) decodes the stream by the matching format of
+    /// [formats](EgzReaderBuilder::formats), or passes it through if none matches.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::{EgzReaderBuilder, Format};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// // Never decode streams beginning with "#!", even if they look compressed.
+    /// let mut r = EgzReaderBuilder::new()
+    ///     .detector(|magic: &[u8]| magic.starts_with(b"#!").then(|| Format::Raw))
+    ///     .build(&b"#!/bin/sh"[..]);
+    /// let mut s = String::new();
+    /// r.read_to_string(&mut s)?;
+    /// assert_eq!(s, "#!/bin/sh");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn detector<F>(mut self, detector: F) -> EgzReaderBuilder
+    where
+        F: Fn(&[u8]) -> Option<Format> + Send + Sync + 'static,
+    {
+        self.opts.detector = Some(Detector::new(detector));
+        self
+    }
+
+    /// Decodes a raw deflate stream (without gzip or zlib wrapper), e.g. an HTTP body
+    /// sent with `Content-Encoding: deflate` by some servers. Disabled by default.
+    ///
+    /// Raw deflate has no magic number, so a non-gzip stream is decoded as deflate
+    /// only if its first 4 KiB are inflated without an error.
+    /// Otherwise the stream is passed through.
+    pub fn deflate(mut self, deflate: bool) -> EgzReaderBuilder {
+        self.opts.deflate = deflate;
+        self
+    }
+
+    /// Limits the total size of the decoded output to `bytes`, e.g. to guard against
+    /// decompression bombs. Not limited by default.
+    ///
+    /// Reading beyond the limit fails with [InvalidData](std::io::ErrorKind::InvalidData).
+    pub fn max_decoded_size(mut self, bytes: u64) -> EgzReaderBuilder {
+        self.opts.max_decoded_size = Some(bytes);
+        self
+    }
+
+    /// Limits the decoded output to `bytes_per_sec` bytes per second, by sleeping in `read()`.
+    /// Not limited by default.
+    ///
+    /// # Panics
+    /// Panics if `bytes_per_sec` is zero.
+    pub fn max_decoded_rate(mut self, bytes_per_sec: u64) -> EgzReaderBuilder {
+        assert!(0 < bytes_per_sec, "rate must be positive");
+        self.opts.decoded_rate = Some(bytes_per_sec);
+        self
+    }
+
+    /// Limits reading the underlying reader to `bytes_per_sec` bytes per second,
+    /// by sleeping in `read()`. Not limited by default.
+    ///
+    /// This caps the load on the source (e.g. a shared file system) regardless of
+    /// the compression ratio.
+    ///
+    /// # Panics
+    /// Panics if `bytes_per_sec` is zero.
+    pub fn max_compressed_rate(mut self, bytes_per_sec: u64) -> EgzReaderBuilder {
+        assert!(0 < bytes_per_sec, "rate must be positive");
+        self.opts.compressed_rate = Some(bytes_per_sec);
+        self
+    }
+
+    /// Retries reading the underlying reader on transient errors following `policy`.
+    /// Errors are not retried by default.
+    ///
+    /// See [RetryPolicy](RetryPolicy) for details.
+    pub fn retry(mut self, policy: RetryPolicy) -> EgzReaderBuilder {
+        self.opts.retry = Some(policy);
+        self
+    }
+
+    /// Gives up accumulating the bytes for the format detection after `timeout`,
+    /// and passes through the bytes arrived so far as a non-gzip stream.
+    /// Not limited by default.
+    ///
+    /// The underlying reader is expected to return `WouldBlock` or `TimedOut` when no data arrives
+    /// (e.g. a non-blocking pipe or a socket with a read timeout), which is polled until `timeout`.
+    /// A blocking `read()` can not be interrupted, so the timeout is checked between reads.
+    /// If nothing has arrived at the timeout, the error is returned and detection is tried again
+    /// at the next read.
+    pub fn sniff_timeout(mut self, timeout: Duration) -> EgzReaderBuilder {
+        self.opts.sniff_timeout = Some(timeout);
+        self
+    }
+
+    /// Reads `bytes` bytes ahead for the format detection, instead of 11 bytes.
+    ///
+    /// A larger window gives more bytes to a [detector](EgzReaderBuilder::detector)
+    /// or the magic bytes of [formats](EgzReaderBuilder::formats).
+    /// A smaller window lets a short message be decoded without waiting for more bytes,
+    /// detecting gzip by the first 3 bytes. Then a stream shorter than the gzip header
+    /// is decoded as gzip and fails, as with [lenient_length](EgzReaderBuilder::lenient_length).
+    ///
+    /// # Panics
+    /// Panics if `bytes` is less than 3, the length of the gzip magic number and
+    /// the compression method.
+    pub fn sniff_window(mut self, bytes: usize) -> EgzReaderBuilder {
+        assert!(3 <= bytes, "sniff window must be at least 3 bytes");
+        self.opts.sniff_window = Some(bytes);
+        self
+    }
+
+    /// Reads ahead from the underlying reader with a buffer whose size adapts to the observed reads,
+    /// between `min` and `max` bytes. Not buffered by default.
+    ///
+    /// The buffer grows while the underlying reader fills it, which reduces calls for bulk transfers,
+    /// and shrinks while the reader returns much less, e.g. for trickling streams.
+    ///
+    /// # Panics
+    /// Panics if `min` is zero or greater than `max`.
+    pub fn adaptive_buffer(mut self, min: usize, max: usize) -> EgzReaderBuilder {
+        assert!(0 < min && min <= max, "invalid buffer bounds");
+        self.opts.buffer_bounds = Some((min, max));
+        self
+    }
+
+    /// Stacks a [Transform](Transform) on the decoded output.
+    ///
+    /// `factory` is called to create the transform for each reader built.
+    /// Transforms are applied in the order they are added, after the text mode.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReaderBuilder;
+    ///
+    /// fn upper(input: &[u8], out: &mut Vec<u8>) -> std::io::Result<()> {
+    ///     out.extend(input.to_ascii_uppercase());
+    ///     Ok(())
+    /// }
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let builder = EgzReaderBuilder::new()
+    ///     .text(true)
+    ///     .post_transform(|| upper);
+    ///
+    /// let mut s = String::new();
+    /// builder.build(&b"Hello!\r\n"[..]).read_to_string(&mut s)?;
+    /// assert_eq!(s, "HELLO!\n");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn post_transform<F, T>(mut self, factory: F) -> EgzReaderBuilder
+    where
+        F: Fn() -> T + Send + Sync + 'static,
+        T: Transform + Send + Sync + 'static,
+    {
+        self.stages.push(factory);
+        self
+    }
+
+    /// Installs a transform applied to the underlying reader before detection,
+    /// e.g. a decryption layer or a de-framing step.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::io::prelude::*;
+    /// use egzreader::EgzReaderBuilder;
+    ///
+    /// // A toy "decryption" layer.
+    /// struct Xor<R>(R);
+    /// impl<R: Read> Read for Xor<R> {
+    ///     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    ///         let n = self.0.read(buf)?;
+    ///         buf[..n].iter_mut().for_each(|b| *b ^= 0xff);
+    ///         Ok(n)
+    ///     }
+    /// }
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let builder = EgzReaderBuilder::new().pre_transform(Xor);
+    ///
+    /// let data: Vec<u8> = b"Hello!".iter().map(|b| b ^ 0xff).collect();
+    /// let mut s = String::new();
+    /// builder.build(&data[..]).read_to_string(&mut s)?;
+    /// assert_eq!(s, "Hello!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pre_transform<F>(self, transform: F) -> PreTransformBuilder<F> {
+        PreTransformBuilder {
+            builder: self,
+            transform,
+        }
+    }
+}
+
+/// A builder with a transform applied before detection.
+///
+/// This is created by [EgzReaderBuilder::pre_transform](EgzReaderBuilder::pre_transform).
+#[derive(Clone)]
+pub struct PreTransformBuilder<F> {
+    builder: EgzReaderBuilder,
+    transform: F,
+}
+
+impl<F> PreTransformBuilder<F> {
+    /// Creates an [EgzReader](EgzReader) over the transformed reader.
+    pub fn build<R, T>(&self, r: R) -> EgzReader<T>
+    where
+        F: Fn(R) -> T,
+        T: Read,
+    {
+        self.builder.build((self.transform)(r))
+    }
+}
+impl<F> fmt::Debug for PreTransformBuilder<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreTransformBuilder")
+            .field("builder", &self.builder)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Cursor, ErrorKind, Read, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use flate2::read::{GzDecoder, MultiGzDecoder};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::test_support::ShortRead;
+
+    use super::{
+        DynEgzReader, DynSyncEgzReader, EgzError, EgzReader, EgzReaderBuilder, ExtensionPolicy,
+        Format, FormatRegistry, ReaderType, RetryPolicy, TrailingData,
+    };
+
+    // Reader which fails with ConnectionReset after all data is read.
+    struct Broken<'a>(&'a [u8]);
+    impl Read for Broken<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                Err(ErrorKind::ConnectionReset.into())
+            } else {
+                self.0.read(buf)
+            }
+        }
+    }
+
+    // "Hello!"
+    const HELLO: &[u8] = &[0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x21];
+
+    // "Hello!" encoded by gzip
+    const HELLO_GZ: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0xeb, 0x47, 0x74, 0x60, 0x00, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0x04, 0x00, 0x56, 0xcc, 0x2a, 0x9d, 0x06, 0x00, 0x00, 0x00,
+    ];
+
+    // Zero-byte file encoded by gzip
+    const ZERO_GZ: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x08, 0x32, 0xb1, 0x90, 0x63, 0x00, 0x03, 0x7a, 0x65, 0x72, 0x6f, 0x00,
+        0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn read_zero() {
+        let data: &[u8] = &[0; 0];
+        let mut r = EgzReader::new(data);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "");
+    }
+    #[test]
+    fn read_zero_gz() {
+        let mut r = EgzReader::new(ZERO_GZ);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "");
+    }
+    #[test]
+    fn read_long() {
+        let data: &[u8] = &[0x41; 20];
+        let mut r = EgzReader::new(data);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "AAAAAAAAAAAAAAAAAAAA");
+    }
+    #[test]
+    fn read_hello_txt() {
+        let mut r = EgzReader::new(HELLO);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn read_hello_gz() {
+        let mut r = EgzReader::new(HELLO_GZ);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn read_fake_gz() {
+        let mut r = EgzReader::new(&HELLO_GZ[..10]);
+        let mut buf = [0; 11];
+        let n = r.read(&mut buf).unwrap();
+        assert_eq!(buf[..n], HELLO_GZ[..10]);
+    }
+    #[test]
+    fn read_short_gz() {
+        let builder = EgzReaderBuilder::new().lenient_length(true);
+        let mut r = builder.build(&HELLO_GZ[..10]);
+        let e = r.read(&mut [0; 11]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(r.format(), Some(Format::Gzip));
+
+        // Too short to have the compression method
+        let mut out = Vec::new();
+        builder
+            .build(&b"\x1f\x8b"[..])
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, b"\x1f\x8b");
+    }
+    #[test]
+    fn read_strict() {
+        let builder = EgzReaderBuilder::new().strict(true);
+        let mut s = String::new();
+        builder.build(HELLO_GZ).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        // Invalid compression method, reserved flags and header CRC
+        let mut fhcrc = HELLO_GZ[..10].to_vec();
+        fhcrc[3] |= 0x02;
+        fhcrc.extend_from_slice(&[0, 0]);
+        fhcrc.extend_from_slice(&HELLO_GZ[10..]);
+        for (i, b) in [(2, 0x07), (3, 0x20)].iter() {
+            let mut data = HELLO_GZ.to_vec();
+            data[*i] = *b;
+            let mut out = Vec::new();
+            builder.build(&data[..]).read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+        }
+        let mut out = Vec::new();
+        builder.build(&fhcrc[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, fhcrc);
+        assert!(EgzReader::new(&fhcrc[..]).read_to_end(&mut out).is_err());
+    }
+    #[test]
+    fn read_truncated_gz() {
+        let mut r = EgzReader::new(&HELLO_GZ[..HELLO_GZ.len() - 8]);
+        let mut s = String::new();
+        let e = r.read_to_string(&mut s).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+    #[test]
+    fn read_live_gz() {
+        // A gzip stream flushed by Z_SYNC_FLUSH without the trailer.
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        e.flush().unwrap();
+        let data = e.get_ref().clone();
+
+        let mut r = EgzReaderBuilder::new().live(true).build(&data[..]);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn read_live_complete_gz() {
+        let mut r = EgzReaderBuilder::new().live(true).build(HELLO_GZ);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn replace_inner_gz() {
+        let mut r = EgzReader::new(Broken(&HELLO_GZ[..15]));
+        let mut s = String::new();
+        let e = r.read_to_string(&mut s).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ConnectionReset);
+
+        r.replace_inner(Broken(&HELLO_GZ[15..]));
+        let mut buf = [0; 6];
+        r.read_exact(&mut buf[s.len()..]).unwrap();
+        assert_eq!(&buf[s.len()..], &HELLO[s.len()..]);
+    }
+    #[test]
+    fn replace_inner_raw() {
+        let data: &[u8] = &[0x41; 20];
+        let mut r = EgzReader::new(Broken(&data[..12]));
+        let mut buf = [0; 12];
+        r.read_exact(&mut buf).unwrap();
+
+        r.replace_inner(Broken(&data[12..]));
+        let mut buf = [0; 8];
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0x41; 8]);
+    }
+    #[test]
+    fn replace_inner_init() {
+        let mut r = EgzReader::new(&HELLO[..0]);
+        r.replace_inner(HELLO_GZ);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn reset() {
+        let mut r = EgzReaderBuilder::new().max_decoded_size(6).build(HELLO_GZ);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+
+        // The gzip decoder is kept for the next gzip stream.
+        assert_eq!(r.reset(HELLO_GZ), b"");
+        assert!(matches!(r.reader, ReaderType::Reset(..)));
+        assert_eq!(r.detect().unwrap(), Format::Gzip);
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!Hello!");
+
+        // Non-gzip, in the middle of a stream with peeked bytes
+        r.reset(HELLO);
+        assert_eq!(r.peek_decoded(2).unwrap(), b"He");
+        assert_eq!(r.reset(HELLO_GZ), b"");
+        r.reset(HELLO);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!((r.format(), &s[..]), (Some(Format::Raw), "Hello!"));
+
+        // A truncated stream, and a stream over the size limit
+        r.reset(&HELLO_GZ[..18]);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+        r.reset(HELLO_GZ);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+        let data = [HELLO, HELLO].concat();
+        r.reset(&data[..]);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+
+        // Before the detection
+        let mut r = EgzReader::new(HELLO_GZ);
+        r.reset(HELLO_GZ);
+        assert_eq!(r.into_members().unwrap().count(), 1);
+        let mut r = EgzReader::from(GzDecoder::new(HELLO_GZ));
+        r.reset(HELLO);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn read_dyn_ref() {
+        fn read_unsized<R: Read + ?Sized>(r: &mut R) -> String {
+            let mut s = String::new();
+            EgzReader::new(r).read_to_string(&mut s).unwrap();
+            s
+        }
+
+        let mut data = HELLO_GZ;
+        let r: &mut dyn Read = &mut data;
+        assert_eq!(read_unsized(r), "Hello!");
+    }
+    #[test]
+    fn read_dyn_box() {
+        let r: Box<dyn Read> = Box::new(HELLO_GZ);
+        let mut r = EgzReader::new(r);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn read_text_gz() {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!\r\nHello!\r\n").unwrap();
+        let data = e.finish().unwrap();
+
+        let mut r = EgzReaderBuilder::new()
+            .text(true)
+            .validate_utf8(true)
+            .build(&data[..]);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!\nHello!\n");
+    }
+    #[test]
+    fn into_gz_decoder() {
+        let mut r = EgzReader::new(HELLO_GZ);
+        let mut buf = [0; 2];
+        r.read_exact(&mut buf).unwrap();
+
+        let mut d = r.into_gz_decoder().unwrap();
+        let mut s = String::new();
+        d.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "llo!");
+    }
+    #[test]
+    fn as_gz() {
+        let mut r = EgzReader::new(HELLO_GZ);
+        r.detect().unwrap();
+        assert!(r.as_gz().is_some());
+
+        let data = [HELLO_GZ, HELLO_GZ].concat();
+        let mut r = EgzReaderBuilder::new().multi_member(true).build(&data[..]);
+        r.detect().unwrap();
+        assert!(r.as_gz().is_none());
+        assert!(r.gz_header().is_some());
+
+        let z = crate::bgzf::tests::bgzf(HELLO, 1000);
+        let mut r = EgzReader::new(&z[..]);
+        r.detect().unwrap();
+        assert!(r.as_gz().is_none());
+        assert!(r.gz_header().is_some());
+
+        let mut r = EgzReader::from(GzDecoder::new(HELLO_GZ));
+        assert!(r.as_gz().is_none());
+    }
+    #[test]
+    fn into_gz_decoder_raw() {
+        let r = EgzReader::new(HELLO_GZ);
+        let mut r = r.into_gz_decoder().unwrap_err();
+        let mut buf = [0; 2];
+        r.read_exact(&mut buf).unwrap();
+
+        let mut r = r.into_gz_decoder().unwrap();
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "llo!");
+
+        let mut r = EgzReader::new(HELLO);
+        r.read_exact(&mut buf).unwrap();
+        assert!(r.into_gz_decoder().is_err());
+    }
+    #[test]
+    fn try_from_egz_reader() {
+        use std::convert::TryFrom;
+        // Readers from flate2 decoders are converted back by TryFrom, not into_gz_decoder.
+        let mut r = EgzReader::from(GzDecoder::new(HELLO_GZ));
+        let mut buf = [0; 2];
+        r.read_exact(&mut buf).unwrap();
+        let r = r.into_gz_decoder().unwrap_err();
+        let mut d = GzDecoder::try_from(r).unwrap();
+        let mut s = String::new();
+        d.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "llo!");
+
+        let data = [HELLO_GZ, HELLO_GZ].concat();
+        let r = EgzReader::from(MultiGzDecoder::new(&data[..]));
+        let r = GzDecoder::try_from(r).err().unwrap();
+        let mut d = MultiGzDecoder::try_from(r).unwrap();
+        let mut s = String::new();
+        d.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!Hello!");
+
+        assert!(MultiGzDecoder::try_from(EgzReader::new(HELLO_GZ)).is_err());
+    }
+    #[test]
+    fn into_parts() {
+        let (format, rest, inner) = EgzReader::new(HELLO_GZ).into_parts();
+        assert_eq!((format, &rest[..], inner), (None, &b""[..], HELLO_GZ));
+
+        // Bytes read for the detection before an error
+        let mut r = EgzReader::new(Broken(&HELLO_GZ[..4]));
+        assert!(r.read(&mut [0; 4]).is_err());
+        let (format, rest, _) = r.into_parts();
+        assert_eq!((format, &rest[..]), (None, &HELLO_GZ[..4]));
+
+        // A gzip member followed by the next message
+        let data = [HELLO_GZ, b"next"].concat();
+        let mut r = EgzReader::new(&data[..]);
+        r.read_to_end(&mut Vec::new()).unwrap();
+        let (format, _, _) = r.into_parts();
+        assert_eq!(format, Some(Format::Gzip));
+    }
+    #[test]
+    fn into_inner() {
+        let data = [HELLO, HELLO].concat();
+        let mut r = EgzReader::new(Cursor::new(&data));
+        r.read_exact(&mut [0; 2]).unwrap();
+        // The bytes read for the detection are lost.
+        assert_eq!(r.into_inner().position(), 11);
+
+        let r = EgzReader::new(Cursor::new(&data));
+        assert_eq!(r.into_inner().position(), 0);
+    }
+    #[test]
+    fn buf_read() {
+        let data: Vec<u8> = (0..2000)
+            .flat_map(|i| format!("line {}\n", i).into_bytes())
+            .collect();
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&data).unwrap();
+        let gz = e.finish().unwrap();
+
+        let mut r = EgzReader::new(&gz[..]);
+        let mut line = String::new();
+        r.read_line(&mut line).unwrap();
+        assert_eq!(line, "line 0\n");
+        assert_eq!(r.peek_decoded(6).unwrap(), b"line 1");
+        let mut buf = [0; 7];
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"line 1\n");
+        let lines: Vec<String> = r.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines.len(), 1998);
+        assert_eq!(lines[1997], "line 1999");
+
+        let mut r = EgzReader::new(&b"a\nb"[..]);
+        let mut out = Vec::new();
+        r.read_until(b'\n', &mut out).unwrap();
+        assert_eq!(out, b"a\n");
+        assert_eq!(r.fill_buf().unwrap(), b"b");
+        r.consume(1);
+        assert_eq!(r.fill_buf().unwrap(), b"");
+    }
+    #[test]
+    fn lines() {
+        let lines: Vec<String> = EgzReader::new(HELLO_GZ)
+            .lines()
+            .map(|l| l.unwrap())
+            .collect();
+        assert_eq!(lines, ["Hello!"]);
+
+        let mut lines = EgzReader::new(&b"a\r\nb\n\nc"[..]).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "a");
+        assert_eq!(lines.next().unwrap().unwrap(), "b");
+        assert_eq!(lines.next().unwrap().unwrap(), "");
+        assert_eq!(lines.next().unwrap().unwrap(), "c");
+        assert!(lines.next().is_none());
+
+        let e = EgzReader::new(&HELLO_GZ[..18]).lines().next().unwrap();
+        assert!(e.is_err());
+    }
+    #[test]
+    fn gz_header() {
+        let mut e = flate2::GzBuilder::new()
+            .filename("a.log")
+            .comment("logs")
+            .mtime(1_600_000_000)
+            .operating_system(3)
+            .write(Vec::new(), Compression::default());
+        e.write_all(HELLO).unwrap();
+        let gz = e.finish().unwrap();
+
+        let mut r = EgzReader::new(&gz[..]);
+        assert!(r.gz_header().is_none());
+        r.detect().unwrap();
+        let header = r.gz_header().unwrap();
+        assert_eq!(header.filename(), Some(&b"a.log"[..]));
+        assert_eq!(header.comment(), Some(&b"logs"[..]));
+        assert_eq!(header.mtime(), 1_600_000_000);
+        assert_eq!(header.os(), 3);
+
+        // The header of the last member after the end
+        let data = [HELLO_GZ, &gz].concat();
+        let mut r = EgzReaderBuilder::new().multi_member(true).build(&data[..]);
+        r.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(r.gz_header().unwrap().filename(), Some(&b"a.log"[..]));
+
+        // BGZF blocks
+        let z = crate::bgzf::tests::bgzf(b"Hello, World!", 1000);
+        let mut r = EgzReader::new(&z[..]);
+        assert_eq!(r.detect().unwrap(), Format::Bgzf);
+        let header = r.gz_header().unwrap();
+        assert_eq!(header.subfield(*b"BC"), Some(&z[16..18]));
+        assert_eq!(header.os(), 0xff);
+        r.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(r.gz_header(), Some(header));
+
+        let mut r = EgzReader::new(HELLO);
+        r.detect().unwrap();
+        assert!(r.gz_header().is_none());
+    }
+    #[test]
+    fn egz_error() {
+        let class_with = |builder: &EgzReaderBuilder, data: &[u8]| {
+            let e = builder
+                .build(data)
+                .read_to_end(&mut Vec::new())
+                .unwrap_err();
+            let kind = e.kind();
+            match EgzError::of(&e).unwrap() {
+                EgzError::Corrupt(format, _) => ("corrupt", *format, kind),
+                EgzError::Checksum(format, _) => ("checksum", *format, kind),
+                EgzError::UnexpectedEof(format, _) => ("eof", *format, kind),
+                EgzError::Detection(_) => unreachable!(),
+            }
+        };
+        let class = |data: &[u8]| class_with(&EgzReaderBuilder::new(), data);
+        let mut data = HELLO_GZ.to_vec();
+        data[10] = 0xff;
+        assert_eq!(
+            class(&data),
+            ("corrupt", Format::Gzip, ErrorKind::InvalidInput)
+        );
+        let mut data = HELLO_GZ.to_vec();
+        data[18] ^= 1;
+        assert_eq!(
+            class(&data),
+            ("checksum", Format::Gzip, ErrorKind::InvalidInput)
+        );
+        assert_eq!(
+            class(&HELLO_GZ[..18]),
+            ("eof", Format::Gzip, ErrorKind::UnexpectedEof)
+        );
+        let builder = EgzReaderBuilder::new().trailing_data(TrailingData::Error);
+        let data = [HELLO_GZ, b"garbage"].concat();
+        assert_eq!(
+            class_with(&builder, &data),
+            ("corrupt", Format::Gzip, ErrorKind::InvalidData)
+        );
+
+        let z = crate::bgzf::tests::bgzf(b"Hello, world!", 1000);
+        let mut data = z.clone();
+        data[18] = 0xff;
+        assert_eq!(
+            class(&data),
+            ("corrupt", Format::Bgzf, ErrorKind::InvalidData)
+        );
+        let mut data = z.clone();
+        let block_len = u16::from_le_bytes([z[16], z[17]]) as usize + 1;
+        data[block_len - 8] ^= 1;
+        assert_eq!(
+            class(&data),
+            ("checksum", Format::Bgzf, ErrorKind::InvalidData)
+        );
+        assert_eq!(
+            class(&z[..block_len - 4]),
+            ("eof", Format::Bgzf, ErrorKind::UnexpectedEof)
+        );
+
+        let e = EgzReader::new(Broken(&HELLO_GZ[..4]))
+            .read(&mut [0; 4])
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ConnectionReset);
+        let class = EgzError::of(&e).unwrap();
+        assert!(matches!(class, EgzError::Detection(_)));
+        assert_eq!(class.format(), None);
+
+        // Errors not of decoding are given as they are.
+        let e = EgzReader::new(Broken(b"Hello, world!"))
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert!(EgzError::of(&e).is_none());
+        let e = EgzReaderBuilder::new()
+            .max_decoded_size(2)
+            .build(HELLO_GZ)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert!(EgzError::of(&e).is_none());
+    }
+    #[test]
+    fn get_ref() {
+        let data = [HELLO, HELLO].concat();
+        let mut r = EgzReader::new(Cursor::new(&data));
+        assert_eq!(r.get_ref().position(), 0);
+        r.read_exact(&mut [0; 2]).unwrap();
+        assert_eq!(r.get_ref().position(), 11);
+
+        let mut r = EgzReader::new(Cursor::new(HELLO_GZ));
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(r.get_ref().position(), HELLO_GZ.len() as u64);
+        r.get_mut().set_position(0);
+        assert_eq!(r.into_inner().position(), 0);
+    }
+    #[test]
+    fn from_gz_decoder() {
+        let mut r = EgzReader::from(GzDecoder::new(HELLO_GZ));
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        let data = [HELLO_GZ, HELLO_GZ].concat();
+        let mut r = EgzReader::from(MultiGzDecoder::new(&data[..]));
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!Hello!");
+    }
+    #[test]
+    fn read_pre_transform() {
+        // De-framing a 4-byte frame header.
+        fn deframe(r: &[u8]) -> &[u8] {
+            &r[4..]
+        }
+        let builder = EgzReaderBuilder::new().pre_transform(deframe);
+
+        let data = [&[0, 0, 0, 26][..], HELLO_GZ].concat();
+        let mut s = String::new();
+        builder.build(&data[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn read_base64_gz() {
+        let data: &[u8] = b"H4sIAOtHdGAAA/NIzcnJVwQAVswqnQYAAAA=";
+
+        let mut r = EgzReaderBuilder::new().base64(true).build(data);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        // Disabled by default
+        let mut r = EgzReader::new(data);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s.as_bytes(), data);
+    }
+    #[test]
+    fn read_rate_limited() {
+        let data = [0x41; 1000];
+        let start = Instant::now();
+        let mut r = EgzReaderBuilder::new()
+            .max_decoded_rate(10_000)
+            .build(&data[..]);
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, &data[..]);
+        assert!(Duration::from_millis(90) <= start.elapsed());
+
+        let start = Instant::now();
+        let mut r = EgzReaderBuilder::new()
+            .max_compressed_rate(200)
+            .build(HELLO_GZ);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+        // 15 bytes after the preread
+        assert!(Duration::from_millis(70) <= start.elapsed());
+    }
+    // Reader failing with ConnectionReset every other read().
+    struct Flaky<'a> {
+        data: &'a [u8],
+        fail: bool,
+    }
+    impl Read for Flaky<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.fail = !self.fail;
+            if self.fail {
+                Err(std::io::Error::from(ErrorKind::ConnectionReset))
+            } else {
+                let n = buf.len().min(3);
+                (&mut self.data).read(&mut buf[..n])
+            }
+        }
+    }
+
+    #[test]
+    fn read_retry() {
+        let policy =
+            RetryPolicy::new(1).backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let builder = EgzReaderBuilder::new().retry(policy);
+        for data in [HELLO, HELLO_GZ].iter() {
+            let flaky = Flaky { data, fail: false };
+            let mut s = String::new();
+            builder.build(flaky).read_to_string(&mut s).unwrap();
+            assert_eq!(s, "Hello!");
+        }
+
+        let flaky = Flaky {
+            data: HELLO_GZ,
+            fail: false,
+        };
+        let mut s = String::new();
+        let e = EgzReader::new(flaky).read_to_string(&mut s).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ConnectionReset);
+    }
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+    #[allow(dead_code)]
+    fn assert_send_sync_generic<R: Read + Send + Sync>() {
+        assert_send::<EgzReader<R>>();
+        assert_sync::<EgzReader<R>>();
+        assert_send::<super::Split<R>>();
+        assert_sync::<super::Split<R>>();
+    }
+
+    #[test]
+    fn send_sync() {
+        assert_send::<EgzReader<std::fs::File>>();
+        assert_sync::<EgzReader<std::fs::File>>();
+        assert_send::<DynEgzReader>();
+        assert_send::<DynSyncEgzReader>();
+        assert_sync::<DynSyncEgzReader>();
+        assert_send::<EgzReaderBuilder>();
+        assert_sync::<EgzReaderBuilder>();
+        assert_send::<super::PushDecoder>();
+        assert_send::<super::EgzFollower>();
+
+        let r: DynEgzReader = EgzReader::new(Box::new(HELLO_GZ));
+        let s = std::thread::spawn(move || {
+            let mut r = r;
+            let mut s = String::new();
+            r.read_to_string(&mut s).unwrap();
+            s
+        })
+        .join()
+        .unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    // Reader returning its chunks one by one, with WouldBlock after the last one.
+    struct Trickle<'a>(Vec<&'a [u8]>);
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::Error::from(ErrorKind::WouldBlock));
+            }
+            let n = self.0[0].read(buf)?;
+            if self.0[0].is_empty() {
+                self.0.remove(0);
+            }
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn read_sniff_timeout() {
+        let builder = EgzReaderBuilder::new().sniff_timeout(Duration::from_millis(20));
+
+        let mut r = builder.build(Trickle(vec![b"Hi"]));
+        let mut buf = [0; 8];
+        let n = r.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"Hi");
+        assert!(r.into_gz_decoder().is_err());
+
+        let mut r = builder.build(Trickle(vec![&HELLO_GZ[..4], &HELLO_GZ[4..]]));
+        let n = r.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"Hello!");
+
+        let mut r = builder.build(Trickle(vec![]));
+        let e = r.read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::WouldBlock);
+    }
+    #[test]
+    fn read_sniff_window() {
+        // A short message is detected without waiting for the default window.
+        let mut buf = [0; 8];
+        let mut r = EgzReader::new(Trickle(vec![b"Hi!"]));
+        let e = r.read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::WouldBlock);
+        let mut r = EgzReaderBuilder::new()
+            .sniff_window(3)
+            .build(Trickle(vec![b"Hi!"]));
+        let n = r.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"Hi!");
+
+        let builder = EgzReaderBuilder::new().sniff_window(3);
+        let mut s = String::new();
+        builder.build(HELLO_GZ).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+        assert!(builder
+            .build(&HELLO_GZ[..8])
+            .read_to_end(&mut Vec::new())
+            .is_err());
+
+        // A detector sees the whole window.
+        let seen = Arc::new(AtomicUsize::new(0));
+        let len = seen.clone();
+        let builder = EgzReaderBuilder::new()
+            .sniff_window(32)
+            .detector(move |magic: &[u8]| {
+                len.store(magic.len(), Ordering::SeqCst);
+                None
+            });
+        assert_eq!(builder.build(&[0; 100][..]).detect().unwrap(), Format::Raw);
+        assert_eq!(seen.load(Ordering::SeqCst), 32);
+    }
+    #[test]
+    fn as_gz_raw() {
+        let mut r = EgzReader::new(HELLO_GZ);
+        assert!(r.as_gz().is_none());
+        let mut buf = [0; 2];
+        r.read_exact(&mut buf).unwrap();
+        assert!(r.as_gz().unwrap().header().is_some());
+        assert!(r.as_raw().is_none());
+
+        let mut r = EgzReader::new(HELLO);
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(r.as_raw().unwrap().buffer(), b"llo!");
+        assert!(r.as_gz().is_none());
+    }
+    #[test]
+    fn peek_decoded() {
+        let mut r = EgzReader::new(HELLO_GZ);
+        assert_eq!(r.peek_decoded(2).unwrap(), b"He");
+        assert_eq!(r.peek_decoded(4).unwrap(), b"Hell");
+        assert!(r.as_gz().is_some());
+
+        let mut buf = [0; 3];
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"Hel");
+        assert_eq!(r.peek_decoded(10).unwrap(), b"lo!");
+
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "lo!");
+        assert_eq!(r.peek_decoded(1).unwrap(), b"");
+
+        let e = r.peek_decoded(usize::MAX).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        let text = "Hello, World!\n".repeat(100_000);
+        let mut r = EgzReader::new(text.as_bytes());
+        assert_eq!(
+            r.peek_decoded(super::PEEK_LIMIT).unwrap(),
+            &text.as_bytes()[..super::PEEK_LIMIT]
+        );
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, text);
+    }
+    #[test]
+    fn read_adaptive_buffer() {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        let text = "Hello, World!\n".repeat(1000);
+        e.write_all(text.as_bytes()).unwrap();
+        let data = e.finish().unwrap();
+
+        let builder = EgzReaderBuilder::new().adaptive_buffer(16, 64 * 1024);
+        for input in [&data[..], text.as_bytes()].iter() {
+            for &max in [1, 100, 100_000].iter() {
+                let mut r = builder.build(ShortRead::new(*input, max));
+                let mut s = String::new();
+                r.read_to_string(&mut s).unwrap();
+                assert_eq!(s, text);
+            }
+        }
+
+        let mut r = builder.build(HELLO);
+        let mut buf = [0; 2];
+        r.read_exact(&mut buf).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(r.as_raw().unwrap().buffer(), b"o!");
+    }
+    #[test]
+    fn read_with_initial_bytes() {
+        for n in 0..HELLO_GZ.len() {
+            let mut r = EgzReader::with_initial_bytes(HELLO_GZ[..n].to_vec(), &HELLO_GZ[n..]);
+            let mut s = String::new();
+            r.read_to_string(&mut s).unwrap();
+            assert_eq!(s, "Hello!");
+        }
+
+        let long = "Hello, World!".repeat(3);
+        let mut r = EgzReader::with_initial_bytes(long.as_bytes().to_vec(), &b"!"[..]);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, long + "!");
+    }
+    #[test]
+    fn read_detection_resumed() {
+        // Bytes read before WouldBlock are kept for the next detection.
+        for data in [HELLO, HELLO_GZ].iter() {
+            for n in 1..data.len().min(11) {
+                let mut r = EgzReader::new(Trickle(vec![&data[..n]]));
+                let mut buf = [0; 8];
+                let e = r.read(&mut buf).unwrap_err();
+                assert_eq!(e.kind(), ErrorKind::WouldBlock);
+                // An empty chunk gives EOF.
+                r.replace_inner(Trickle(vec![&data[n..], b""]));
+
+                let mut out = Vec::new();
+                while out.len() < 6 {
+                    let m = r.read(&mut buf).unwrap();
+                    out.extend_from_slice(&buf[..m]);
+                }
+                assert_eq!(out, b"Hello!");
+            }
+        }
+    }
+    #[test]
+    fn read_deflate() {
+        let builder = EgzReaderBuilder::new().deflate(true);
+        let text = "Hello, World!".repeat(1000);
+        for data in [text.as_bytes(), b"Hello!"].iter() {
+            let mut e = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+            e.write_all(data).unwrap();
+            let deflated = e.finish().unwrap();
+
+            let mut out = Vec::new();
+            builder.build(&deflated[..]).read_to_end(&mut out).unwrap();
+            assert_eq!(out, *data);
+
+            // Passed through without the option.
+            let mut out = Vec::new();
+            EgzReader::new(&deflated[..]).read_to_end(&mut out).unwrap();
+            assert_eq!(out, deflated);
+
+            let mut out = Vec::new();
+            builder.build(*data).read_to_end(&mut out).unwrap();
+            assert_eq!(out, *data);
+        }
+
+        let mut s = String::new();
+        builder.build(HELLO_GZ).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn read_zlib() {
+        let builder = EgzReaderBuilder::new().zlib(true);
+        let mut e = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let data = e.finish().unwrap();
+
+        let mut s = String::new();
+        builder.build(&data[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        // Text beginning with a zlib header is decoded as zlib.
+        let mut r = builder.build(&b"x^2"[..]);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+        assert_eq!(r.format(), Some(Format::Zlib));
+        let mut s = String::new();
+        builder
+            .build(&b"Hello!"[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!");
+
+        // Truncated, and with a corrupt Adler-32
+        let mut r = builder.build(&data[..data.len() - 4]);
+        let e = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            (r.format(), e.kind()),
+            (Some(Format::Zlib), ErrorKind::UnexpectedEof)
+        );
+        let mut corrupt = data.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        let mut r = builder.build(&corrupt[..]);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+        assert_eq!(r.format(), Some(Format::Zlib));
+
+        let mut out = Vec::new();
+        EgzReader::new(&data[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+    }
+    #[cfg(feature = "zlib-dictionary")]
+    #[test]
+    fn read_zlib_dictionary() {
+        let dictionary = b"Hello, ";
+        let data = crate::deflate::tests::zlib_with_dictionary(b"Hello, World!", dictionary);
+        let builder = EgzReaderBuilder::new()
+            .zlib(true)
+            .zlib_dictionary(dictionary);
+        let mut r = builder.build(ShortRead::new(&data[..], 1));
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello, World!");
+        assert_eq!(r.format(), Some(Format::Zlib));
+
+        // Streams requiring other dictionaries are not detected.
+        let other = crate::deflate::tests::zlib_with_dictionary(b"Hello, World!", b"Hi");
+        let mut out = Vec::new();
+        builder.build(&other[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, other);
+        let e = builder
+            .clone()
+            .force(Format::Zlib)
+            .build(&other[..])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        // Streams without dictionaries are decoded as well.
+        let mut e = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let mut s = String::new();
+        builder
+            .build(&e.finish().unwrap()[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[cfg(feature = "lzw")]
+    #[test]
+    fn read_lzw() {
+        // "Hello!" compressed by compress(1)
+        let z = b"\x1f\x9d\x90\x48\xca\xb0\x61\xf3\x26\x04";
+        let mut s = String::new();
+        EgzReader::new(&z[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[cfg(feature = "zip")]
+    #[test]
+    fn read_zip() {
+        let data =
+            crate::zip::tests::zip(&[("a.txt", b"Hello!", 8, false), ("b.txt", b"b", 0, false)]);
+        let mut s = String::new();
+        EgzReaderBuilder::new()
+            .zip(true)
+            .build(&data[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!");
+
+        let mut out = Vec::new();
+        EgzReader::new(&data[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+    }
+    #[cfg(feature = "zip")]
+    #[test]
+    fn zip_egz_error() {
+        let class = |data: &[u8]| {
+            let e = EgzReaderBuilder::new()
+                .zip(true)
+                .build(data)
+                .read_to_end(&mut Vec::new())
+                .unwrap_err();
+            let class = EgzError::of(&e).unwrap();
+            assert_eq!(class.format(), Some(Format::Zip));
+            match class {
+                EgzError::Corrupt(..) => "corrupt",
+                EgzError::Checksum(..) => "checksum",
+                EgzError::UnexpectedEof(..) => "eof",
+                EgzError::Detection(_) => unreachable!(),
+            }
+        };
+        // The data of the entry begins after the local header of 30 bytes and the name.
+        let mut data = crate::zip::tests::zip(&[("a.txt", b"Hello!", 8, false)]);
+        data[35] = 0xff;
+        assert_eq!(class(&data), "corrupt");
+        let mut data = crate::zip::tests::zip(&[("a.txt", b"Hello!", 0, false)]);
+        data[35] = b'J';
+        assert_eq!(class(&data), "checksum");
+        assert_eq!(class(&data[..38]), "eof");
+    }
+    #[cfg(feature = "zip")]
+    #[test]
+    fn zip_entries() {
+        let data = crate::zip::tests::zip(&[
+            ("a.txt", b"Hello, ", 8, true),
+            ("b.txt", b"unread", 0, false),
+            ("c.txt", b"World!", 0, false),
+        ]);
+        let mut entries = EgzReader::new(&data[..]).zip_entries().unwrap();
+
+        let mut a = entries.next().unwrap().unwrap();
+        assert_eq!(a.name(), "a.txt");
+        let mut s = String::new();
+        a.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello, ");
+
+        let b = entries.next().unwrap().unwrap();
+        assert_eq!(b.name(), "b.txt");
+        let mut c = entries.next().unwrap().unwrap();
+        let mut s = String::new();
+        c.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "World!");
+        assert!(entries.next().is_none());
+
+        // Non-ZIP streams are given back.
+        let mut r = EgzReader::new(HELLO_GZ).zip_entries().unwrap_err();
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn read_bgzf() {
+        let data = "Hello, World!\n".repeat(10_000);
+        let z = crate::bgzf::tests::bgzf(data.as_bytes(), 65280);
+
+        let mut s = String::new();
+        EgzReader::new(&z[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, data);
+        let mut s = String::new();
+        EgzReaderBuilder::new()
+            .bgzf_threads(4)
+            .build(&z[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, data);
+
+        // Truncated streams are detected if required, unless live.
+        let z = &z[..z.len() - crate::bgzf::tests::EOF_BLOCK.len()];
+        let mut s = String::new();
+        EgzReader::new(z).read_to_string(&mut s).unwrap();
+        assert_eq!(s, data);
+        let builder = EgzReaderBuilder::new().require_bgzf_eof(true);
+        let e = builder.build(z).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        let mut s = String::new();
+        builder.live(true).build(z).read_to_string(&mut s).unwrap();
+        assert_eq!(s, data);
+
+        // BGZF in a gzip stream
+        let z = crate::bgzf::tests::bgzf(data.as_bytes(), 1000);
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&z).unwrap();
+        let gz = e.finish().unwrap();
+        let mut s = String::new();
+        EgzReaderBuilder::new()
+            .max_depth(2)
+            .build(&gz[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, data);
+    }
+    #[test]
+    fn read_registered_format() {
+        // Bytes after "R13" are rotated by 13.
+        let rot13 = || {
+            let mut started = false;
+            move |input: &mut dyn Read, buf: &mut [u8]| {
+                if !started {
+                    input.read_exact(&mut [0; 3])?;
+                    started = true;
+                }
+                let n = input.read(buf)?;
+                for b in buf[..n].iter_mut() {
+                    if b.is_ascii_alphabetic() {
+                        let base = if b.is_ascii_lowercase() { b'a' } else { b'A' };
+                        *b = (*b - base + 13) % 26 + base;
+                    }
+                }
+                Ok(n)
+            }
+        };
+        let builder =
+            EgzReaderBuilder::new().formats(FormatRegistry::new().register(b"R13", rot13));
+
+        let mut s = String::new();
+        builder
+            .build(&b"R13Uryyb!"[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!");
+
+        // Built-in formats come first.
+        let mut s = String::new();
+        builder.build(HELLO_GZ).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+        let mut s = String::new();
+        builder.build(&b"R1"[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "R1");
+    }
+    #[test]
+    fn read_detector() {
+        // Streams tagged with "Z:" are zlib after the tag.
+        let builder = EgzReaderBuilder::new().detector(|magic: &[u8]| {
+            if magic.starts_with(b"Z:") {
+                Some(Format::Raw)
+            } else if magic.starts_with(&[0x78]) {
+                Some(Format::Zlib)
+            } else {
+                None
+            }
+        });
+        let mut e = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let data = e.finish().unwrap();
+
+        let mut s = String::new();
+        builder.build(&data[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        let mut s = String::new();
+        builder.build(&b"Z:x"[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Z:x");
+
+        // Left to the built-in detection
+        let mut s = String::new();
+        builder.build(HELLO_GZ).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+        let mut r = builder.build(&b""[..]);
+        assert_eq!(r.detect().unwrap(), Format::Empty);
+    }
+    #[test]
+    fn format() {
+        let mut r = EgzReader::new(HELLO_GZ);
+        assert_eq!(r.format(), None);
+        let mut buf = [0; 1];
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(r.format(), Some(Format::Gzip));
+
+        assert_eq!(EgzReader::new(&b""[..]).detect().unwrap(), Format::Empty);
+        let mut r = EgzReader::new(HELLO);
+        assert_eq!(r.detect().unwrap(), Format::Raw);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        let r = EgzReader::from(GzDecoder::new(HELLO_GZ));
+        assert_eq!(r.format(), Some(Format::Gzip));
+    }
+    #[test]
+    fn build_buffered() {
+        let builder = EgzReaderBuilder::new();
+        let mut r = builder.build_buffered(BufReader::new(HELLO_GZ));
+        assert_eq!(r.format(), Some(Format::Gzip));
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        // Too short buffer to tell the format
+        let mut r = builder.build_buffered(BufReader::with_capacity(4, HELLO_GZ));
+        assert_eq!(r.format(), None);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        let data = [b"R13".to_vec(), vec![0; 16]].concat();
+        let formats = FormatRegistry::new().register(b"R13", || {
+            |input: &mut dyn Read, buf: &mut [u8]| input.read(buf)
+        });
+        let r = builder
+            .clone()
+            .formats(formats)
+            .build_buffered(BufReader::new(&data[..]));
+        assert_eq!(r.format(), Some(Format::Custom));
+    }
+    #[test]
+    fn build_seekable() {
+        let builder = EgzReaderBuilder::new();
+        let mut data = Cursor::new(HELLO_GZ);
+        let mut r = builder.build_seekable(&mut data).unwrap();
+        assert_eq!(r.format(), Some(Format::Gzip));
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        // From the middle of a stream
+        let data = [HELLO, HELLO_GZ].concat();
+        let mut data = Cursor::new(&data[..]);
+        data.set_position(HELLO.len() as u64);
+        let mut r = builder.build_seekable(&mut data).unwrap();
+        assert_eq!(r.format(), Some(Format::Gzip));
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        let r = builder.build_seekable(Cursor::new(b"")).unwrap();
+        assert_eq!(r.format(), Some(Format::Empty));
+    }
+    #[test]
+    fn read_http_hints() {
+        let mut e = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let zlib = e.finish().unwrap();
+        let mut e = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let deflate = e.finish().unwrap();
+
+        for body in [&zlib[..], &deflate[..]] {
+            let mut s = String::new();
+            EgzReader::with_http_hints(body, Some("deflate"), None)
+                .read_to_string(&mut s)
+                .unwrap();
+            assert_eq!(s, "Hello!");
+        }
+
+        // Mislabeled bodies
+        let mut s = String::new();
+        EgzReader::with_http_hints(HELLO, Some("gzip"), None)
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!");
+        let mut s = String::new();
+        EgzReader::with_http_hints(HELLO_GZ, None, Some("text/plain"))
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!");
+
+        // A truncated body labeled gzip is an error.
+        let mut r = EgzReader::with_http_hints(&HELLO_GZ[..5], Some("gzip"), None);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+    }
+    #[test]
+    fn read_fallback_to_raw() {
+        // Binary data beginning with the gzip magic number
+        let data = [&HELLO_GZ[..10], &[0xff; 32][..]].concat();
+        let builder = EgzReaderBuilder::new().fallback_to_raw(true);
+        let mut r = builder.build_seekable(Cursor::new(&data)).unwrap();
+        assert_eq!(r.format(), Some(Format::Gzip));
+        let mut out = Vec::new();
+        r.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        let mut r = builder.build_seekable(Cursor::new(HELLO_GZ)).unwrap();
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        // Not without rewinding
+        assert!(builder
+            .build(&data[..])
+            .read_to_end(&mut Vec::new())
+            .is_err());
+        let mut r = EgzReaderBuilder::new()
+            .build_seekable(Cursor::new(&data))
+            .unwrap();
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+    }
+    #[test]
+    fn read_multi_member() {
+        let data = [HELLO_GZ, HELLO_GZ].concat();
+        let mut s = String::new();
+        EgzReaderBuilder::new()
+            .multi_member(true)
+            .build(&data[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!Hello!");
+
+        let mut s = String::new();
+        EgzReader::new(&data[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn read_nested() {
+        fn gzip(data: &[u8]) -> Vec<u8> {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            e.write_all(data).unwrap();
+            e.finish().unwrap()
+        }
+        // Large enough to be fed through the pipe several times
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let twice = gzip(&gzip(&data));
+        let thrice = gzip(&twice);
+
+        let builder = EgzReaderBuilder::new().max_depth(2);
+        let mut out = Vec::new();
+        let mut r = builder.build(&twice[..]);
+        r.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        assert_eq!(r.format(), Some(Format::Gzip));
+
+        // Beyond the depth
+        let mut out = Vec::new();
+        builder.build(&thrice[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, gzip(&data));
+
+        let mut s = String::new();
+        builder.build(HELLO).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+        let mut s = String::new();
+        builder.build(HELLO_GZ).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        let e = builder
+            .build(&twice[..twice.len() - 100])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+    #[test]
+    fn into_trailing() {
+        // A gzip blob followed by a section beginning with another member
+        let data = [HELLO_GZ, HELLO_GZ, b"index"].concat();
+        let mut r = EgzReaderBuilder::new()
+            .trailing_data(TrailingData::Expose)
+            .build(&data[..]);
+        let mut buf = [0; 2];
+        r.read_exact(&mut buf).unwrap();
+        let mut r = r.into_trailing().unwrap_err();
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "llo!");
+
+        let mut rest = Vec::new();
+        r.into_trailing().unwrap().read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [HELLO_GZ, b"index"].concat());
+
+        let mut r = EgzReader::new(HELLO_GZ);
+        r.read_to_end(&mut Vec::new()).unwrap();
+        assert!(r.into_trailing().is_err());
+    }
+    #[test]
+    fn trailing_detect() {
+        let mut e = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b" zlib").unwrap();
+        let zlib = e.finish().unwrap();
+
+        let builder = EgzReaderBuilder::new()
+            .zlib(true)
+            .trailing_data(TrailingData::Detect);
+        let inputs = [
+            ([HELLO_GZ, HELLO_GZ, b"text"].concat(), "Hello!Hello!text"),
+            ([HELLO_GZ, HELLO_GZ, &zlib].concat(), "Hello!Hello! zlib"),
+        ];
+        for builder in [builder.clone(), builder.clone().multi_member(true)].iter() {
+            for (data, expected) in inputs.iter() {
+                for max in [1, 7, data.len()].iter() {
+                    let mut s = String::new();
+                    builder
+                        .build(ShortRead::new(&data[..], *max))
+                        .read_to_string(&mut s)
+                        .unwrap();
+                    assert_eq!(s, *expected);
+
+                    let mut s = String::new();
+                    builder
+                        .clone()
+                        .adaptive_buffer(16, 64)
+                        .build(ShortRead::new(&data[..], *max))
+                        .read_to_string(&mut s)
+                        .unwrap();
+                    assert_eq!(s, *expected);
+                }
+            }
+        }
+
+        // The format of the current stream
+        let mut r = builder.build(&inputs[1].0[..]);
+        let mut buf = [0; 7];
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(r.format(), Some(Format::Gzip));
+        r.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(r.format(), Some(Format::Zlib));
+
+        let mut s = String::new();
+        builder.build(HELLO_GZ).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn new_eager() {
+        let r = EgzReader::new_eager(HELLO_GZ).unwrap();
+        assert_eq!(r.format(), Some(Format::Gzip));
+
+        let e = EgzReader::new_eager(Broken(&HELLO_GZ[..4])).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::ConnectionReset);
+    }
+    #[test]
+    fn read_max_decoded_size() {
+        let mut s = String::new();
+        let builder = EgzReaderBuilder::new().max_decoded_size(6);
+        builder.build(HELLO_GZ).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+
+        let mut r = EgzReaderBuilder::new().max_decoded_size(5).build(HELLO_GZ);
+        let mut buf = [0; 5];
+        r.read_exact(&mut buf).unwrap();
+        let e = r.read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+    #[test]
+    fn read_forced() {
+        let mut out = Vec::new();
+        let mut r = EgzReader::raw(HELLO_GZ);
+        r.read_to_end(&mut out).unwrap();
+        assert_eq!(out, HELLO_GZ);
+        assert_eq!(r.format(), Some(Format::Raw));
+
+        // A gzip stream shorter than the detection length
+        let data = [
+            0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0xff, 0x03, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let mut s = String::new();
+        EgzReader::gzip(&data[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, "");
+        assert!(EgzReader::gzip(HELLO).read(&mut [0; 8]).is_err());
+
+        let builder = EgzReaderBuilder::new().force(Format::Deflate);
+        let mut e = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let mut s = String::new();
+        builder
+            .build(&e.finish().unwrap()[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!");
+    }
+    #[test]
+    fn open_with_extension() {
+        let path = std::env::temp_dir().join(format!("egzreader-open-{}.txt", std::process::id()));
+        std::fs::write(&path, HELLO_GZ).unwrap();
+
+        let mut s = String::new();
+        EgzReader::open(&path)
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!");
+
+        let mut out = Vec::new();
+        EgzReaderBuilder::new()
+            .extension_policy(ExtensionPolicy::Extension)
+            .open(&path)
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, HELLO_GZ);
+
+        let e = EgzReaderBuilder::new()
+            .extension_policy(ExtensionPolicy::Reject)
+            .open(&path)
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
     }
 }
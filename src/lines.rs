@@ -0,0 +1,173 @@
+use std::io::{BufRead, BufReader, Read, Result};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::EgzReader;
+
+// Buffer size of Split, larger than the default of BufReader for bulk data.
+const SPLIT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// An iterator over the records of a decoded stream, created by
+/// [EgzReader::split](EgzReader::split).
+#[derive(Debug)]
+pub struct Split<R: Read> {
+    reader: BufReader<EgzReader<R>>,
+    delimiter: u8,
+}
+
+impl<R: Read> Split<R> {
+    pub(crate) fn new(r: EgzReader<R>, delimiter: u8) -> Split<R> {
+        Split {
+            reader: BufReader::with_capacity(SPLIT_BUFFER_SIZE, r),
+            delimiter,
+        }
+    }
+}
+
+impl<R: Read> Iterator for Split<R> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>>> {
+        let mut record = Vec::new();
+        match self.reader.read_until(self.delimiter, &mut record) {
+            Ok(0) => None,
+            Ok(_) => {
+                if record.last() == Some(&self.delimiter) {
+                    record.pop();
+                }
+                Some(Ok(record))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Calls `f` for each line of the decoded stream of `r` in parallel.
+///
+/// The calling thread decodes `r` and sends batches of `chunk_lines` lines to worker threads,
+/// one per available CPU, which call `f`. The number of batches in flight is bounded,
+/// so decoding waits for the workers when they fall behind.
+/// Lines are given without the line terminator, and are not processed in order.
+///
+/// An error is returned on a decode error or a line not in UTF-8,
+/// after the workers have finished the lines already sent.
+///
+/// # Panics
+/// Panics if `chunk_lines` is zero, or `f` panics.
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use egzreader::process_lines_parallel;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let errors = AtomicUsize::new(0);
+/// process_lines_parallel(File::open("app.log.gz")?, 1000, |line| {
+///     if line.contains("ERROR") {
+///         errors.fetch_add(1, Ordering::Relaxed);
+///     }
+/// })?;
+/// println!("{}", errors.into_inner());
+/// # Ok(())
+/// # }
+/// ```
+pub fn process_lines_parallel<R, F>(r: R, chunk_lines: usize, f: F) -> Result<()>
+where
+    R: Read,
+    F: Fn(&str) + Sync,
+{
+    assert!(0 < chunk_lines, "chunk_lines must be positive");
+
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let (tx, rx) = mpsc::sync_channel::<Vec<String>>(workers * 2);
+    let rx = Arc::new(Mutex::new(rx));
+    let f = &f;
+
+    thread::scope(|s| {
+        for _ in 0..workers {
+            let rx = rx.clone();
+            s.spawn(move || loop {
+                let batch = match rx.lock().unwrap().recv() {
+                    Ok(batch) => batch,
+                    Err(_) => return,
+                };
+                for line in batch.iter() {
+                    f(line);
+                }
+            });
+        }
+
+        // Dropping `tx` at return stops the workers.
+        let tx = tx;
+        let mut batch = Vec::with_capacity(chunk_lines);
+        for line in BufReader::new(EgzReader::new(r)).lines() {
+            batch.push(line?);
+            if batch.len() == chunk_lines {
+                let full = std::mem::replace(&mut batch, Vec::with_capacity(chunk_lines));
+                if tx.send(full).is_err() {
+                    // All workers panicked, which is propagated by the scope.
+                    return Ok(());
+                }
+            }
+        }
+        if !batch.is_empty() {
+            let _ = tx.send(batch);
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Write};
+    use std::sync::Mutex;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::process_lines_parallel;
+    use crate::EgzReader;
+
+    #[test]
+    fn parallel_lines() {
+        let text: String = (0..1000).map(|i| format!("{}\n", i)).collect();
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(text.as_bytes()).unwrap();
+        let data = e.finish().unwrap();
+
+        for &chunk_lines in [1, 7, 1000, 2000].iter() {
+            let lines = Mutex::new(Vec::new());
+            process_lines_parallel(&data[..], chunk_lines, |line| {
+                lines.lock().unwrap().push(line.parse::<usize>().unwrap());
+            })
+            .unwrap();
+
+            let mut lines = lines.into_inner().unwrap();
+            lines.sort_unstable();
+            assert_eq!(lines, (0..1000).collect::<Vec<_>>());
+        }
+    }
+    #[test]
+    fn parallel_lines_invalid() {
+        let data = b"Hello\n\xff\nWorld\n";
+        let e = process_lines_parallel(&data[..], 1, |_| {}).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+    #[test]
+    fn split() {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello\0\0World\0!").unwrap();
+        let data = e.finish().unwrap();
+
+        let records: Vec<Vec<u8>> = EgzReader::new(&data[..])
+            .split(0)
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(records, [&b"Hello"[..], b"", b"World", b"!"]);
+
+        assert_eq!(EgzReader::new(&b""[..]).split(0).count(), 0);
+        assert_eq!(EgzReader::new(&b"a\x1e"[..]).split(0x1e).count(), 1);
+    }
+}
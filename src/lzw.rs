@@ -0,0 +1,411 @@
+use std::io::{Error, ErrorKind, Read, Result};
+
+use crate::error::DecodeError;
+
+// Magic bytes of files made by compress(1).
+const MAGIC: [u8; 2] = [0x1f, 0x9d];
+// Flags of the third byte.
+const BLOCK_MODE: u8 = 0x80;
+const RESERVED: u8 = 0x60;
+const BITS_MASK: u8 = 0x1f;
+
+const CLEAR: u32 = 256;
+const INIT_BITS: u32 = 9;
+const MAX_BITS: u32 = 16;
+
+// Returns true if `preread` begins with the header of compress(1).
+pub(crate) fn is_lzw(preread: &[u8]) -> bool {
+    3 <= preread.len()
+        && preread[..2] == MAGIC
+        && preread[2] & RESERVED == 0
+        && (INIT_BITS..=MAX_BITS).contains(&u32::from(preread[2] & BITS_MASK))
+}
+
+fn invalid(msg: &str) -> Error {
+    DecodeError::corrupt(msg)
+}
+
+// Decoder of the LZW stream of compress(1), `.Z` files.
+//
+// The decoding follows unlzw of gzip, including its quirk:
+// codes are written in groups of 8, and the rest of a group is skipped
+// when the code width changes.
+#[derive(Debug)]
+pub(crate) struct LzwDecoder<R: Read> {
+    inner: R,
+    input: Vec<u8>,
+    in_pos: usize,
+    in_len: usize,
+
+    header: Vec<u8>,
+    block_mode: bool,
+    max_code: u32,
+
+    // Bits read and not yet used, LSB first.
+    bits: u32,
+    nbits: u32,
+    // Bits to be skipped before the next code.
+    skip: u32,
+    // Width of codes, and the number of codes read with the width.
+    width: u32,
+    codes: u32,
+    width_max: u32,
+
+    // Dictionary
+    prefix: Vec<u16>,
+    suffix: Vec<u8>,
+    free: u32,
+    old: Option<u32>,
+    last: u8,
+
+    // Decoded bytes of the last code.
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> LzwDecoder<R> {
+    pub(crate) fn new(inner: R) -> LzwDecoder<R> {
+        LzwDecoder {
+            inner,
+            input: vec![0; 8 * 1024],
+            in_pos: 0,
+            in_len: 0,
+            header: Vec::with_capacity(3),
+            block_mode: false,
+            max_code: 0,
+            bits: 0,
+            nbits: 0,
+            skip: 0,
+            width: INIT_BITS,
+            codes: 0,
+            width_max: (1 << INIT_BITS) - 1,
+            prefix: Vec::new(),
+            suffix: Vec::new(),
+            free: 0,
+            old: None,
+            last: 0,
+            out: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    // Note that the buffered bytes are lost.
+    pub(crate) fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>> {
+        if self.in_pos == self.in_len {
+            self.in_len = self.inner.read(&mut self.input)?;
+            self.in_pos = 0;
+            if self.in_len == 0 {
+                return Ok(None);
+            }
+        }
+        self.in_pos += 1;
+        Ok(Some(self.input[self.in_pos - 1]))
+    }
+
+    // Reads the next code, or returns None at the end of stream.
+    //
+    // The state is kept on errors, so reading can be resumed.
+    fn next_code(&mut self) -> Result<Option<u32>> {
+        while 0 < self.skip {
+            if self.nbits == 0 {
+                match self.next_byte()? {
+                    Some(b) => {
+                        self.bits = u32::from(b);
+                        self.nbits = 8;
+                    }
+                    None => return Ok(None),
+                }
+            }
+            let n = self.skip.min(self.nbits);
+            self.bits >>= n;
+            self.nbits -= n;
+            self.skip -= n;
+        }
+        while self.nbits < self.width {
+            match self.next_byte()? {
+                Some(b) => {
+                    self.bits |= u32::from(b) << self.nbits;
+                    self.nbits += 8;
+                }
+                None => return Ok(None),
+            }
+        }
+        let code = self.bits & ((1 << self.width) - 1);
+        self.bits >>= self.width;
+        self.nbits -= self.width;
+        self.codes += 1;
+        Ok(Some(code))
+    }
+
+    // Skips the rest of the current group of codes, and changes the code width.
+    fn set_width(&mut self, width: u32) {
+        self.skip = (8 - self.codes % 8) % 8 * self.width;
+        self.codes = 0;
+        self.width = width;
+        self.width_max = if width == self.max_code.trailing_zeros() {
+            self.max_code
+        } else {
+            (1 << width) - 1
+        };
+    }
+
+    fn read_header(&mut self) -> Result<()> {
+        while self.header.len() < 3 {
+            match self.next_byte()? {
+                Some(b) => self.header.push(b),
+                None => return Err(ErrorKind::UnexpectedEof.into()),
+            }
+        }
+        if !is_lzw(&self.header) {
+            return Err(invalid("invalid compress header"));
+        }
+        let max_bits = u32::from(self.header[2] & BITS_MASK);
+        self.block_mode = self.header[2] & BLOCK_MODE != 0;
+        self.max_code = 1 << max_bits;
+        self.prefix = vec![0; self.max_code as usize];
+        self.suffix = (0..self.max_code).map(|c| c as u8).collect();
+        self.free = if self.block_mode { CLEAR + 1 } else { CLEAR };
+        Ok(())
+    }
+
+    // Decodes the next code into `out`. Returns false at the end of stream.
+    fn decode(&mut self) -> Result<bool> {
+        if self.max_code == 0 {
+            self.read_header()?;
+        }
+        if self.width_max < self.free {
+            self.set_width(self.width + 1);
+        }
+        let code = match self.next_code()? {
+            Some(code) => code,
+            None => return Ok(false),
+        };
+
+        let old = match self.old {
+            Some(old) => old,
+            None => {
+                // The first code is a literal.
+                if CLEAR <= code {
+                    return Err(invalid("corrupt compress stream"));
+                }
+                self.old = Some(code);
+                self.last = code as u8;
+                self.out.push(self.last);
+                return Ok(true);
+            }
+        };
+        if code == CLEAR && self.block_mode {
+            self.free = CLEAR;
+            self.set_width(INIT_BITS);
+            return Ok(true);
+        }
+
+        let mut c = code;
+        if self.free <= c {
+            // The code being defined by this code, i.e. KwKwK.
+            if self.free < c {
+                return Err(invalid("corrupt compress stream"));
+            }
+            self.out.push(self.last);
+            c = old;
+        }
+        while CLEAR <= c {
+            self.out.push(self.suffix[c as usize]);
+            c = u32::from(self.prefix[c as usize]);
+        }
+        self.last = c as u8;
+        self.out.push(self.last);
+        self.out.reverse();
+
+        if self.free < self.max_code {
+            self.prefix[self.free as usize] = old as u16;
+            self.suffix[self.free as usize] = self.last;
+            self.free += 1;
+        }
+        self.old = Some(code);
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for LzwDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pos == self.out.len() {
+            self.out.clear();
+            self.pos = 0;
+            if !self.decode()? {
+                return Ok(0);
+            }
+        }
+        let n = (&self.out[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    use super::{is_lzw, LzwDecoder, CLEAR, INIT_BITS};
+
+    // Compresses `data` as compress(1) does.
+    //
+    // If `clear_at` is given, the dictionary is cleared when it has the number of entries.
+    fn compress(data: &[u8], max_bits: u32, clear_at: Option<u32>) -> Vec<u8> {
+        struct Writer {
+            out: Vec<u8>,
+            bits: u32,
+            nbits: u32,
+            width: u32,
+            codes: u32,
+        }
+        impl Writer {
+            fn write(&mut self, code: u32) {
+                self.bits |= code << self.nbits;
+                self.nbits += self.width;
+                while 8 <= self.nbits {
+                    self.out.push(self.bits as u8);
+                    self.bits >>= 8;
+                    self.nbits -= 8;
+                }
+                self.codes += 1;
+            }
+            fn set_width(&mut self, width: u32) {
+                while self.codes % 8 != 0 {
+                    self.write(0);
+                }
+                self.codes = 0;
+                self.width = width;
+            }
+        }
+
+        let max_code = 1 << max_bits;
+        let mut w = Writer {
+            out: vec![0x1f, 0x9d, 0x80 | max_bits as u8],
+            bits: 0,
+            nbits: 0,
+            width: INIT_BITS,
+            codes: 0,
+        };
+        let mut width_max = (1 << INIT_BITS) - 1;
+        let mut dict: HashMap<(u32, u8), u32> = HashMap::new();
+        let mut free = CLEAR + 1;
+        let mut output = |w: &mut Writer, code: u32, free: u32, clear: bool| {
+            w.write(code);
+            if clear {
+                w.set_width(INIT_BITS);
+                width_max = (1 << INIT_BITS) - 1;
+            } else if width_max < free {
+                w.set_width(w.width + 1);
+                width_max = if w.width == max_bits {
+                    max_code
+                } else {
+                    (1 << w.width) - 1
+                };
+            }
+        };
+
+        let mut ent = match data.first() {
+            Some(&b) => u32::from(b),
+            None => return w.out,
+        };
+        for &b in data[1..].iter() {
+            if let Some(&code) = dict.get(&(ent, b)) {
+                ent = code;
+                continue;
+            }
+            output(&mut w, ent, free, false);
+            if free < max_code {
+                dict.insert((ent, b), free);
+                free += 1;
+            }
+            if clear_at == Some(free) {
+                dict.clear();
+                free = CLEAR + 1;
+                output(&mut w, CLEAR, free, true);
+            }
+            ent = u32::from(b);
+        }
+        output(&mut w, ent, free, false);
+        if 0 < w.nbits {
+            w.out.push(w.bits as u8);
+        }
+        w.out
+    }
+
+    fn decode(data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        LzwDecoder::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn lzw() {
+        let text: Vec<u8> = (0..20_000u32)
+            .flat_map(|i| format!("{} ", i * i % 1000).into_bytes())
+            .collect();
+        for &max_bits in [9, 12, 16].iter() {
+            let z = compress(&text, max_bits, None);
+            assert!(is_lzw(&z));
+            assert_eq!(decode(&z).unwrap(), text);
+        }
+        let z = compress(&text, 16, Some(3000));
+        assert_eq!(decode(&z).unwrap(), text);
+
+        assert_eq!(decode(&compress(b"", 16, None)).unwrap(), b"");
+        assert_eq!(decode(&compress(b"aaaaaaa", 16, None)).unwrap(), b"aaaaaaa");
+    }
+    #[test]
+    fn lzw_fixtures() {
+        // Made by the compress filter of libarchive (`bsdtar --format raw -Z`),
+        // which follows compress(1) and writes a CLEAR code in block mode
+        // when the compression ratio drops after the table is full.
+        let z = include_bytes!("../examples/hello.txt.Z");
+        assert_eq!(decode(z).unwrap(), b"Hello!");
+
+        // 200000 letters, 25000 random bytes and 10000 letters,
+        // the random bytes making the ratio drop.
+        let mut x: u64 = 1;
+        let data: Vec<u8> = (0..235_000)
+            .map(|i| {
+                x = x
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                let b = (x >> 33) as u8;
+                match i {
+                    200_000..=224_999 => b,
+                    _ => b"abcdefgh"[usize::from(b % 8)],
+                }
+            })
+            .collect();
+        let z = include_bytes!("../examples/lcg.bin.Z");
+        assert_eq!(z[2], 0x80 | 16);
+        assert!(decode(z).unwrap() == data);
+    }
+    #[test]
+    fn lzw_invalid() {
+        assert!(!is_lzw(b"\x1f\x9d"));
+        assert!(!is_lzw(b"\x1f\x9d\x88"));
+        assert!(!is_lzw(b"\x1f\x8b\x08"));
+
+        // The first code must be a literal.
+        let e = decode(b"\x1f\x9d\x90\x00\x03").unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
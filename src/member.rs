@@ -0,0 +1,622 @@
+use flate2::bufread::{GzDecoder, MultiGzDecoder};
+use std::io::{self, BufRead, BufReader, Error, Read, Result, Seek, SeekFrom, Write};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use crate::header::OwnedGzHeader;
+use crate::{Options, Prefixed, ReaderType};
+
+// End offset of a member, set when it has been decoded to its end.
+type End = Arc<Mutex<Option<u64>>>;
+
+// Decodes all members of `r` to verify their checksums.
+fn verify<R: Read>(r: R) -> Result<()> {
+    let mut d = MultiGzDecoder::new(BufReader::new(r));
+    io::copy(&mut d, &mut io::sink())?;
+    Ok(())
+}
+
+/// Joins gzip streams into one multi-member gzip stream without recompression.
+///
+/// All inputs are verified before anything is written: each of them must consist of
+/// valid gzip members only. Then the inputs are copied to `w` as they are,
+/// which is a safe version of `cat *.gz > all.gz`.
+///
+/// Returns the number of bytes written.
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use egzreader::concat_members;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let inputs = vec![File::open("a.gz")?, File::open("b.gz")?];
+/// concat_members(inputs, File::create("all.gz")?)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn concat_members<R, I, W>(inputs: I, mut w: W) -> Result<u64>
+where
+    R: Read + Seek,
+    I: IntoIterator<Item = R>,
+    W: Write,
+{
+    let mut inputs: Vec<R> = inputs.into_iter().collect();
+
+    for (i, r) in inputs.iter_mut().enumerate() {
+        let start = r.stream_position()?;
+        verify(&mut *r).map_err(|e| Error::new(e.kind(), format!("input {}: {}", i, e)))?;
+        r.seek(SeekFrom::Start(start))?;
+    }
+
+    let mut total = 0;
+    for r in inputs.iter_mut() {
+        total += io::copy(r, &mut w)?;
+    }
+    Ok(total)
+}
+
+/// Information of a gzip member given to [split_members](split_members).
+#[derive(Debug, Clone)]
+pub struct MemberInfo {
+    index: usize,
+    offset: u64,
+}
+
+impl MemberInfo {
+    /// Zero-based index of the member.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Offset of the member in the compressed stream.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+// BufRead which writes consumed bytes to a sink.
+struct Tee<'a, R, W> {
+    inner: &'a mut R,
+    sink: W,
+    consumed: u64,
+
+    // Error of the sink, which can not be returned from consume().
+    err: Option<Error>,
+}
+impl<R: BufRead, W: Write> Read for Tee<'_, R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+impl<R: BufRead, W: Write> BufRead for Tee<'_, R, W> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if let Some(e) = self.err.take() {
+            return Err(e);
+        }
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        if self.err.is_none() {
+            if let Ok(buf) = self.inner.fill_buf() {
+                if let Err(e) = self.sink.write_all(&buf[..amt]) {
+                    self.err = Some(e);
+                }
+            }
+        }
+        self.consumed += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+/// Splits a multi-member gzip stream into its members without recompression.
+///
+/// For each member, `f` is called with its [MemberInfo](MemberInfo) and creates a sink
+/// which the compressed bytes of the member are written to.
+/// Each member is decoded simultaneously to find its end and verify its checksum.
+///
+/// Returns the number of members.
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use egzreader::split_members;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let r = File::open("all.gz")?;
+/// split_members(r, |info| File::create(format!("member-{}.gz", info.index())))?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn split_members<R, W, F>(r: R, mut f: F) -> Result<usize>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(MemberInfo) -> Result<W>,
+{
+    let mut r = BufReader::new(r);
+    let mut offset = 0;
+    let mut index = 0;
+
+    while !r.fill_buf()?.is_empty() {
+        let sink = f(MemberInfo { index, offset })?;
+        let mut tee = Tee {
+            inner: &mut r,
+            sink,
+            consumed: 0,
+            err: None,
+        };
+
+        let mut d = GzDecoder::new(&mut tee);
+        io::copy(&mut d, &mut io::sink())?;
+        drop(d);
+        if let Some(e) = tee.err.take() {
+            return Err(e);
+        }
+        tee.sink.flush()?;
+
+        offset += tee.consumed;
+        index += 1;
+    }
+    Ok(index)
+}
+
+// BufRead counting the consumed bytes, i.e. the offset in the compressed stream.
+#[derive(Debug)]
+struct Counted<R> {
+    inner: R,
+    consumed: u64,
+}
+impl<R: BufRead> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+impl<R: BufRead> BufRead for Counted<R> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.consumed += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+#[derive(Debug)]
+enum Source<R: Read> {
+    // Not detected yet
+    Init(R, Options, Vec<u8>),
+    // Between gzip members
+    Next(Counted<BufReader<Prefixed<R>>>),
+    Gz(Box<GzDecoder<Counted<BufReader<Prefixed<R>>>>>),
+    // Non-gzip stream, given as a single member
+    RawNext(BufReader<Prefixed<R>>),
+    Raw(BufReader<Prefixed<R>>),
+    Done,
+}
+
+#[derive(Debug)]
+struct Shared<R: Read> {
+    source: Source<R>,
+    // Index of the current member, starting from 1.
+    index: usize,
+    // Offset, end offset and header of the current member
+    offset: u64,
+    end: End,
+    header: Option<OwnedGzHeader>,
+}
+
+impl<R: Read> Shared<R> {
+    fn next_member(&mut self) -> Result<bool> {
+        loop {
+            match mem::replace(&mut self.source, Source::Done) {
+                Source::Init(mut r, opts, mut buf) => {
+                    ReaderType::preread(&mut r, &opts, &mut buf)?;
+                    let gzip = opts.is_gzip(&buf);
+                    let empty = buf.is_empty();
+                    let prefixed = Prefixed::new(buf, r)
+                        .limit(opts.compressed_rate)
+                        .retry(opts.retry)
+                        .buffered(opts.buffer_bounds);
+                    let r = BufReader::new(prefixed);
+                    self.source = if gzip {
+                        Source::Next(Counted {
+                            inner: r,
+                            consumed: 0,
+                        })
+                    } else if !empty {
+                        Source::RawNext(r)
+                    } else {
+                        Source::Done
+                    };
+                }
+                // Skip the rest of the current member.
+                Source::Gz(mut d) => {
+                    io::copy(&mut d, &mut io::sink())?;
+                    *self.end.lock().unwrap() = Some(d.get_ref().consumed);
+                    self.source = Source::Next(d.into_inner());
+                }
+                Source::Next(mut r) => {
+                    if r.fill_buf()?.is_empty() {
+                        return Ok(false);
+                    }
+                    self.offset = r.consumed;
+                    self.end = End::default();
+                    let d = GzDecoder::new(r);
+                    self.header = d.header().map(OwnedGzHeader::from);
+                    self.source = Source::Gz(Box::new(d));
+                    self.index += 1;
+                    return Ok(true);
+                }
+                Source::RawNext(r) => {
+                    self.offset = 0;
+                    self.end = End::default();
+                    self.header = None;
+                    self.source = Source::Raw(r);
+                    self.index += 1;
+                    return Ok(true);
+                }
+                Source::Raw(_) | Source::Done => return Ok(false),
+            }
+        }
+    }
+}
+
+/// An iterator over the gzip members of a stream, created by
+/// [EgzReader::into_members](crate::EgzReader::into_members).
+///
+/// Each item is a [Member](Member) reading the decoded bytes of one member,
+/// which also gives its offsets in the compressed stream and its header.
+/// A non-gzip stream is given as a single member.
+/// Advancing the iterator skips the rest of the previous member,
+/// after which the previous `Member` returns EOF.
+#[derive(Debug)]
+pub struct Members<R: Read>(Arc<Mutex<Shared<R>>>);
+
+impl<R: Read> Members<R> {
+    pub(crate) fn new(r: R, opts: Options, preread: Vec<u8>) -> Members<R> {
+        Members(Arc::new(Mutex::new(Shared {
+            source: Source::Init(r, opts, preread),
+            index: 0,
+            offset: 0,
+            end: End::default(),
+            header: None,
+        })))
+    }
+}
+
+impl<R: Read> Iterator for Members<R> {
+    type Item = Result<Member<R>>;
+
+    fn next(&mut self) -> Option<Result<Member<R>>> {
+        let mut shared = self.0.lock().unwrap();
+        match shared.next_member() {
+            Ok(true) => Some(Ok(Member {
+                shared: self.0.clone(),
+                index: shared.index,
+                offset: shared.offset,
+                end: shared.end.clone(),
+                header: shared.header.take(),
+            })),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// A reader of the decoded bytes of one gzip member, given by [Members](Members).
+#[derive(Debug)]
+pub struct Member<R: Read> {
+    shared: Arc<Mutex<Shared<R>>>,
+    index: usize,
+    offset: u64,
+    end: End,
+    header: Option<OwnedGzHeader>,
+}
+
+impl<R: Read> Member<R> {
+    /// Offset of the member in the compressed stream.
+    ///
+    /// The offset of a non-gzip stream is 0.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Offset just after the trailer of the member in the compressed stream,
+    /// i.e. the offset of the next member if any.
+    ///
+    /// Returns `None` until the member has been read to its end or skipped by
+    /// advancing the iterator, and always for a non-gzip stream.
+    pub fn end_offset(&self) -> Option<u64> {
+        *self.end.lock().unwrap()
+    }
+
+    /// Length of the member in the compressed stream, including its header and trailer.
+    ///
+    /// Returns `None` whenever [end_offset](Member::end_offset) does.
+    pub fn compressed_len(&self) -> Option<u64> {
+        self.end_offset().map(|end| end - self.offset)
+    }
+
+    /// Header of the member, e.g. to get the original filename and the modification time.
+    ///
+    /// Returns `None` for a non-gzip stream, or if the header could not be read
+    /// when the member was given.
+    pub fn header(&self) -> Option<&OwnedGzHeader> {
+        self.header.as_ref()
+    }
+}
+
+impl<R: Read> Read for Member<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.index != self.index {
+            return Ok(0);
+        }
+        match &mut shared.source {
+            Source::Gz(d) => {
+                let n = d.read(buf)?;
+                if n == 0 && !buf.is_empty() {
+                    *self.end.lock().unwrap() = Some(d.get_ref().consumed);
+                }
+                Ok(n)
+            }
+            Source::Raw(r) => r.read(buf),
+            _ => Ok(0),
+        }
+    }
+}
+
+/// Decoded size of a stream, returned by [measure](measure).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedSize {
+    total: u64,
+    members: Option<Vec<u64>>,
+}
+
+impl DecodedSize {
+    /// Total number of decoded bytes.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Decoded sizes of the gzip members, or `None` if the stream is not gzip.
+    pub fn members(&self) -> Option<&[u64]> {
+        self.members.as_deref()
+    }
+}
+
+/// Counts the decoded bytes of `r` without storing the output.
+///
+/// Every member of a multi-member gzip stream is decoded and counted,
+/// so sizes of 4 GiB or more are exact unlike the ISIZE field of the trailer,
+/// which is the size modulo 2<sup>32</sup>. A non-gzip stream is counted as it is.
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::File;
+/// use egzreader::measure;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let size = measure(File::open("all.gz")?)?;
+/// println!("{} bytes", size.total());
+/// # Ok(())
+/// # }
+/// ```
+pub fn measure<R: Read>(mut r: R) -> Result<DecodedSize> {
+    let opts = Options::default();
+    let mut buf = Vec::new();
+    ReaderType::preread(&mut r, &opts, &mut buf)?;
+    let gzip = opts.is_gzip(&buf);
+    let mut r = BufReader::new(Prefixed::new(buf, r));
+
+    if !gzip {
+        let total = io::copy(&mut r, &mut io::sink())?;
+        return Ok(DecodedSize {
+            total,
+            members: None,
+        });
+    }
+
+    let mut members = Vec::new();
+    while !r.fill_buf()?.is_empty() {
+        let mut d = GzDecoder::new(&mut r);
+        members.push(io::copy(&mut d, &mut io::sink())?);
+    }
+    Ok(DecodedSize {
+        total: members.iter().sum(),
+        members: Some(members),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{Cursor, Read, Result, Write};
+    use std::rc::Rc;
+
+    use flate2::read::MultiGzDecoder;
+    use flate2::write::GzEncoder;
+    use flate2::{Compression, GzBuilder};
+
+    use super::{concat_members, measure, split_members};
+    use crate::EgzReader;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn concat() {
+        let a = gzip(b"Hello");
+        let b = [gzip(b", "), gzip(b"World")].concat();
+
+        let mut out = Vec::new();
+        let n = concat_members(vec![Cursor::new(&a), Cursor::new(&b)], &mut out).unwrap();
+        assert_eq!(n as usize, a.len() + b.len());
+        assert_eq!(out, [&a[..], &b[..]].concat());
+
+        let mut s = String::new();
+        MultiGzDecoder::new(&out[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello, World");
+    }
+    #[test]
+    fn concat_invalid() {
+        let a = gzip(b"Hello");
+        let mut corrupt = gzip(b"World");
+        let n = corrupt.len();
+        corrupt[n - 8] ^= 0xff;
+        let garbage = [gzip(b"World"), b"garbage".to_vec()].concat();
+
+        for b in [corrupt, garbage, b"World".to_vec(), Vec::new()].iter() {
+            let mut out = Vec::new();
+            assert!(concat_members(vec![Cursor::new(&a), Cursor::new(b)], &mut out).is_err());
+            assert!(out.is_empty());
+        }
+    }
+    // Writer appending to the last buffer of shared outputs.
+    #[derive(Default, Clone)]
+    struct Outputs(Rc<RefCell<Vec<Vec<u8>>>>);
+    impl Write for Outputs {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.borrow_mut().last_mut().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn split() {
+        let members = [gzip(b"Hello"), gzip(b", "), gzip(b"World")];
+        let data = members.concat();
+
+        let outputs = Outputs::default();
+        let mut offsets = Vec::new();
+        let n = split_members(&data[..], |info| {
+            assert_eq!(info.index(), offsets.len());
+            offsets.push(info.offset());
+            outputs.0.borrow_mut().push(Vec::new());
+            Ok(outputs.clone())
+        })
+        .unwrap();
+
+        assert_eq!(n, 3);
+        assert_eq!(*outputs.0.borrow(), members);
+        assert_eq!(
+            offsets,
+            [0, members[0].len(), members[0].len() + members[1].len()]
+                .iter()
+                .map(|&n| n as u64)
+                .collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn split_empty() {
+        let n = split_members(&b""[..], |_| Ok(Vec::new())).unwrap();
+        assert_eq!(n, 0);
+    }
+    #[test]
+    fn split_invalid() {
+        let data = [gzip(b"Hello"), b"garbage".to_vec()].concat();
+        assert!(split_members(&data[..], |_| Ok(Vec::new())).is_err());
+    }
+    #[test]
+    fn measure_members() {
+        let data = [gzip(b"Hello"), gzip(b""), gzip(b"World!")].concat();
+        let size = measure(&data[..]).unwrap();
+        assert_eq!(size.total(), 11);
+        assert_eq!(size.members(), Some(&[5, 0, 6][..]));
+
+        let size = measure(&b"Hello"[..]).unwrap();
+        assert_eq!(size.total(), 5);
+        assert_eq!(size.members(), None);
+
+        let data = [gzip(b"Hello"), b"garbage".to_vec()].concat();
+        assert!(measure(&data[..]).is_err());
+    }
+    #[test]
+    fn into_members() {
+        let data = [gzip(b"Hello"), gzip(b""), gzip(b"World!")].concat();
+        let members: Vec<String> = EgzReader::new(&data[..])
+            .into_members()
+            .unwrap()
+            .map(|m| {
+                let mut s = String::new();
+                m.unwrap().read_to_string(&mut s).unwrap();
+                s
+            })
+            .collect();
+        assert_eq!(members, ["Hello", "", "World!"]);
+
+        // Skipping members
+        let mut members = EgzReader::new(&data[..]).into_members().unwrap();
+        let mut first = members.next().unwrap().unwrap();
+        let mut buf = [0; 2];
+        first.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"He");
+        members.next().unwrap().unwrap();
+        let mut last = members.next().unwrap().unwrap();
+        assert_eq!(first.read(&mut buf).unwrap(), 0);
+        let mut s = String::new();
+        last.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "World!");
+        assert!(members.next().is_none());
+
+        let mut members = EgzReader::new(&b"Hello"[..]).into_members().unwrap();
+        let mut s = String::new();
+        members
+            .next()
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello");
+        assert!(members.next().is_none());
+        assert_eq!(EgzReader::new(&b""[..]).into_members().unwrap().count(), 0);
+
+        let mut r = EgzReader::new(&data[..]);
+        r.read_exact(&mut buf).unwrap();
+        assert!(r.into_members().is_err());
+    }
+    #[test]
+    fn member_metadata() {
+        let mut e = GzBuilder::new()
+            .filename("b.log")
+            .mtime(1_600_000_000)
+            .write(Vec::new(), Compression::default());
+        e.write_all(b"World").unwrap();
+        let a = gzip(b"Hello");
+        let data = [a.clone(), e.finish().unwrap()].concat();
+
+        let mut members = EgzReader::new(&data[..]).into_members().unwrap();
+        let first = members.next().unwrap().unwrap();
+        assert_eq!(first.offset(), 0);
+        assert_eq!(first.end_offset(), None);
+        assert_eq!(first.header().unwrap().filename(), None);
+        let mut second = members.next().unwrap().unwrap();
+        // The first member is skipped.
+        assert_eq!(first.compressed_len(), Some(a.len() as u64));
+        assert_eq!(second.offset(), a.len() as u64);
+        let header = second.header().unwrap();
+        assert_eq!(header.filename(), Some(&b"b.log"[..]));
+        assert_eq!(header.mtime(), 1_600_000_000);
+        second.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(second.end_offset(), Some(data.len() as u64));
+        assert!(members.next().is_none());
+
+        let mut members = EgzReader::new(&b"Hello"[..]).into_members().unwrap();
+        let mut raw = members.next().unwrap().unwrap();
+        assert!(raw.header().is_none());
+        raw.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(raw.end_offset(), None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::io::{ErrorKind, Read, Result};
+
+use crate::header::OwnedGzHeader;
+use crate::{Format, Options, ReaderType};
+
+// Number of decoded bytes given to the next level at once.
+const CHUNK_SIZE: usize = 32 * 1024;
+
+// Decoded bytes of a level given to the decoder of the next level.
+// Reading an empty pipe fails with WouldBlock until it is filled or closed.
+#[derive(Debug, Default)]
+pub(crate) struct Pipe {
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl Pipe {
+    // Appends `input`, dropping the bytes already read.
+    pub(crate) fn push(&mut self, input: &[u8]) {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        self.buf.extend_from_slice(input);
+    }
+
+    // Marks the end of the input.
+    pub(crate) fn close(&mut self) {
+        self.eof = true;
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.pos == self.buf.len() && !self.eof {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let n = (&self.buf[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Stream encoded twice or more, e.g. `.gz.gz`.
+//
+// The decoded bytes of `inner` are detected and decoded again by `outer`,
+// which is fed through the pipe whenever it would block.
+#[derive(Debug)]
+pub(crate) struct Nested<R: Read> {
+    inner: ReaderType<R>,
+    outer: ReaderType<Pipe>,
+}
+
+impl<R: Read> Nested<R> {
+    pub(crate) fn new(inner: ReaderType<R>, opts: &Options) -> Nested<R> {
+        // The options of the underlying reader are applied to the first level only.
+        let opts = Options {
+            depth: opts.depth - 1,
+            nested: true,
+            force: None,
+            compressed_rate: None,
+            retry: None,
+            sniff_timeout: None,
+            buffer_bounds: None,
+            ..opts.clone()
+        };
+        Nested {
+            inner,
+            outer: ReaderType::Init(Pipe::default(), opts, Vec::new()),
+        }
+    }
+
+    // Format of the first level
+    pub(crate) fn format(&self) -> Option<Format> {
+        self.inner.format()
+    }
+
+    // Header of the first level
+    pub(crate) fn gz_header(&self) -> Option<OwnedGzHeader> {
+        self.inner.gz_header()
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        self.inner.inner_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        self.inner.inner_mut()
+    }
+
+    // The first level, dropping the bytes decoded by it and not read yet.
+    pub(crate) fn into_inner(self) -> ReaderType<R> {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Nested<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.outer.read(buf) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    let pipe = self.outer.inner_mut();
+                    if pipe.eof {
+                        return Err(e);
+                    }
+                    pipe.buf.resize(CHUNK_SIZE, 0);
+                    pipe.pos = 0;
+                    let result = self.inner.read(&mut pipe.buf);
+                    let n = *result.as_ref().unwrap_or(&0);
+                    pipe.buf.truncate(n);
+                    pipe.eof = result.is_ok() && n == 0;
+                    result?;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Functions compatible with the [niffler](https://crates.io/crates/niffler) API.
+//!
+//! [sniff](sniff), [get_reader](get_reader) and [from_path](from_path) have the signatures
+//! of their niffler counterparts except for the error type, which is `io::Error` here.
+//! Code reading with niffler can switch to egzreader by replacing `niffler::`
+//! with `egzreader::niffler::` and converting the errors; the writers of niffler are not provided.
+//!
+//! All formats of niffler are detected, but only gzip is decoded:
+//! the readers of the other compressed formats return an error of `ErrorKind::Unsupported`,
+//! as niffler does with their features disabled.
+//!
+//! # Examples
+//! ```
+//! use std::io::prelude::*;
+//! use egzreader::niffler::{get_reader, Format};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let (mut r, format) = get_reader(Box::new(&b"Hello!"[..]))?;
+//! assert_eq!(format, Format::No);
+//!
+//! let mut s = String::new();
+//! r.read_to_string(&mut s)?;
+//! assert_eq!(s, "Hello!");
+//! # Ok(())
+//! # }
+//! ```
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::Path;
+
+use crate::{is_gzip, Options, Prefixed, ReaderType};
+
+/// Compression format of a stream, with the variants of niffler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// gzip, decoded by [get_reader](get_reader).
+    Gzip,
+    /// bzip2, detected but not supported.
+    Bzip,
+    /// xz, detected but not supported.
+    Lzma,
+    /// Zstandard, detected but not supported.
+    Zstd,
+    /// Not compressed, or in an unknown format.
+    No,
+}
+
+impl Format {
+    fn of(preread: &[u8]) -> Format {
+        if is_gzip(preread) {
+            Format::Gzip
+        } else if preread.starts_with(b"BZh") {
+            Format::Bzip
+        } else if preread.starts_with(b"\xfd7zXZ\x00") {
+            Format::Lzma
+        } else if preread.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Format::Zstd
+        } else {
+            Format::No
+        }
+    }
+}
+
+/// Detects the format of `in_stream`, returning a reader yielding the stream as it is.
+pub fn sniff<'a>(mut in_stream: Box<dyn Read + 'a>) -> Result<(Box<dyn Read + 'a>, Format)> {
+    let mut buf = Vec::new();
+    ReaderType::preread(&mut in_stream, &Options::default(), &mut buf)?;
+    let format = Format::of(&buf);
+    Ok((Box::new(Prefixed::new(buf, in_stream)), format))
+}
+
+/// Detects the format of `in_stream`, returning a reader yielding the decoded stream.
+///
+/// All members of a multi-member gzip stream are decoded, as niffler does.
+/// Returns an error of `ErrorKind::Unsupported` for the other compressed formats.
+pub fn get_reader<'a>(in_stream: Box<dyn Read + 'a>) -> Result<(Box<dyn Read + 'a>, Format)> {
+    let (r, format) = sniff(in_stream)?;
+    match format {
+        Format::Gzip => Ok((Box::new(MultiGzDecoder::new(r)), format)),
+        Format::No => Ok((r, format)),
+        _ => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("{:?} is not supported", format),
+        )),
+    }
+}
+
+/// Opens the file at `path` and calls [get_reader](get_reader).
+pub fn from_path<'a, P: AsRef<Path>>(path: P) -> Result<(Box<dyn Read + 'a>, Format)> {
+    get_reader(Box::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{ErrorKind, Read, Write};
+
+    use super::{get_reader, sniff, Format};
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn niffler_compat() {
+        let data = [gzip(b"Hello, "), gzip(b"World!")].concat();
+
+        let (mut r, format) = get_reader(Box::new(&data[..])).unwrap();
+        assert_eq!(format, Format::Gzip);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello, World!");
+
+        let (mut r, format) = sniff(Box::new(&data[..])).unwrap();
+        assert_eq!(format, Format::Gzip);
+        let mut raw = Vec::new();
+        r.read_to_end(&mut raw).unwrap();
+        assert_eq!(raw, data);
+
+        let (_, format) = get_reader(Box::new(&b""[..])).unwrap();
+        assert_eq!(format, Format::No);
+
+        let zstd = [0x28, 0xb5, 0x2f, 0xfd, 0x24, 0x05, 0x29, 0x00, 0x00];
+        let (_, format) = sniff(Box::new(&zstd[..])).unwrap();
+        assert_eq!(format, Format::Zstd);
+        let e = get_reader(Box::new(&zstd[..])).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::Unsupported);
+        let (_, format) = sniff(Box::new(&b"BZh91AY&SY"[..])).unwrap();
+        assert_eq!(format, Format::Bzip);
+        let (_, format) = sniff(Box::new(&b"\xfd7zXZ\x00\x00\x04"[..])).unwrap();
+        assert_eq!(format, Format::Lzma);
+    }
+}
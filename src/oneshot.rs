@@ -0,0 +1,61 @@
+use std::io::{Read, Result};
+
+use crate::{EgzReader, FILL_LEN};
+
+/// Reads all the decoded bytes of `r`, whether it is gzipped or not.
+///
+/// Equivalent to `read_to_end` of `EgzReader::new(r)`, beginning with a buffer
+/// of the decoded chunk size instead of growing it from a few bytes.
+///
+/// # Examples
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// assert_eq!(egzreader::read_to_vec(&b"Hello!"[..])?, b"Hello!");
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_to_vec<R: Read>(r: R) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(FILL_LEN);
+    EgzReader::new(r).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Reads all the decoded bytes of `r` as a string, whether it is gzipped or not.
+///
+/// Fails with `InvalidData` if the decoded bytes are not UTF-8, as
+/// [Read::read_to_string](std::io::Read::read_to_string).
+///
+/// # Examples
+/// ```
+/// use std::fs::File;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let s = egzreader::read_to_string(File::open("examples/hello.txt.gz")?)?;
+/// assert_eq!(s, "Hello!");
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_to_string<R: Read>(r: R) -> Result<String> {
+    let mut s = String::with_capacity(FILL_LEN);
+    EgzReader::new(r).read_to_string(&mut s)?;
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::{read_to_string, read_to_vec};
+    use crate::test_support::{HELLO, HELLO_GZ};
+
+    #[test]
+    fn read_all() {
+        assert_eq!(read_to_vec(HELLO_GZ).unwrap(), HELLO);
+        assert_eq!(read_to_vec(&b""[..]).unwrap(), b"");
+        assert_eq!(read_to_string(HELLO_GZ).unwrap(), "Hello!");
+
+        let e = read_to_string(&[0xff, 0xfe][..]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(read_to_vec(&HELLO_GZ[..18]).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::Path;
+
+use crate::Format;
+
+/// How [EgzReaderBuilder::open](crate::EgzReaderBuilder::open) treats a file
+/// whose extension and content disagree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExtensionPolicy {
+    /// The content decides, as zcat does. The format of the extension is detected
+    /// even if its option is disabled, e.g. zlib for `.zz`.
+    #[default]
+    Content,
+    /// The extension decides, skipping the detection like
+    /// [EgzReaderBuilder::force](crate::EgzReaderBuilder::force).
+    Extension,
+    /// Opening fails with `InvalidData` if the detected format differs from the extension.
+    Reject,
+}
+
+// Format implied by the extension of `path`, if known.
+pub(crate) fn format_of(path: &Path) -> Option<Format> {
+    let ext = path.extension()?.to_str()?;
+    #[cfg(feature = "lzw")]
+    {
+        // compress(1) uses the upper case, unlike gzip.
+        if ext == "Z" {
+            return Some(Format::Lzw);
+        }
+    }
+    Some(match ext.to_ascii_lowercase().as_str() {
+        "gz" | "tgz" | "gzip" => Format::Gzip,
+        "bgz" | "bgzf" | "bam" => Format::Bgzf,
+        "zz" | "zlib" => Format::Zlib,
+        "deflate" => Format::Deflate,
+        #[cfg(feature = "zip")]
+        "zip" => Format::Zip,
+        "txt" | "text" | "csv" | "tsv" | "json" | "jsonl" | "log" | "xml" => Format::Raw,
+        _ => return None,
+    })
+}
+
+// The formats are the same as far as an extension tells.
+pub(crate) fn agrees(hint: Format, detected: Format) -> bool {
+    match (hint, detected) {
+        // .gz files are often BGZF, and both are gzip.
+        (Format::Gzip, Format::Bgzf) | (Format::Raw, Format::Empty) => true,
+        _ => hint == detected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::format_of;
+    use crate::Format;
+
+    #[test]
+    fn extension() {
+        assert_eq!(format_of(Path::new("a/b.tar.GZ")), Some(Format::Gzip));
+        assert_eq!(format_of(Path::new("calls.vcf.bgz")), Some(Format::Bgzf));
+        assert_eq!(format_of(Path::new("notes.txt")), Some(Format::Raw));
+        assert_eq!(format_of(Path::new("Makefile")), None);
+        assert_eq!(format_of(Path::new("a.bin")), None);
+    }
+}
@@ -0,0 +1,256 @@
+use std::io::{Error, ErrorKind, Read, Result};
+
+use crate::nested::Pipe;
+use crate::{EgzReader, EgzReaderBuilder};
+
+// Number of decoded bytes read from the reader at once.
+const CHUNK_SIZE: usize = 32 * 1024;
+
+/// A push-based decoder which does not require [Read](std::io::Read).
+///
+/// `PushDecoder` is driven by the caller feeding chunks of the input stream,
+/// so it can be used from custom event loops or packet processors.
+/// Call [finish](PushDecoder::finish) at the end of the input.
+///
+/// The fed bytes are decoded by an [EgzReader](crate::EgzReader), which waits for more
+/// input instead of blocking, so the stream is detected and decoded with the options of
+/// [EgzReaderBuilder::build_push](crate::EgzReaderBuilder::build_push), e.g. all the
+/// members with [multi_member](crate::EgzReaderBuilder::multi_member).
+/// Only the formats detected in the nested levels of
+/// [max_depth](crate::EgzReaderBuilder::max_depth) are decoded: gzip, BGZF,
+/// and zlib and deflate if enabled. Any other input is passed through as it is.
+///
+/// # Examples
+/// ```
+/// use egzreader::PushDecoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut d = PushDecoder::new();
+///
+/// let mut out = d.feed(b"Hel")?;
+/// out.extend(d.feed(b"lo!")?);
+/// out.extend(d.finish()?);
+/// assert_eq!(out, b"Hello!");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PushDecoder {
+    reader: EgzReader<Pipe>,
+    // The decoded stream has ended, and the following input is ignored.
+    ended: bool,
+    failed: bool,
+}
+
+impl Default for PushDecoder {
+    fn default() -> PushDecoder {
+        PushDecoder::new()
+    }
+}
+
+impl PushDecoder {
+    /// Creates a `PushDecoder` with the default options.
+    pub fn new() -> PushDecoder {
+        EgzReaderBuilder::new().build_push()
+    }
+
+    pub(crate) fn from_reader(reader: EgzReader<Pipe>) -> PushDecoder {
+        PushDecoder {
+            reader,
+            ended: false,
+            failed: false,
+        }
+    }
+
+    /// Feeds `input` and returns the bytes decoded from it.
+    pub fn feed(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.decode_into(input, &mut out)?;
+        Ok(out)
+    }
+
+    /// Feeds `input` and appends the bytes decoded from it to `out`.
+    ///
+    /// Returns the number of bytes appended.
+    pub fn decode_into(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<usize> {
+        if !self.ended {
+            self.reader.get_mut().push(input);
+        }
+        self.drain(out)
+    }
+
+    /// Signals the end of input and returns the remaining decoded bytes.
+    ///
+    /// Fails when the input ended in the middle of the stream, e.g. a gzip member.
+    pub fn finish(&mut self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.finish_into(&mut out)?;
+        Ok(out)
+    }
+
+    /// Signals the end of input and appends the remaining decoded bytes to `out`.
+    ///
+    /// Returns the number of bytes appended.
+    pub fn finish_into(&mut self, out: &mut Vec<u8>) -> Result<usize> {
+        self.reader.get_mut().close();
+        self.drain(out)
+    }
+
+    // Reads the bytes decoded from the input fed so far into `out`.
+    fn drain(&mut self, out: &mut Vec<u8>) -> Result<usize> {
+        if self.failed {
+            return Err(Error::new(ErrorKind::Other, "decoder has failed"));
+        }
+        let start = out.len();
+        while !self.ended {
+            let len = out.len();
+            out.resize(len + CHUNK_SIZE, 0);
+            let result = self.reader.read(&mut out[len..]);
+            out.truncate(len + *result.as_ref().unwrap_or(&0));
+            match result {
+                Ok(0) => self.ended = true,
+                Ok(_) => {}
+                // Waiting for more input
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    self.failed = true;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(out.len() - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::{Compression, GzBuilder};
+
+    use super::PushDecoder;
+    use crate::test_support::HELLO_GZ;
+    use crate::{EgzError, EgzReader, EgzReaderBuilder, Format};
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    fn decode_chunks(data: &[u8], size: usize) -> std::io::Result<Vec<u8>> {
+        decode_chunks_with(&EgzReaderBuilder::new(), data, size)
+    }
+
+    fn decode_chunks_with(
+        builder: &EgzReaderBuilder,
+        data: &[u8],
+        size: usize,
+    ) -> std::io::Result<Vec<u8>> {
+        let mut d = builder.build_push();
+        let mut out = Vec::new();
+        for chunk in data.chunks(size) {
+            d.decode_into(chunk, &mut out)?;
+        }
+        d.finish_into(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn push_gz() {
+        let data = gzip(b"Hello!");
+        assert_eq!(decode_chunks(&data, data.len()).unwrap(), b"Hello!");
+        assert_eq!(decode_chunks(&data, 1).unwrap(), b"Hello!");
+    }
+    #[test]
+    fn push_gz_long() {
+        let text: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        let data = gzip(&text);
+        assert_eq!(decode_chunks(&data, 4096).unwrap(), text);
+    }
+    #[test]
+    fn push_gz_header_fields() {
+        let mut e = GzBuilder::new()
+            .filename("hello.txt")
+            .comment("comment")
+            .extra(vec![1, 2, 3])
+            .write(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let data = e.finish().unwrap();
+        assert_eq!(decode_chunks(&data, 3).unwrap(), b"Hello!");
+    }
+    #[test]
+    fn push_raw() {
+        assert_eq!(decode_chunks(b"", 1).unwrap(), b"");
+        assert_eq!(decode_chunks(b"Hello!", 2).unwrap(), b"Hello!");
+        let data = [0x41; 20];
+        assert_eq!(decode_chunks(&data, 7).unwrap(), data);
+    }
+    #[test]
+    fn push_fake_gz() {
+        let data = gzip(b"Hello!");
+        assert_eq!(decode_chunks(&data[..10], 1).unwrap(), &data[..10]);
+    }
+    #[test]
+    fn push_truncated_gz() {
+        let data = gzip(b"Hello!");
+        let e = decode_chunks(&data[..data.len() - 4], 5).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+    #[test]
+    fn push_corrupt_gz() {
+        let mut data = gzip(b"Hello!");
+        let n = data.len();
+        data[n - 8] ^= 0xff;
+        let e = decode_chunks(&data, 5).unwrap_err();
+        assert!(matches!(
+            EgzError::of(&e),
+            Some(EgzError::Checksum(Format::Gzip, _))
+        ));
+
+        // Failing again after the error
+        let mut d = PushDecoder::new();
+        assert!(d.feed(&data).is_err());
+        assert_eq!(d.feed(b"").unwrap_err().kind(), ErrorKind::Other);
+        assert_eq!(d.finish().unwrap_err().kind(), ErrorKind::Other);
+    }
+    #[test]
+    fn push_long_header() {
+        // An unterminated FNAME
+        let mut data = vec![0x1f, 0x8b, 0x08, 0x08, 0, 0, 0, 0, 0, 0xff];
+        data.resize(100_000, b'a');
+        let kind = EgzReader::new(&data[..])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err()
+            .kind();
+        assert_eq!(decode_chunks(&data, 4096).unwrap_err().kind(), kind);
+        assert_eq!(decode_chunks(&data, data.len()).unwrap_err().kind(), kind);
+
+        // Passed through in the strict mode
+        let builder = EgzReaderBuilder::new().strict(true);
+        assert_eq!(decode_chunks_with(&builder, &data, 4096).unwrap(), data);
+    }
+    #[test]
+    fn push_options() {
+        let data = [HELLO_GZ, HELLO_GZ].concat();
+        assert_eq!(decode_chunks(&data, 3).unwrap(), b"Hello!");
+        let builder = EgzReaderBuilder::new().multi_member(true);
+        assert_eq!(
+            decode_chunks_with(&builder, &data, 3).unwrap(),
+            b"Hello!Hello!"
+        );
+
+        let text: Vec<u8> = (0..50_000).map(|i| (i % 251) as u8).collect();
+        let data = crate::bgzf::tests::bgzf(&text, 10_000);
+        assert_eq!(decode_chunks(&data, 4096).unwrap(), text);
+        assert_eq!(decode_chunks(&data, 1).unwrap(), text);
+
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"Hello!").unwrap();
+        let data = e.finish().unwrap();
+        assert_eq!(decode_chunks(&data, 1).unwrap(), data);
+        let builder = EgzReaderBuilder::new().zlib(true);
+        assert_eq!(decode_chunks_with(&builder, &data, 1).unwrap(), b"Hello!");
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+use std::io::{Read, Result};
+use std::sync::Arc;
+
+use crate::Format;
+
+/// A decoder of a user-defined format, registered to a [FormatRegistry](FormatRegistry).
+///
+/// The encoded stream is given to each call as `input`, beginning with the magic bytes,
+/// since the type of the underlying reader is generic and may borrow.
+/// A decoder keeps its own state between calls.
+///
+/// Closures of `FnMut(&mut dyn Read, &mut [u8]) -> io::Result<usize>` implement `Decoder`.
+pub trait Decoder {
+    /// Reads decoded bytes into `buf`, reading encoded bytes from `input` as needed.
+    ///
+    /// Returns 0 at the end of the decoded stream, as `Read::read` does.
+    fn read(&mut self, input: &mut dyn Read, buf: &mut [u8]) -> Result<usize>;
+}
+
+impl<F> Decoder for F
+where
+    F: FnMut(&mut dyn Read, &mut [u8]) -> Result<usize>,
+{
+    fn read(&mut self, input: &mut dyn Read, buf: &mut [u8]) -> Result<usize> {
+        self(input, buf)
+    }
+}
+
+type BoxedDecoder = Box<dyn Decoder + Send + Sync>;
+type Factory = Arc<dyn Fn() -> BoxedDecoder + Send + Sync>;
+type DetectFn = dyn Fn(&[u8]) -> Option<Format> + Send + Sync;
+
+/// Formats detected by their magic bytes, given by
+/// [EgzReaderBuilder::formats](crate::EgzReaderBuilder::formats).
+///
+/// The registry is consulted when a stream is none of the built-in formats,
+/// before falling back to reading it as it is. The first matching format is used.
+///
+/// # Examples
+/// ```
+/// use std::io::prelude::*;
+/// use egzreader::{EgzReaderBuilder, FormatRegistry};
+///
+/// // Bytes after "NOT\n" are inverted.
+/// fn not() -> impl FnMut(&mut dyn Read, &mut [u8]) -> std::io::Result<usize> {
+///     let mut started = false;
+///     move |input, buf| {
+///         if !started {
+///             input.read_exact(&mut [0; 4])?;
+///             started = true;
+///         }
+///         let n = input.read(buf)?;
+///         buf[..n].iter_mut().for_each(|b| *b = !*b);
+///         Ok(n)
+///     }
+/// }
+///
+/// # fn main() -> std::io::Result<()> {
+/// let formats = FormatRegistry::new().register(b"NOT\n", not);
+/// let data = [b"NOT\n".to_vec(), b"Hello!".iter().map(|b| !b).collect()].concat();
+///
+/// let mut s = String::new();
+/// EgzReaderBuilder::new()
+///     .formats(formats)
+///     .build(&data[..])
+///     .read_to_string(&mut s)?;
+/// assert_eq!(s, "Hello!");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct FormatRegistry {
+    formats: Vec<(Vec<u8>, Factory)>,
+}
+
+impl FormatRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> FormatRegistry {
+        FormatRegistry::default()
+    }
+
+    /// Registers a format beginning with `magic`.
+    /// `factory` is called to create a decoder for each stream of the format.
+    ///
+    /// # Panics
+    /// Panics if `magic` is empty.
+    pub fn register<F, D>(mut self, magic: &[u8], factory: F) -> FormatRegistry
+    where
+        F: Fn() -> D + Send + Sync + 'static,
+        D: Decoder + Send + Sync + 'static,
+    {
+        assert!(!magic.is_empty(), "magic must not be empty");
+        self.formats
+            .push((magic.to_vec(), Arc::new(move || Box::new(factory()))));
+        self
+    }
+
+    // Number of bytes to read for detection.
+    pub(crate) fn probe_len(&self) -> usize {
+        self.formats.iter().map(|(m, _)| m.len()).max().unwrap_or(0)
+    }
+
+    pub(crate) fn matches(&self, preread: &[u8]) -> bool {
+        self.formats
+            .iter()
+            .any(|(magic, _)| preread.starts_with(magic))
+    }
+
+    pub(crate) fn decoder(&self, preread: &[u8]) -> Option<CustomDecoder> {
+        self.formats
+            .iter()
+            .find(|(magic, _)| preread.starts_with(magic))
+            .map(|(_, factory)| CustomDecoder(factory()))
+    }
+}
+impl fmt::Debug for FormatRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.formats.iter().map(|(m, _)| m))
+            .finish()
+    }
+}
+
+// Decoder created for a stream.
+pub(crate) struct CustomDecoder(BoxedDecoder);
+
+impl CustomDecoder {
+    pub(crate) fn read(&mut self, input: &mut dyn Read, buf: &mut [u8]) -> Result<usize> {
+        self.0.read(input, buf)
+    }
+}
+impl fmt::Debug for CustomDecoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomDecoder")
+    }
+}
+
+// Detection callback given by EgzReaderBuilder::detector.
+#[derive(Clone)]
+pub(crate) struct Detector(Arc<DetectFn>);
+
+impl Detector {
+    pub(crate) fn new<F>(f: F) -> Detector
+    where
+        F: Fn(&[u8]) -> Option<Format> + Send + Sync + 'static,
+    {
+        Detector(Arc::new(f))
+    }
+
+    pub(crate) fn detect(&self, preread: &[u8]) -> Option<Format> {
+        (self.0)(preread)
+    }
+}
+impl fmt::Debug for Detector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Detector")
+    }
+}
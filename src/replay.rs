@@ -0,0 +1,151 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Sequence number of temporary files in this process.
+static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+/// A reader which copies the output of `R` to a temporary file,
+/// so it can be read again by [rewind](ReplayReader::rewind) without reading `R` again.
+///
+/// Wrapping an [EgzReader](crate::EgzReader) makes the decoded stream readable twice
+/// without decompressing it again, even if it comes from a non-seekable source like a socket.
+/// After rewinding, the cached bytes are read from the file, followed by the rest of `R`.
+///
+/// The temporary file is created in [std::env::temp_dir](std::env::temp_dir)
+/// and removed when the reader is dropped (or immediately after creation on Unix).
+///
+/// # Examples
+/// ```
+/// use std::io::prelude::*;
+/// use egzreader::{EgzReader, ReplayReader};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut r = ReplayReader::new(EgzReader::new(&b"Hello!"[..]))?;
+/// let mut first = String::new();
+/// r.read_to_string(&mut first)?;
+///
+/// r.rewind()?;
+/// let mut second = String::new();
+/// r.read_to_string(&mut second)?;
+/// assert_eq!(first, second);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ReplayReader<R: Read> {
+    inner: R,
+    file: File,
+    // Path to remove at drop, unless already removed.
+    path: Option<PathBuf>,
+
+    // Length of the cache, and the position of the file cursor.
+    written: u64,
+    pos: u64,
+}
+
+impl<R: Read> ReplayReader<R> {
+    /// Creates a reader caching the output of `inner` to a new temporary file.
+    pub fn new(inner: R) -> Result<ReplayReader<R>> {
+        let path = std::env::temp_dir().join(format!(
+            "egzreader-replay-{}-{}",
+            process::id(),
+            SEQUENCE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        // An open file can be removed on Unix, which leaves no file behind even on a crash.
+        let path = if cfg!(unix) && fs::remove_file(&path).is_ok() {
+            None
+        } else {
+            Some(path)
+        };
+
+        Ok(ReplayReader {
+            inner,
+            file,
+            path,
+            written: 0,
+            pos: 0,
+        })
+    }
+
+    /// Moves back to the beginning of the stream.
+    pub fn rewind(&mut self) -> Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.pos = 0;
+        Ok(())
+    }
+
+    /// Returns the number of bytes cached in the temporary file.
+    pub fn cached_len(&self) -> u64 {
+        self.written
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Read> Read for ReplayReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.pos < self.written {
+            let rest = self.written - self.pos;
+            let len = buf.len().min(rest.min(usize::MAX as u64) as usize);
+            let n = self.file.read(&mut buf[..len])?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+
+        // The file cursor is at the end of the cache here.
+        let n = self.inner.read(buf)?;
+        self.file.write_all(&buf[..n])?;
+        self.written += n as u64;
+        self.pos = self.written;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Drop for ReplayReader<R> {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::ReplayReader;
+
+    #[test]
+    fn replay() {
+        let data = "Hello, World!".repeat(1000);
+        let mut r = ReplayReader::new(data.as_bytes()).unwrap();
+
+        let mut buf = vec![0; 100];
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(r.cached_len(), 100);
+
+        // Rewinding in the middle reads the cache, then the rest.
+        r.rewind().unwrap();
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, data);
+
+        r.rewind().unwrap();
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, data);
+        assert_eq!(r.cached_len(), data.len() as u64);
+    }
+}
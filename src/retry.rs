@@ -0,0 +1,126 @@
+use std::io::{ErrorKind, Result};
+use std::thread;
+use std::time::Duration;
+
+/// A policy to retry reading the underlying reader on transient errors,
+/// configured by [EgzReaderBuilder::retry](crate::EgzReaderBuilder::retry).
+///
+/// A failed `read()` of the underlying reader is retried after a backoff, which is doubled
+/// on each retry up to the maximum. The decoder state is kept across retries,
+/// so the stream continues as if the error did not happen.
+/// The error is returned when it is not retryable or the retries are exhausted.
+///
+/// By default, `ConnectionReset`, `ConnectionAborted` and `TimedOut` are retried
+/// with the backoff from 100 ms up to 10 s.
+///
+/// # Examples
+/// ```
+/// use std::io::ErrorKind;
+/// use std::time::Duration;
+/// use egzreader::{EgzReaderBuilder, RetryPolicy};
+///
+/// let policy = RetryPolicy::new(5)
+///     .kinds(&[ErrorKind::ConnectionReset, ErrorKind::UnexpectedEof])
+///     .backoff(Duration::from_millis(10), Duration::from_secs(1));
+/// let builder = EgzReaderBuilder::new().retry(policy);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    kinds: Vec<ErrorKind>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy retrying up to `max_retries` times in a row.
+    pub fn new(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            kinds: vec![
+                ErrorKind::ConnectionReset,
+                ErrorKind::ConnectionAborted,
+                ErrorKind::TimedOut,
+            ],
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets the error kinds to retry.
+    pub fn kinds(mut self, kinds: &[ErrorKind]) -> RetryPolicy {
+        self.kinds = kinds.to_vec();
+        self
+    }
+
+    /// Sets the backoff of the first retry and its maximum.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> RetryPolicy {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    // Calls `read` until it succeeds or fails with an error not to be retried.
+    pub(crate) fn read<F>(&self, mut read: F) -> Result<usize>
+    where
+        F: FnMut() -> Result<usize>,
+    {
+        let mut retries = 0;
+        let mut backoff = self.initial_backoff;
+        loop {
+            match read() {
+                Err(e) if retries < self.max_retries && self.kinds.contains(&e.kind()) => {
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    retries += 1;
+                }
+                r => return r,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error, ErrorKind};
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn retry() {
+        let policy =
+            RetryPolicy::new(2).backoff(Duration::from_millis(1), Duration::from_millis(2));
+
+        let mut errors = 2;
+        let r = policy.read(|| {
+            if 0 < errors {
+                errors -= 1;
+                Err(Error::from(ErrorKind::TimedOut))
+            } else {
+                Ok(1)
+            }
+        });
+        assert_eq!(r.unwrap(), 1);
+
+        let mut errors = 3;
+        let r = policy.read(|| {
+            if 0 < errors {
+                errors -= 1;
+                Err(Error::from(ErrorKind::TimedOut))
+            } else {
+                Ok(1)
+            }
+        });
+        assert_eq!(r.unwrap_err().kind(), ErrorKind::TimedOut);
+        assert_eq!(errors, 0);
+
+        let mut calls = 0;
+        let r = policy.read(|| {
+            calls += 1;
+            Err(Error::from(ErrorKind::InvalidData))
+        });
+        assert_eq!(r.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(calls, 1);
+    }
+}
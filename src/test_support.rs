@@ -0,0 +1,170 @@
+//! Readers and fixtures to test code using `EgzReader` against edge cases.
+//!
+//! This module is available with the `test-support` feature.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "test-support")]
+//! # fn main() -> std::io::Result<()> {
+//! use std::io::prelude::*;
+//! use egzreader::EgzReader;
+//! use egzreader::test_support::{Interrupting, OneByte, HELLO_GZ};
+//!
+//! let r = Interrupting::new(OneByte::new(HELLO_GZ));
+//! let mut s = String::new();
+//! EgzReader::new(r).read_to_string(&mut s)?;
+//! assert_eq!(s, "Hello!");
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "test-support"))]
+//! # fn main() {}
+//! ```
+use std::io::{Error, ErrorKind, Read, Result};
+
+/// "Hello!" in plain text.
+pub const HELLO: &[u8] = b"Hello!";
+
+/// "Hello!" encoded by gzip.
+pub const HELLO_GZ: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0xeb, 0x47, 0x74, 0x60, 0x00, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0x57,
+    0x04, 0x00, 0x56, 0xcc, 0x2a, 0x9d, 0x06, 0x00, 0x00, 0x00,
+];
+
+/// [HELLO_GZ](HELLO_GZ) without its 8-byte trailer.
+pub const TRUNCATED_GZ: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0xeb, 0x47, 0x74, 0x60, 0x00, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0x57,
+    0x04, 0x00,
+];
+
+/// An empty content encoded by gzip.
+pub const ZERO_GZ: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x08, 0x32, 0xb1, 0x90, 0x63, 0x00, 0x03, 0x7a, 0x65, 0x72, 0x6f, 0x00, 0x03,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// A reader returning at most one byte per `read()`.
+#[derive(Debug)]
+pub struct OneByte<R>(R);
+
+impl<R: Read> OneByte<R> {
+    pub fn new(inner: R) -> OneByte<R> {
+        OneByte(inner)
+    }
+}
+impl<R: Read> Read for OneByte<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(1);
+        self.0.read(&mut buf[..n])
+    }
+}
+
+/// A reader returning at most `max` bytes per `read()`.
+#[derive(Debug)]
+pub struct ShortRead<R> {
+    inner: R,
+    max: usize,
+}
+
+impl<R: Read> ShortRead<R> {
+    /// # Panics
+    /// Panics if `max` is zero.
+    pub fn new(inner: R, max: usize) -> ShortRead<R> {
+        assert!(0 < max, "max must be positive");
+        ShortRead { inner, max }
+    }
+}
+impl<R: Read> Read for ShortRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(self.max);
+        self.inner.read(&mut buf[..n])
+    }
+}
+
+/// A reader failing with `Interrupted` before every successful `read()`.
+#[derive(Debug)]
+pub struct Interrupting<R> {
+    inner: R,
+    interrupted: bool,
+}
+
+impl<R: Read> Interrupting<R> {
+    pub fn new(inner: R) -> Interrupting<R> {
+        Interrupting {
+            inner,
+            interrupted: false,
+        }
+    }
+}
+impl<R: Read> Read for Interrupting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.interrupted = !self.interrupted;
+        if self.interrupted {
+            Err(Error::from(ErrorKind::Interrupted))
+        } else {
+            self.inner.read(buf)
+        }
+    }
+}
+
+/// A reader failing with an error of `kind` once `offset` bytes have been read.
+///
+/// Reads are shortened to stop at `offset`, and every read after that fails.
+#[derive(Debug)]
+pub struct ErrorAt<R> {
+    inner: R,
+    offset: u64,
+    kind: ErrorKind,
+    pos: u64,
+}
+
+impl<R: Read> ErrorAt<R> {
+    pub fn new(inner: R, offset: u64, kind: ErrorKind) -> ErrorAt<R> {
+        ErrorAt {
+            inner,
+            offset,
+            kind,
+            pos: 0,
+        }
+    }
+}
+impl<R: Read> Read for ErrorAt<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let rest = self.offset - self.pos;
+        if rest == 0 {
+            return Err(Error::new(self.kind, "injected error"));
+        }
+        let n = buf.len().min(rest.min(usize::MAX as u64) as usize);
+        let n = self.inner.read(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read};
+
+    use super::*;
+    use crate::EgzReader;
+
+    fn read_egz<R: Read>(r: R) -> std::io::Result<String> {
+        let mut s = String::new();
+        EgzReader::new(r).read_to_string(&mut s)?;
+        Ok(s)
+    }
+
+    #[test]
+    fn readers() {
+        for data in [HELLO, HELLO_GZ].iter() {
+            assert_eq!(read_egz(OneByte::new(*data)).unwrap(), "Hello!");
+            assert_eq!(read_egz(ShortRead::new(*data, 3)).unwrap(), "Hello!");
+            assert_eq!(read_egz(Interrupting::new(*data)).unwrap(), "Hello!");
+        }
+        assert_eq!(read_egz(ZERO_GZ).unwrap(), "");
+
+        let e = read_egz(ErrorAt::new(HELLO_GZ, 20, ErrorKind::ConnectionReset)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ConnectionReset);
+        let e = read_egz(TRUNCATED_GZ).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,215 @@
+use std::io::{Error, ErrorKind, Read, Result};
+use std::str;
+
+fn invalid_utf8() -> Error {
+    Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+}
+
+// Width of the UTF-8 sequence starting with `lead`.
+fn utf8_width(lead: u8) -> usize {
+    match lead {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    }
+}
+
+// Post-process of decoded bytes for the text mode.
+#[derive(Debug, Default)]
+pub(crate) struct TextFilter {
+    crlf: bool,
+    utf8: bool,
+
+    // A byte read from the reader but not yet returned.
+    // It is '\r' waiting for the next byte, except when the output buffer has 1 byte length.
+    held: Option<u8>,
+    // An incomplete UTF-8 sequence at the end of the last output.
+    partial: Vec<u8>,
+}
+
+impl TextFilter {
+    pub(crate) fn new(crlf: bool, utf8: bool) -> TextFilter {
+        TextFilter {
+            crlf,
+            utf8,
+            ..TextFilter::default()
+        }
+    }
+
+    pub(crate) fn read<R: Read>(&mut self, r: &mut R, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let n = if self.crlf {
+            self.read_crlf(r, buf)?
+        } else {
+            r.read(buf)?
+        };
+
+        if self.utf8 {
+            self.validate(&buf[..n])?;
+        }
+        Ok(n)
+    }
+
+    // Reads and converts CRLF into LF.
+    fn read_crlf<R: Read>(&mut self, r: &mut R, buf: &mut [u8]) -> Result<usize> {
+        debug_assert!(!buf.is_empty());
+        loop {
+            let held = match self.held.take() {
+                Some(b'\r') if buf.len() == 1 => return self.read_cr(r, buf),
+                Some(b) => {
+                    buf[0] = b;
+                    1
+                }
+                None => 0,
+            };
+
+            let n = if held < buf.len() {
+                r.read(&mut buf[held..])?
+            } else {
+                // Only a non-CR byte was held.
+                return Ok(held);
+            };
+            let eof = n == 0;
+            let len = held + n;
+
+            let mut w = 0;
+            for i in 0..len {
+                if buf[i] == b'\r' && i + 1 < len && buf[i + 1] == b'\n' {
+                    continue;
+                }
+                buf[w] = buf[i];
+                w += 1;
+            }
+
+            // A trailing CR may be followed by LF in the next read.
+            if !eof && buf[w - 1] == b'\r' {
+                self.held = Some(b'\r');
+                w -= 1;
+            }
+            if 0 < w || eof {
+                return Ok(w);
+            }
+        }
+    }
+
+    // Resolves a held CR into 1-byte buffer.
+    fn read_cr<R: Read>(&mut self, r: &mut R, buf: &mut [u8]) -> Result<usize> {
+        let mut next = [0; 1];
+        let n = r.read(&mut next)?;
+        if n == 1 && next[0] == b'\n' {
+            buf[0] = b'\n';
+        } else {
+            buf[0] = b'\r';
+            if n == 1 {
+                self.held = Some(next[0]);
+            }
+        }
+        Ok(1)
+    }
+
+    // Validates UTF-8 for each output, including sequences splitted into two outputs.
+    fn validate(&mut self, mut data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            // End of stream
+            return if self.partial.is_empty() {
+                Ok(())
+            } else {
+                Err(invalid_utf8())
+            };
+        }
+
+        if !self.partial.is_empty() {
+            let need = utf8_width(self.partial[0]) - self.partial.len();
+            let n = need.min(data.len());
+            self.partial.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if n < need {
+                return Ok(());
+            }
+            if str::from_utf8(&self.partial).is_err() {
+                return Err(invalid_utf8());
+            }
+            self.partial.clear();
+        }
+
+        match str::from_utf8(data) {
+            Ok(_) => Ok(()),
+            Err(e) if e.error_len().is_none() => {
+                self.partial.extend_from_slice(&data[e.valid_up_to()..]);
+                Ok(())
+            }
+            Err(_) => Err(invalid_utf8()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read, Result};
+
+    use super::TextFilter;
+
+    // Reader returning 1 byte for each read().
+    struct OneByte<'a>(&'a [u8]);
+    impl Read for OneByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = buf.len().min(self.0.len()).min(1);
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    fn filter<R: Read>(mut r: R, crlf: bool, utf8: bool, size: usize) -> Result<Vec<u8>> {
+        let mut f = TextFilter::new(crlf, utf8);
+        let mut out = Vec::new();
+        let mut buf = vec![0; size];
+        loop {
+            let n = f.read(&mut r, &mut buf)?;
+            if n == 0 {
+                return Ok(out);
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    #[test]
+    fn crlf() {
+        let data: &[u8] = b"a\r\nb\r\r\n\rc\n\r";
+        for size in 1..16 {
+            assert_eq!(filter(data, true, false, size).unwrap(), b"a\nb\r\n\rc\n\r");
+            assert_eq!(
+                filter(OneByte(data), true, false, size).unwrap(),
+                b"a\nb\r\n\rc\n\r"
+            );
+        }
+    }
+    #[test]
+    fn crlf_disabled() {
+        let data: &[u8] = b"a\r\nb";
+        assert_eq!(filter(data, false, false, 4).unwrap(), data);
+    }
+    #[test]
+    fn utf8() {
+        let data = "あいう\r\nえお".as_bytes();
+        for size in 1..16 {
+            assert_eq!(filter(OneByte(data), false, true, size).unwrap(), data);
+        }
+        assert_eq!(
+            filter(data, true, true, 4).unwrap(),
+            "あいう\nえお".as_bytes()
+        );
+    }
+    #[test]
+    fn invalid_utf8() {
+        let data: &[u8] = &[0x41, 0xe3, 0x81, 0x41];
+        let e = filter(OneByte(data), false, true, 4).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        // Truncated at the end of stream
+        let data: &[u8] = &[0x41, 0xe3, 0x81];
+        let e = filter(data, false, true, 4).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+}
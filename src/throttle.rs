@@ -0,0 +1,62 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Token bucket limiting bytes per second, allowing a burst of one second at most.
+#[derive(Debug, Clone)]
+pub(crate) struct Limiter {
+    rate: u64,
+    allowance: f64,
+    last: Instant,
+}
+
+impl Limiter {
+    pub(crate) fn new(rate: u64) -> Limiter {
+        debug_assert!(0 < rate);
+        Limiter {
+            rate,
+            allowance: 0.0,
+            last: Instant::now(),
+        }
+    }
+
+    // Maximum size of a read not to exceed the burst.
+    pub(crate) fn chunk_size(&self, len: usize) -> usize {
+        len.min(self.rate.min(usize::MAX as u64) as usize)
+    }
+
+    // Accounts `n` bytes, sleeping until they are allowed.
+    pub(crate) fn consume(&mut self, n: usize) {
+        let now = Instant::now();
+        let rate = self.rate as f64;
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.allowance = (self.allowance + elapsed * rate).min(rate) - n as f64;
+        self.last = now;
+
+        if self.allowance < 0.0 {
+            let wait = Duration::from_secs_f64(-self.allowance / rate);
+            thread::sleep(wait);
+            self.allowance = 0.0;
+            self.last = now + wait;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Limiter;
+
+    #[test]
+    fn limit() {
+        let mut l = Limiter::new(10_000);
+        assert_eq!(l.chunk_size(100_000), 10_000);
+        assert_eq!(l.chunk_size(100), 100);
+
+        let start = Instant::now();
+        for _ in 0..10 {
+            l.consume(100);
+        }
+        assert!(Duration::from_millis(90) <= start.elapsed());
+    }
+}
@@ -0,0 +1,223 @@
+use flate2::bufread::GzDecoder;
+use flate2::GzHeader;
+use std::io::{BufRead, BufReader, Read, Result};
+use std::mem;
+
+use crate::error::DecodeError;
+use crate::Options;
+
+/// How bytes following the last gzip member are treated,
+/// given by [EgzReaderBuilder::trailing_data](crate::EgzReaderBuilder::trailing_data).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingData {
+    /// The bytes are ignored, as gzip(1) does.
+    #[default]
+    Ignore,
+    /// Reading fails with `InvalidData` at the bytes, e.g. to catch corrupted uploads.
+    Error,
+    /// The stream ends at the bytes, which are given by
+    /// [EgzReader::into_trailing](crate::EgzReader::into_trailing).
+    ///
+    /// Without [multi_member](crate::EgzReaderBuilder::multi_member), the stream ends
+    /// after the first member, so a gzip blob followed by e.g. an index section can be
+    /// read from a framed file, even if the section begins with another member.
+    Expose,
+    /// The bytes are read as they are after the decoded bytes,
+    /// e.g. uncompressed lines appended to a gzip file by a log shipper.
+    Raw,
+    /// The bytes are decoded as another stream, whose format is detected again with
+    /// the same options, e.g. a gzip member followed by a zlib stream or plain text.
+    ///
+    /// The detection runs again at the end of each gzip stream found so, and
+    /// [EgzReader::format](crate::EgzReader::format) gives the format of the current one.
+    /// A stream of another format is read to the end of the underlying reader, and
+    /// formats without a decoder in this crate, e.g. zstd, are read as they are.
+    Detect,
+}
+
+#[derive(Debug)]
+enum State<R: Read> {
+    Member(Box<GzDecoder<BufReader<R>>>),
+    // After a member
+    Between(BufReader<R>),
+    // At the trailing data or the end of stream
+    Done(BufReader<R>),
+    Moving,
+}
+
+// Decoder of gzip members which stops at the trailing data,
+// keeping the bytes after the last member.
+#[derive(Debug)]
+pub(crate) struct GzStream<R: Read> {
+    state: State<R>,
+    multi_member: bool,
+    policy: TrailingData,
+    // Header of the last member read to the end
+    header: Option<GzHeader>,
+    // Options to detect the format of the trailing data, with TrailingData::Detect
+    detect: Option<Box<Options>>,
+}
+
+impl<R: Read> GzStream<R> {
+    pub(crate) fn new(r: R, multi_member: bool, policy: TrailingData) -> GzStream<R> {
+        GzStream {
+            state: State::Member(Box::new(GzDecoder::new(BufReader::new(r)))),
+            multi_member,
+            policy,
+            header: None,
+            detect: None,
+        }
+    }
+
+    // Detects the format of the trailing data with `opts`, for TrailingData::Detect.
+    pub(crate) fn detect_with(mut self, opts: &Options) -> GzStream<R> {
+        self.detect = Some(Box::new(opts.clone()));
+        self
+    }
+
+    // Whether the stream has been read to the end and the trailing data is to be detected.
+    pub(crate) fn detects_next(&self) -> bool {
+        self.detect.is_some() && matches!(self.state, State::Done(_))
+    }
+
+    // The bytes after the last member and the options to detect their format,
+    // once the stream has been read to the end with TrailingData::Detect.
+    pub(crate) fn into_next(self) -> Option<(BufReader<R>, Options)> {
+        match (self.state, self.detect) {
+            (State::Done(r), Some(opts)) => Some((r, *opts)),
+            _ => None,
+        }
+    }
+
+    // Header of the current member, or of the last member after it.
+    pub(crate) fn header(&self) -> Option<&GzHeader> {
+        match &self.state {
+            State::Member(d) => d.header(),
+            _ => self.header.as_ref(),
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        match &self.state {
+            State::Member(d) => d.get_ref().get_ref(),
+            State::Between(r) | State::Done(r) => r.get_ref(),
+            State::Moving => unreachable!(),
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        match &mut self.state {
+            State::Member(d) => d.get_mut().get_mut(),
+            State::Between(r) | State::Done(r) => r.get_mut(),
+            State::Moving => unreachable!(),
+        }
+    }
+
+    // Note that the buffered bytes are lost.
+    pub(crate) fn into_inner(self) -> R {
+        match self.state {
+            State::Member(d) => d.into_inner().into_inner(),
+            State::Between(r) | State::Done(r) => r.into_inner(),
+            State::Moving => unreachable!(),
+        }
+    }
+
+    // The bytes after the last member, once the stream has been read to the end.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn into_trailing(self) -> std::result::Result<BufReader<R>, GzStream<R>> {
+        match self.state {
+            State::Done(r) => Ok(r),
+            state => Err(GzStream { state, ..self }),
+        }
+    }
+}
+
+// Whether `buf` may begin another member.
+pub(crate) fn is_member(buf: &[u8]) -> bool {
+    buf.starts_with(&[0x1f, 0x8b]) || buf == [0x1f]
+}
+
+impl<R: Read> Read for GzStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match &mut self.state {
+                State::Member(d) => {
+                    let n = d.read(buf)?;
+                    if n != 0 || buf.is_empty() {
+                        return Ok(n);
+                    }
+                }
+                State::Between(r) => {
+                    let rest = r.fill_buf()?;
+                    if !rest.is_empty() {
+                        if self.multi_member && is_member(rest) {
+                            if let State::Between(r) = mem::replace(&mut self.state, State::Moving)
+                            {
+                                self.state = State::Member(Box::new(GzDecoder::new(r)));
+                            }
+                            continue;
+                        }
+                        if self.policy == TrailingData::Error {
+                            return Err(DecodeError::corrupt("trailing data after gzip stream"));
+                        }
+                    }
+                }
+                State::Done(r) if self.policy == TrailingData::Raw => return r.read(buf),
+                State::Done(_) => return Ok(0),
+                State::Moving => unreachable!(),
+            }
+
+            self.state = match mem::replace(&mut self.state, State::Moving) {
+                State::Member(d) => {
+                    self.header = d.header().cloned();
+                    State::Between(d.into_inner())
+                }
+                State::Between(r) => State::Done(r),
+                _ => unreachable!(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read};
+
+    use super::{GzStream, TrailingData};
+    use crate::test_support::HELLO_GZ;
+
+    #[test]
+    fn trailing_data() {
+        let data = [HELLO_GZ, HELLO_GZ, b"tail"].concat();
+
+        let mut s = String::new();
+        GzStream::new(&data[..], true, TrailingData::Ignore)
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!Hello!");
+
+        let mut r = GzStream::new(&data[..], false, TrailingData::Expose);
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello!");
+        let mut rest = Vec::new();
+        r.into_trailing().unwrap().read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [HELLO_GZ, b"tail"].concat());
+
+        let e = GzStream::new(&data[..], true, TrailingData::Error)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        let mut s = String::new();
+        GzStream::new(&data[..], true, TrailingData::Raw)
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!Hello!tail");
+
+        let mut s = String::new();
+        GzStream::new(HELLO_GZ, false, TrailingData::Error)
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "Hello!");
+    }
+}
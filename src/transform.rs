@@ -0,0 +1,180 @@
+use std::fmt;
+use std::io::{Read, Result};
+use std::mem;
+use std::sync::Arc;
+
+/// A transform applied to the decoded output, configured by
+/// [EgzReaderBuilder::post_transform](crate::EgzReaderBuilder::post_transform).
+///
+/// Closures of `FnMut(&[u8], &mut Vec<u8>) -> io::Result<()>` implement `Transform`.
+pub trait Transform {
+    /// Transforms `input`, a chunk of the decoded stream, and appends the result to `out`.
+    fn transform(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<()>;
+
+    /// Appends remaining bytes to `out` at the end of stream.
+    fn finish(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        let _ = out;
+        Ok(())
+    }
+}
+
+impl<F> Transform for F
+where
+    F: FnMut(&[u8], &mut Vec<u8>) -> Result<()>,
+{
+    fn transform(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        self(input, out)
+    }
+}
+
+type Stage = Box<dyn Transform + Send + Sync>;
+
+// Factories creating the stages for each reader.
+#[derive(Clone, Default)]
+pub(crate) struct Stages(Vec<Arc<dyn Fn() -> Stage + Send + Sync>>);
+
+impl Stages {
+    pub(crate) fn push<F, T>(&mut self, factory: F)
+    where
+        F: Fn() -> T + Send + Sync + 'static,
+        T: Transform + Send + Sync + 'static,
+    {
+        self.0.push(Arc::new(move || Box::new(factory())));
+    }
+
+    pub(crate) fn pipeline(&self) -> Option<Pipeline> {
+        if self.0.is_empty() {
+            None
+        } else {
+            Some(Pipeline {
+                stages: self.0.iter().map(|f| f()).collect(),
+                buf: Vec::new(),
+                pos: 0,
+                eof: false,
+            })
+        }
+    }
+}
+impl fmt::Debug for Stages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Stages({})", self.0.len())
+    }
+}
+
+// Transforms stacked on the decoded output.
+pub(crate) struct Pipeline {
+    stages: Vec<Stage>,
+
+    // Transformed bytes not yet returned.
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl Pipeline {
+    pub(crate) fn read<F>(&mut self, mut read: F, buf: &mut [u8]) -> Result<usize>
+    where
+        F: FnMut(&mut [u8]) -> Result<usize>,
+    {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut chunk = [0; 8 * 1024];
+        let mut next = Vec::new();
+        while self.pos == self.buf.len() {
+            if self.eof {
+                return Ok(0);
+            }
+
+            let n = read(&mut chunk)?;
+            self.eof = n == 0;
+            self.buf.clear();
+            self.buf.extend_from_slice(&chunk[..n]);
+            self.pos = 0;
+
+            for stage in self.stages.iter_mut() {
+                next.clear();
+                stage.transform(&self.buf, &mut next)?;
+                if self.eof {
+                    stage.finish(&mut next)?;
+                }
+                mem::swap(&mut self.buf, &mut next);
+            }
+        }
+
+        let n = (&self.buf[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("stages", &self.stages.len())
+            .field("pos", &self.pos)
+            .field("eof", &self.eof)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Result};
+
+    use super::{Stages, Transform};
+
+    // Appends the number of bytes at the end.
+    #[derive(Default)]
+    struct Count(usize);
+    impl Transform for Count {
+        fn transform(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<()> {
+            self.0 += input.len();
+            out.extend_from_slice(input);
+            Ok(())
+        }
+        fn finish(&mut self, out: &mut Vec<u8>) -> Result<()> {
+            out.extend_from_slice(format!("({})", self.0).as_bytes());
+            Ok(())
+        }
+    }
+
+    fn upper(input: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        out.extend(input.to_ascii_uppercase());
+        Ok(())
+    }
+
+    fn run(stages: &Stages, mut data: &[u8], size: usize) -> Vec<u8> {
+        let mut p = stages.pipeline().unwrap();
+        let mut out = Vec::new();
+        let mut buf = vec![0; size];
+        loop {
+            let n = p.read(|b| data.read(b), &mut buf).unwrap();
+            if n == 0 {
+                return out;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    #[test]
+    fn stacked() {
+        let mut stages = Stages::default();
+        assert!(stages.pipeline().is_none());
+
+        stages.push(|| upper);
+        stages.push(Count::default);
+        for size in 1..8 {
+            assert_eq!(run(&stages, b"Hello!", size), b"HELLO!(6)");
+        }
+        assert_eq!(run(&stages, b"", 4), b"(0)");
+    }
+    #[test]
+    fn stacked_order() {
+        let mut stages = Stages::default();
+        stages.push(Count::default);
+        stages.push(|| upper);
+        stages.push(Count::default);
+        assert_eq!(run(&stages, b"ab", 4), b"AB(2)(5)");
+    }
+}
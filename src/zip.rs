@@ -0,0 +1,446 @@
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Result};
+use std::sync::{Arc, Mutex};
+
+use crate::error::DecodeError;
+use crate::Prefixed;
+
+const LOCAL_HEADER: [u8; 4] = *b"PK\x03\x04";
+const DATA_DESCRIPTOR: [u8; 4] = *b"PK\x07\x08";
+const CENTRAL_HEADER: [u8; 4] = *b"PK\x01\x02";
+const ZIP64_EXTRA: u16 = 0x0001;
+
+const FLAG_ENCRYPTED: u16 = 0x0001;
+const FLAG_DESCRIPTOR: u16 = 0x0008;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+// Returns true if `preread` begins with a local file header of ZIP.
+pub(crate) fn is_zip(preread: &[u8]) -> bool {
+    4 <= preread.len() && preread[..4] == LOCAL_HEADER
+}
+
+fn invalid(msg: &str) -> Error {
+    DecodeError::corrupt(msg)
+}
+
+#[derive(Debug)]
+enum Data {
+    // Number of bytes left
+    Stored(u64),
+    Deflated(Decompress),
+}
+
+#[derive(Debug)]
+struct Entry {
+    flags: u16,
+    zip64: bool,
+    crc: u32,
+    size: u64,
+    data: Data,
+
+    actual: Crc,
+    written: u64,
+    done: bool,
+}
+
+// Reader of the entries of a ZIP stream, from the local file headers in order.
+//
+// The central directory at the end is not used, so the stream need not be seekable.
+// Entries must be stored or deflated; sizes given only in the data descriptor
+// are supported for deflated entries.
+#[derive(Debug)]
+pub(crate) struct ZipReader<R: Read> {
+    input: BufReader<R>,
+    entry: Option<Entry>,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> ZipReader<R> {
+    pub(crate) fn new(r: R) -> ZipReader<R> {
+        ZipReader {
+            input: BufReader::new(r),
+            entry: None,
+            started: false,
+            finished: false,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        self.input.get_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        self.input.get_mut()
+    }
+
+    // Note that the buffered bytes are lost.
+    pub(crate) fn into_inner(self) -> R {
+        self.input.into_inner()
+    }
+
+    // Skips the rest of the current entry, and moves to the next entry.
+    //
+    // Returns the name of the entry, or None after the last entry.
+    pub(crate) fn next_entry(&mut self) -> Result<Option<Vec<u8>>> {
+        self.started = true;
+        if self.entry.as_ref().map_or(false, |e| !e.done) {
+            io::copy(self, &mut io::sink())?;
+        }
+        self.entry = None;
+        if self.finished {
+            return Ok(None);
+        }
+
+        // The central directory or the end of stream follows the last entry.
+        if self.input.fill_buf()?.is_empty() {
+            self.finished = true;
+            return Ok(None);
+        }
+        let mut sig = [0; 4];
+        self.input.read_exact(&mut sig)?;
+        match sig {
+            LOCAL_HEADER => {}
+            CENTRAL_HEADER => {
+                self.finished = true;
+                return Ok(None);
+            }
+            _ => return Err(invalid("invalid signature after a zip entry")),
+        }
+
+        let mut h = [0; 26];
+        self.input.read_exact(&mut h)?;
+        let u16_at = |b: &[u8], i: usize| u16::from_le_bytes([b[i], b[i + 1]]);
+        let u32_at = |b: &[u8], i: usize| u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+        let (flags, method) = (u16_at(&h, 2), u16_at(&h, 4));
+        let crc = u32_at(&h, 10);
+        let mut csize = u64::from(u32_at(&h, 14));
+        let mut size = u64::from(u32_at(&h, 18));
+        let mut name = vec![0; u16_at(&h, 22) as usize];
+        self.input.read_exact(&mut name)?;
+        let mut extra = vec![0; u16_at(&h, 24) as usize];
+        self.input.read_exact(&mut extra)?;
+
+        // 64-bit sizes replacing the fields of 0xFFFFFFFF
+        let mut zip64 = false;
+        let mut rest = &extra[..];
+        while 4 <= rest.len() {
+            let (id, len) = (u16_at(rest, 0), u16_at(rest, 2) as usize);
+            let field = &rest[4..rest.len().min(4 + len)];
+            if id == ZIP64_EXTRA {
+                zip64 = true;
+                let mut values = field
+                    .chunks_exact(8)
+                    .map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]));
+                if size == 0xffff_ffff {
+                    size = values
+                        .next()
+                        .ok_or_else(|| invalid("corrupt zip64 field"))?;
+                }
+                if csize == 0xffff_ffff {
+                    csize = values
+                        .next()
+                        .ok_or_else(|| invalid("corrupt zip64 field"))?;
+                }
+            }
+            rest = &rest[rest.len().min(4 + len)..];
+        }
+
+        if flags & FLAG_ENCRYPTED != 0 {
+            return Err(invalid("encrypted zip entries are not supported"));
+        }
+        let data = match method {
+            STORED if flags & FLAG_DESCRIPTOR == 0 => Data::Stored(csize),
+            DEFLATED => Data::Deflated(Decompress::new(false)),
+            _ => return Err(invalid("unsupported zip compression method")),
+        };
+        self.entry = Some(Entry {
+            flags,
+            zip64,
+            crc,
+            size,
+            data,
+            actual: Crc::new(),
+            written: 0,
+            done: false,
+        });
+        Ok(Some(name))
+    }
+
+    // Reads the data descriptor if any, and verifies the entry.
+    fn finish(input: &mut BufReader<R>, entry: &mut Entry) -> Result<()> {
+        if entry.flags & FLAG_DESCRIPTOR != 0 {
+            // The signature is optional.
+            let mut b = [0; 4];
+            input.read_exact(&mut b)?;
+            if b == DATA_DESCRIPTOR {
+                input.read_exact(&mut b)?;
+            }
+            entry.crc = u32::from_le_bytes(b);
+            let mut sizes = [0; 16];
+            let sizes = if entry.zip64 {
+                &mut sizes[..]
+            } else {
+                &mut sizes[..8]
+            };
+            input.read_exact(sizes)?;
+            let at = if entry.zip64 { 8 } else { 4 };
+            let mut size = [0; 8];
+            size[..at].copy_from_slice(&sizes[at..2 * at]);
+            entry.size = u64::from_le_bytes(size);
+        }
+        entry.done = true;
+        if entry.crc != entry.actual.sum() || entry.size != entry.written {
+            return Err(DecodeError::checksum("zip entry checksum mismatch"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ZipReader<R> {
+    // Reads the current entry, or the first entry if no entry is started.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.started {
+            self.next_entry()?;
+        }
+        let ZipReader { input, entry, .. } = self;
+        let entry = match entry {
+            Some(e) if !e.done => e,
+            _ => return Ok(0),
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let (n, end) = match &mut entry.data {
+            Data::Stored(rest) => {
+                let len = (*rest).min(buf.len() as u64) as usize;
+                let n = input.read(&mut buf[..len])?;
+                if n == 0 && len != 0 {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+                *rest -= n as u64;
+                (n, *rest == 0)
+            }
+            Data::Deflated(d) => loop {
+                let data = input.fill_buf()?;
+                let (total_in, total_out) = (d.total_in(), d.total_out());
+                let status = d
+                    .decompress(data, buf, FlushDecompress::None)
+                    .map_err(|_| invalid("corrupt deflate stream"))?;
+                let eof = data.is_empty();
+                input.consume((d.total_in() - total_in) as usize);
+                let n = (d.total_out() - total_out) as usize;
+                if status == Status::StreamEnd {
+                    break (n, true);
+                } else if 0 < n {
+                    break (n, false);
+                } else if eof {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+            },
+        };
+        entry.actual.update(&buf[..n]);
+        entry.written += n as u64;
+        if end {
+            Self::finish(input, entry)?;
+        }
+        Ok(n)
+    }
+}
+
+#[derive(Debug)]
+struct Shared<R: Read> {
+    reader: ZipReader<Prefixed<R>>,
+    // Index of the current entry, starting from 1.
+    index: usize,
+}
+
+/// An iterator over the entries of a ZIP archive, created by
+/// [EgzReader::zip_entries](crate::EgzReader::zip_entries).
+///
+/// Each item is a [ZipEntry](ZipEntry) reading the decompressed bytes of one entry.
+/// Advancing the iterator skips the rest of the previous entry,
+/// after which the previous `ZipEntry` returns EOF.
+#[derive(Debug)]
+pub struct ZipEntries<R: Read>(Arc<Mutex<Shared<R>>>);
+
+impl<R: Read> ZipEntries<R> {
+    pub(crate) fn new(r: Prefixed<R>) -> ZipEntries<R> {
+        ZipEntries(Arc::new(Mutex::new(Shared {
+            reader: ZipReader::new(r),
+            index: 0,
+        })))
+    }
+}
+
+impl<R: Read> Iterator for ZipEntries<R> {
+    type Item = Result<ZipEntry<R>>;
+
+    fn next(&mut self) -> Option<Result<ZipEntry<R>>> {
+        let mut shared = self.0.lock().unwrap();
+        match shared.reader.next_entry() {
+            Ok(Some(name)) => {
+                shared.index += 1;
+                Some(Ok(ZipEntry {
+                    shared: self.0.clone(),
+                    index: shared.index,
+                    name: String::from_utf8_lossy(&name).into_owned(),
+                }))
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// A reader of the decompressed bytes of one ZIP entry, given by [ZipEntries](ZipEntries).
+///
+/// The CRC-32 of the entry is verified at the end of the entry.
+#[derive(Debug)]
+pub struct ZipEntry<R: Read> {
+    shared: Arc<Mutex<Shared<R>>>,
+    index: usize,
+    name: String,
+}
+
+impl<R: Read> ZipEntry<R> {
+    /// Name of the entry in the archive. Bytes invalid as UTF-8 are replaced.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<R: Read> Read for ZipEntry<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.index != self.index {
+            return Ok(0);
+        }
+        shared.reader.read(buf)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use flate2::write::DeflateEncoder;
+    use flate2::{Compression, Crc};
+    use std::io::{ErrorKind, Read, Write};
+
+    use super::{is_zip, ZipReader, DEFLATED, STORED};
+
+    // Builds a ZIP archive of `(name, data, method, with data descriptor)`.
+    pub(crate) fn zip(entries: &[(&str, &[u8], u16, bool)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+        for &(name, data, method, descriptor) in entries.iter() {
+            let body = if method == DEFLATED {
+                let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            } else {
+                data.to_vec()
+            };
+            let mut crc = Crc::new();
+            crc.update(data);
+            let flags: u16 = if descriptor { 0x0008 } else { 0 };
+            let sizes = [crc.sum(), body.len() as u32, data.len() as u32];
+
+            let offset = out.len() as u32;
+            let mut fields = Vec::new();
+            fields.extend_from_slice(&20u16.to_le_bytes());
+            fields.extend_from_slice(&flags.to_le_bytes());
+            fields.extend_from_slice(&method.to_le_bytes());
+            fields.extend_from_slice(&[0; 4]);
+            let mut local = fields.clone();
+            for s in sizes.iter() {
+                let s = if descriptor { 0 } else { *s };
+                local.extend_from_slice(&s.to_le_bytes());
+            }
+            local.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            local.extend_from_slice(&[0; 2]);
+
+            out.extend_from_slice(b"PK\x03\x04");
+            out.extend_from_slice(&local);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&body);
+            if descriptor {
+                out.extend_from_slice(b"PK\x07\x08");
+                for s in sizes.iter() {
+                    out.extend_from_slice(&s.to_le_bytes());
+                }
+            }
+
+            central.extend_from_slice(b"PK\x01\x02");
+            central.extend_from_slice(&20u16.to_le_bytes());
+            central.extend_from_slice(&fields);
+            for s in sizes.iter() {
+                central.extend_from_slice(&s.to_le_bytes());
+            }
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+
+        let (offset, len) = (out.len() as u32, central.len() as u32);
+        out.extend_from_slice(&central);
+        out.extend_from_slice(b"PK\x05\x06");
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&[0; 2]);
+        out
+    }
+
+    #[test]
+    fn zip_first_entry() {
+        let text = "Hello, World!".repeat(1000);
+        for &(method, descriptor) in [(STORED, false), (DEFLATED, false), (DEFLATED, true)].iter() {
+            let data = zip(&[
+                ("a.txt", text.as_bytes(), method, descriptor),
+                ("b.txt", b"b", method, false),
+            ]);
+            assert!(is_zip(&data));
+            let mut s = String::new();
+            ZipReader::new(&data[..]).read_to_string(&mut s).unwrap();
+            assert_eq!(s, text);
+        }
+
+        let data = zip(&[
+            ("a.txt", b"a", STORED, false),
+            ("b.txt", b"", DEFLATED, true),
+        ]);
+        let mut r = ZipReader::new(&data[..]);
+        assert_eq!(r.next_entry().unwrap().unwrap(), b"a.txt");
+        assert_eq!(r.next_entry().unwrap().unwrap(), b"b.txt");
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "");
+        assert!(r.next_entry().unwrap().is_none());
+    }
+    #[test]
+    fn zip_corrupt() {
+        let mut data = zip(&[("a.txt", b"Hello", STORED, false)]);
+        data[30 + 5] = b'J';
+        let e = ZipReader::new(&data[..])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        // Only the central directory or the end of stream can follow an entry.
+        let data = zip(&[("a.txt", b"Hello", STORED, false)]);
+        let entry = &data[..30 + 5 + 5];
+        let mut r = ZipReader::new(entry);
+        assert_eq!(r.next_entry().unwrap().unwrap(), b"a.txt");
+        assert!(r.next_entry().unwrap().is_none());
+        let data = [entry, b"junk"].concat();
+        let mut r = ZipReader::new(&data[..]);
+        assert_eq!(r.next_entry().unwrap().unwrap(), b"a.txt");
+        let e = r.next_entry().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+}